@@ -1,3 +1,73 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+include!("secret_keys.rs");
+
 fn main() {
+    embed_production_env();
     tauri_build::build()
 }
+
+// Copies `.env.production` to `OUT_DIR` without the secret keys; release builds embed
+// the copy instead of the original file.
+fn embed_production_env() {
+    let source = Path::new("../.env.production");
+    println!("cargo:rerun-if-changed={}", source.display());
+    println!("cargo:rerun-if-changed=secret_keys.rs");
+    let contents = fs::read_to_string(source).unwrap_or_default();
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    fs::write(
+        Path::new(&out_dir).join("env.production"),
+        strip_secrets(&contents),
+    )
+    .expect("failed to write the embedded env file");
+}
+
+fn strip_secrets(contents: &str) -> String {
+    let mut kept = String::new();
+    let mut open_quote: Option<char> = None; // Inside a multi-line value being dropped
+    for line in contents.lines() {
+        if let Some(quote) = open_quote {
+            if closes_quote(line, quote) {
+                open_quote = None;
+            }
+            continue;
+        }
+        let assignment = line.trim_start();
+        let assignment = assignment.strip_prefix("export ").unwrap_or(assignment);
+        let secret_value = assignment
+            .split_once('=')
+            .filter(|(key, _)| SECRET_KEYS.contains(&key.trim()))
+            .map(|(_, value)| value.trim_start());
+        match secret_value {
+            // A quoted value without its closing quote continues on the next lines
+            Some(value) => {
+                open_quote = value
+                    .chars()
+                    .next()
+                    .filter(|&quote| quote == '"' || quote == '\'')
+                    .filter(|&quote| !closes_quote(&value[1..], quote));
+            }
+            None => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+    kept
+}
+
+// Single quotes are literal; inside double quotes a backslash escapes the next character
+fn closes_quote(text: &str, quote: char) -> bool {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == quote {
+            return true;
+        }
+        if c == '\\' && quote == '"' {
+            chars.next();
+        }
+    }
+    false
+}
@@ -0,0 +1,9 @@
+// Keys marked `secret: true` in `config::CONFIG_SCHEMA`. They come from the secret
+// store or the environment and must never be compiled into a release binary.
+// Included by build.rs, and by a config test that keeps the list in sync with the schema.
+const SECRET_KEYS: [&str; 4] = [
+    "GITHUB_CLIENT_SECRET",
+    "WORKER_API_KEY",
+    "OPENAI_API_KEY",
+    "ANTHROPIC_API_KEY",
+];
@@ -0,0 +1,371 @@
+// src-tauri/src/anthropic.rs
+
+// --- Dependencies ---
+use crate::config::AppConfig;
+use crate::conversation::ChatRole;
+use crate::provider::{
+    parse_error, split_data_url, Completion, ModelInfo, Provider, ProviderEvent, ProviderQuery,
+    Usage,
+};
+use crate::query_error::QueryError;
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// --- Anthropic Messages API ---
+// `POST /v1/messages`. Screenshots are sent as base64 `image` blocks ahead of the
+// question, system turns of the history go into the top-level `system` prompt, and the
+// stream is a sequence of typed SSE events.
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+// Required by the API; answers to screenshot questions stay well below it
+const MAX_TOKENS: u32 = 4096;
+
+#[derive(Debug, Clone)]
+pub struct AnthropicProvider {
+    pub base_url: String,
+    pub api_key: String,
+    pub model: Option<String>,
+}
+
+impl AnthropicProvider {
+    // `None` unless the API key is configured
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        Some(AnthropicProvider {
+            base_url: config
+                .anthropic_base_url
+                .as_ref()
+                .map_or(DEFAULT_BASE_URL.to_string(), |url| url.to_string()),
+            api_key: config.anthropic_api_key.clone()?,
+            model: config.anthropic_model.clone(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct AnthropicUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+}
+
+impl From<AnthropicUsage> for Usage {
+    fn from(usage: AnthropicUsage) -> Self {
+        Usage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Vec<ContentBlock>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Other, // e.g. `thinking` or `tool_use`; not part of the answer
+}
+
+// The events of a streamed message. Input tokens arrive with `message_start`, output
+// tokens with `message_delta`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockDelta {
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        usage: Option<AnthropicUsage>,
+    },
+    MessageStop,
+    Error {
+        error: ApiError,
+    },
+    #[serde(other)]
+    Other, // `ping`, `content_block_start`, `content_block_stop`
+}
+
+#[derive(Deserialize)]
+struct MessageStart {
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct BlockDelta {
+    text: Option<String>, // Only `text_delta` carries answer text
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+    display_name: Option<String>,
+}
+
+impl Provider for AnthropicProvider {
+    fn display_name(&self) -> String {
+        "Anthropic".to_string()
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    fn query_request(&self, client: &reqwest::Client) -> RequestBuilder {
+        self.authorize(client.post(self.url("v1/messages")))
+    }
+
+    fn request_body(&self, query: &ProviderQuery) -> Value {
+        let (system, turns): (Vec<_>, Vec<_>) = query
+            .history
+            .iter()
+            .partition(|turn| turn.role == ChatRole::System);
+        let mut messages: Vec<Value> = turns
+            .iter()
+            .map(|turn| json!({ "role": turn.role.as_str(), "content": turn.text }))
+            .collect();
+
+        let mut content: Vec<Value> = query
+            .image_data_urls
+            .iter()
+            .filter_map(|url| split_data_url(url))
+            .map(|(media_type, data)| {
+                json!({
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": media_type, "data": data },
+                })
+            })
+            .collect();
+        content.push(json!({ "type": "text", "text": query.text }));
+        messages.push(json!({ "role": ChatRole::User.as_str(), "content": content }));
+
+        let mut body = json!({
+            "model": query.model,
+            "max_tokens": MAX_TOKENS,
+            "messages": messages,
+        });
+        if !system.is_empty() {
+            let prompt: Vec<&str> = system.iter().map(|turn| turn.text.as_str()).collect();
+            body["system"] = json!(prompt.join("\n\n"));
+        }
+        if query.stream {
+            body["stream"] = json!(true);
+        }
+        body
+    }
+
+    fn parse_completion(&self, body: Value) -> Result<Completion, QueryError> {
+        let message: Message =
+            serde_json::from_value(body).map_err(|e| parse_error(e.to_string()))?;
+        let text: String = message
+            .content
+            .into_iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text),
+                ContentBlock::Other => None,
+            })
+            .collect();
+        if text.is_empty() {
+            return Err(parse_error("message has no text content"));
+        }
+        Ok(Completion {
+            text,
+            usage: message.usage.map(Usage::from),
+        })
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Vec<ProviderEvent> {
+        let event = match serde_json::from_str::<StreamEvent>(data.trim()) {
+            Ok(event) => event,
+            Err(e) => {
+                return vec![ProviderEvent::Error(format!(
+                    "unexpected stream data: {}",
+                    e
+                ))]
+            }
+        };
+        match event {
+            StreamEvent::MessageStart { message } => message
+                .usage
+                .map(|usage| ProviderEvent::Usage(usage.into()))
+                .into_iter()
+                .collect(),
+            StreamEvent::ContentBlockDelta { delta } => {
+                delta.text.map(ProviderEvent::Delta).into_iter().collect()
+            }
+            StreamEvent::MessageDelta { usage } => {
+                // Only the output count is final here; the input count came earlier
+                let usage = usage.unwrap_or_default();
+                vec![ProviderEvent::Usage(Usage {
+                    input_tokens: None,
+                    output_tokens: usage.output_tokens,
+                })]
+            }
+            StreamEvent::MessageStop => vec![ProviderEvent::Done],
+            StreamEvent::Error { error } => vec![ProviderEvent::Error(error.message)],
+            StreamEvent::Other => Vec::new(),
+        }
+    }
+
+    fn models_request(&self, client: &reqwest::Client) -> RequestBuilder {
+        self.authorize(client.get(self.url("v1/models")))
+    }
+
+    fn parse_models(&self, body: Value) -> Result<Vec<ModelInfo>, QueryError> {
+        let list: ModelList =
+            serde_json::from_value(body).map_err(|e| parse_error(e.to_string()))?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|model| ModelInfo {
+                id: model.id,
+                name: model.display_name,
+            })
+            .collect())
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ChatTurn;
+
+    fn provider() -> AnthropicProvider {
+        AnthropicProvider {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_key: "sk-ant-test".to_string(),
+            model: None,
+        }
+    }
+
+    #[test]
+    fn test_request_body_formats_images_and_system_turns() {
+        let history = [
+            ChatTurn {
+                role: ChatRole::System,
+                text: "Be brief.".to_string(),
+                image_ref: None,
+            },
+            ChatTurn {
+                role: ChatRole::User,
+                text: "Earlier question".to_string(),
+                image_ref: None,
+            },
+        ];
+        let images = ["data:image/webp;base64,UklG".to_string()];
+        let body = provider().request_body(&ProviderQuery {
+            model: "claude-test",
+            text: "What is this?",
+            image_data_urls: &images,
+            history: &history,
+            stream: true,
+        });
+        assert_eq!(
+            body,
+            json!({
+                "model": "claude-test",
+                "max_tokens": MAX_TOKENS,
+                "system": "Be brief.",
+                "messages": [
+                    { "role": "user", "content": "Earlier question" },
+                    { "role": "user", "content": [
+                        { "type": "image", "source": { "type": "base64", "media_type": "image/webp", "data": "UklG" } },
+                        { "type": "text", "text": "What is this?" },
+                    ]},
+                ],
+                "stream": true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_requests_carry_key_and_version() {
+        let client = reqwest::Client::new();
+        for request in [
+            provider().query_request(&client),
+            provider().models_request(&client),
+        ] {
+            let request = request.build().unwrap();
+            assert_eq!(request.headers()["x-api-key"], "sk-ant-test");
+            assert_eq!(request.headers()["anthropic-version"], API_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_event_variants() {
+        let provider = provider();
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":25,"output_tokens":1}}}"#
+            ),
+            [ProviderEvent::Usage(Usage {
+                input_tokens: Some(25),
+                output_tokens: Some(1)
+            })]
+        );
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#
+            ),
+            [ProviderEvent::Delta("Hel".to_string())]
+        );
+        // Only text deltas are part of the answer
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{"}}"#
+            ),
+            []
+        );
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#
+            ),
+            [ProviderEvent::Usage(Usage {
+                input_tokens: None,
+                output_tokens: Some(2)
+            })]
+        );
+        assert_eq!(provider.parse_stream_event(r#"{"type":"ping"}"#), []);
+        assert_eq!(
+            provider.parse_stream_event(r#"{"type":"message_stop"}"#),
+            [ProviderEvent::Done]
+        );
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+            ),
+            [ProviderEvent::Error("Overloaded".to_string())]
+        );
+    }
+}
@@ -1,17 +1,27 @@
 // src-tauri/src/auth.rs
 
 // --- 依赖 ---
-use once_cell::sync::Lazy; // 用于惰性静态初始化
+use crate::config::{self, TokenExchange}; // 分层、经过校验的运行时配置
+use crate::http::{send_with_retry, HttpClient, RetryAttempt, RetryPolicy, HTTP_RETRY_EVENT}; // 共享的 HTTP 客户端与重试
+use crate::identity::{
+    self, AuthorizeParams, IdentityProvider, TokenSet, UserProfile, GITHUB_PROVIDER_ID,
+}; // 可插拔的身份提供方
+use crate::session::{emit_session_changed, SessionState}; // 登录状态的持久化与恢复
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _}; // PKCE challenge 编码
+use once_cell::sync::Lazy;
 use rand::distr::Alphanumeric; // `distr` 才是正确的
 use rand::{thread_rng, Rng};
-use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE}; // User-Agent 由共享客户端统一设置
+use ring::digest::{digest, SHA256};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex as StdMutex}; // 对 PendingAuthState 使用 StdMutex
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, PoisonError}; // 对 PendingAuthState 使用 StdMutex
+use tauri::ipc::CapabilityBuilder; // 运行时追加 opener 作用域
 use tauri::{AppHandle, Emitter, Manager, Runtime, State}; // 确保 Manager 已导入
 use thiserror::Error;
 use tokio::sync::{oneshot, Mutex as TokioMutex}; // TokioMutex 用于异步服务器状态
-use urlencoding; // 用于 URL 编码参数
+use url::Url;
 
 // --- 开发服务器的条件导入 ---
 #[cfg(debug_assertions)]
@@ -25,233 +35,242 @@ use axum::{
 #[cfg(debug_assertions)]
 use std::net::SocketAddr;
 
-// --- 配置结构体 ---
-#[derive(Clone, Debug, PartialEq)] // 为方便测试添加 PartialEq
-struct EnvConfig {
-    github_client_id: String,
-    github_client_secret: String,
-    worker_api_url: String,
-    worker_api_key: String,
+// --- 运行时配置的访问器函数 ---
+// 配置由 config 模块分层解析 (环境变量 > 配置文件 > 嵌入式回退 > 默认值) 并按 schema 校验，
+// 可在运行时重新加载。缺失或无效的值返回空字符串，由调用方报告错误，而不是 panic。
+fn get_github_client_id() -> String {
+    config::current().github_client_id.unwrap_or_default()
 }
 
-// --- 新增：为解析逻辑定义的错误类型 ---
-#[derive(Debug, PartialEq)]
-pub enum ConfigParseError {
-    MissingKey(String),
-    EmptyValue(String),
-    MalformedLine(usize, String), // 行号, 行内容
+fn get_github_client_secret() -> String {
+    config::current().github_client_secret.unwrap_or_default()
 }
 
-impl std::fmt::Display for ConfigParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ConfigParseError::MissingKey(key) => write!(f, "缺少必要的键: {}", key),
-            ConfigParseError::EmptyValue(key) => write!(f, "键 {} 的值不能为空", key),
-            ConfigParseError::MalformedLine(num, line) => {
-                write!(f, "无法解析第 {} 行 (缺少 '='?): {}", num, line)
-            }
-        }
-    }
+// 规范化后的基础 URL，不带末尾斜杠
+fn get_worker_api_url() -> String {
+    config::current()
+        .worker_api_url
+        .map(|url| url.as_str().trim_end_matches('/').to_string())
+        .unwrap_or_default()
 }
-impl std::error::Error for ConfigParseError {}
-
-pub(crate) fn parse_env_content(env_content: &str) -> Result<EnvConfig, ConfigParseError> {
-    // println!("Auth: [parse_env_content] 开始解析内容...");
-    let mut vars = HashMap::new();
-    for (line_num, line) in env_content.lines().enumerate() {
-        let trimmed_line = line.trim();
-        if trimmed_line.is_empty() || trimmed_line.starts_with('#') {
-            continue; // 跳过空行和以 # 开头的注释行
-        }
 
-        if let Some((key, value_raw)) = trimmed_line.split_once('=') {
-            let key_trimmed = key.trim();
+fn get_worker_api_key() -> String {
+    config::current().worker_api_key.unwrap_or_default()
+}
 
-            // --- START: 更简化的行内注释处理 ---
-            let value_without_comment_trimmed_end =
-                if let Some(comment_start_index) = value_raw.find('#') {
-                    // 检查 '#' 是否在引号内是高级功能，这里简化处理。
-                    // 假设非引号内的 '#' 及其后的内容为注释。
-                    let mut part_before_comment = &value_raw[..comment_start_index];
+// --- 基于构建类型的动态重定向 URI (保持不变) ---
+fn get_redirect_uri() -> &'static str {
+    if cfg!(debug_assertions) {
+        "http://127.0.0.1:54321/callback" // 开发: 本地服务器
+    } else {
+        "revision://github/callback" // 生产: 使用你的自定义 scheme "revision"
+    }
+}
 
-                    // 一个尝试性的检查，如果 `#` 前面看起来像一个结束的引号，那么注释是合理的
-                    // 例子: MY_VAR="value" # comment
-                    // 而不是: MY_VAR="value#inside"
-                    // 这个逻辑可以非常复杂，这里我们只处理最简单的情况
-                    let mut potential_val = part_before_comment.trim_end(); // 去掉注释前，值和 # 之间的空格
+// --- GitHub 与 worker 端点 ---
+// 基础地址不带末尾斜杠。运行时取自配置，测试时指向本地 mock 服务器 (见 mock_server)
+#[derive(Debug, Clone, PartialEq)]
+pub struct GithubEndpoints {
+    pub web_url: String, // 授权页与 OAuth token 端点所在的地址
+    pub api_url: String, // REST API 地址
+}
 
-                    // 检查被截断的部分是否以引号结束，如果是，可能需要特殊处理
-                    // 但对于 `.env` 通常简单地以第一个 `#` 分割就够了（如果它不在开头的引号内）
+impl GithubEndpoints {
+    // 默认是 github.com；GitHub Enterprise Server 通过 GITHUB_WEB_URL / GITHUB_API_URL 配置
+    pub fn from_config(config: &config::AppConfig) -> Self {
+        GithubEndpoints {
+            web_url: config.github_web_base(),
+            api_url: config.github_api_base(),
+        }
+    }
 
-                    potential_val // 返回修剪了尾部空格的注释前部分
-                } else {
-                    value_raw // 没有注释，使用原始值部分
-                };
-            // --- END: 更简化的行内注释处理 ---
+    fn authorize_url(&self) -> String {
+        format!("{}/login/oauth/authorize", self.web_url)
+    }
 
-            let value_initially_trimmed = value_without_comment_trimmed_end.trim(); // 1. 首先修剪两端空白
+    fn access_token_url(&self) -> String {
+        format!("{}/login/oauth/access_token", self.web_url)
+    }
 
-            let final_value = // 2. 检查并剥离包围的引号
-                if value_initially_trimmed.starts_with('"') && value_initially_trimmed.ends_with('"') && value_initially_trimmed.len() >= 2 {
-                    &value_initially_trimmed[1..value_initially_trimmed.len() - 1]
-                } else if value_initially_trimmed.starts_with('\'') && value_initially_trimmed.ends_with('\'') && value_initially_trimmed.len() >= 2 {
-                    &value_initially_trimmed[1..value_initially_trimmed.len() - 1]
-                } else {
-                    value_initially_trimmed
-                };
+    fn device_code_url(&self) -> String {
+        format!("{}/login/device/code", self.web_url)
+    }
 
-            if key_trimmed.is_empty() {
-                // println!("Auth: [parse_env_content] 警告 - 在第 {} 行解析到空键: {}", line_num + 1, line);
-                continue;
-            }
+    fn user_url(&self) -> String {
+        format!("{}/user", self.api_url)
+    }
+}
 
-            // 在实际运行时打印这个可能比较有用，但在测试中可能会产生大量输出
-            // if cfg!(not(test)){ // 只在非测试时打印
-            println!(
-                "Auth: [parse_env_content] 解析KeyValue: KEY='{}', FINAL_VALUE='{}' (原始行: '{}')",
-                key_trimmed, final_value, line
-            );
-            // }
+#[derive(Debug, Clone)]
+pub struct WorkerEndpoint {
+    pub base_url: String,
+    pub api_key: String,
+}
 
-            vars.insert(key_trimmed.to_string(), final_value.to_string());
-        } else {
-            if !trimmed_line.is_empty() {
-                // 只对非空且无 '=' 的行报错
-                // println!("Auth: [parse_env_content] 警告 - 无法解析第 {} 行 (缺少 '='?): {}", line_num + 1, line);
-                return Err(ConfigParseError::MalformedLine(
-                    line_num + 1,
-                    line.to_string(),
-                ));
-            }
+impl WorkerEndpoint {
+    // 当前运行时配置中的 worker；未配置时 base_url 为空，由调用方报告错误
+    pub fn current() -> Self {
+        WorkerEndpoint {
+            base_url: get_worker_api_url(),
+            api_key: get_worker_api_key(),
         }
     }
-    // println!("Auth: [parse_env_content] 完成解析. 找到 {} 个潜在变量.", vars.len());
-
-    // --- 提取逻辑，使用解析出的 'vars' map ---
-    // (后续的提取逻辑保持不变，但现在它们会收到更干净的 value)
-    // println!("Auth: [parse_env_content] 提取 GITHUB_CLIENT_ID...");
-    let github_client_id = vars
-        .get("GITHUB_CLIENT_ID")
-        .ok_or_else(|| ConfigParseError::MissingKey("GITHUB_CLIENT_ID".to_string()))?
-        .clone();
-    if github_client_id.is_empty() {
-        // println!("Auth: [parse_env_content] 错误 - GITHUB_CLIENT_ID 为空.");
-        return Err(ConfigParseError::EmptyValue("GITHUB_CLIENT_ID".to_string()));
-    }
-    // println!("Auth: [parse_env_content] GITHUB_CLIENT_ID = '{}'", github_client_id);
-
-    // println!("Auth: [parse_env_content] 提取 GITHUB_CLIENT_SECRET...");
-    let github_client_secret = vars
-        .get("GITHUB_CLIENT_SECRET")
-        .ok_or_else(|| ConfigParseError::MissingKey("GITHUB_CLIENT_SECRET".to_string()))?
-        .clone();
-    if github_client_secret.is_empty() {
-        // println!("Auth: [parse_env_content] 错误 - GITHUB_CLIENT_SECRET 为空.");
-        return Err(ConfigParseError::EmptyValue(
-            "GITHUB_CLIENT_SECRET".to_string(),
-        ));
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
     }
-    // let secret_len = github_client_secret.len();
-    // let masked_secret = if secret_len > 4 { /* ... */ } else { /* ... */ };
-    // println!("Auth: [parse_env_content] GITHUB_CLIENT_SECRET = '{}'", masked_secret);
-
-    // println!("Auth: [parse_env_content] 提取 WORKER_API_URL...");
-    let worker_api_url = vars
-        .get("WORKER_API_URL")
-        .ok_or_else(|| ConfigParseError::MissingKey("WORKER_API_URL".to_string()))?
-        .clone();
-    if worker_api_url.is_empty() {
-        // println!("Auth: [parse_env_content] 错误 - WORKER_API_URL 为空.");
-        return Err(ConfigParseError::EmptyValue("WORKER_API_URL".to_string()));
-    }
-    // println!("Auth: [parse_env_content] WORKER_API_URL = '{}'", worker_api_url);
-
-    // println!("Auth: [parse_env_content] 提取 WORKER_API_KEY...");
-    let worker_api_key = vars
-        .get("WORKER_API_KEY")
-        .ok_or_else(|| ConfigParseError::MissingKey("WORKER_API_KEY".to_string()))?
-        .clone();
-    if worker_api_key.is_empty() {
-        // println!("Auth: [parse_env_content] 错误 - WORKER_API_KEY 为空.");
-        return Err(ConfigParseError::EmptyValue("WORKER_API_KEY".to_string()));
-    }
-    // let key_len = worker_api_key.len();
-    // let masked_key = if key_len > 4 { /* ... */ } else { /* ... */ };
-    // println!("Auth: [parse_env_content] WORKER_API_KEY = '{}'", masked_key);
-
-    // println!("Auth: [parse_env_content] 成功构建 EnvConfig.");
-    Ok(EnvConfig {
-        github_client_id,
-        github_client_secret,
-        worker_api_url,
-        worker_api_key,
-    })
 }
 
-// --- 编译时嵌入和惰性解析 (使用详细日志) ---
-// 使用 include_str! 在 *编译时* 读取相应的 .env 文件
-// 在运行时首次访问时 *一次性* 解析内容。
-// 警告：这会将密钥直接嵌入到二进制文件中。
-static CONFIG: Lazy<EnvConfig> = Lazy::new(|| {
-    println!("Auth: 初始化嵌入式配置...");
-    let env_content = if cfg!(debug_assertions) {
-        println!("Auth: 嵌入 .env.development 内容.");
-        include_str!("../../.env.development") // 确保路径正确
-    } else {
-        println!("Auth: 嵌入 .env.production 内容.");
-        include_str!("../../.env.production") // 确保路径正确
-    };
+// 一次 GitHub 登录所需的全部配置，由登录流程显式传递而不是各处读取全局配置
+#[derive(Debug, Clone)]
+pub struct GithubAuthConfig {
+    pub client_id: String,
+    pub client_secret: String, // 只在直接兑换时使用
+    pub token_exchange: TokenExchange,
+    pub endpoints: GithubEndpoints,
+    pub worker: WorkerEndpoint,
+}
 
-    // println!("Auth: 解析嵌入式内容:\n---\n{}\n---", env_content); // 此日志移至 parse_env_content 内部
-    // 现在使用重构的函数
-    // .unwrap_or_else 会在解析失败时 panic，这与 Lazy 初始化的原始行为一致
-    match parse_env_content(env_content) {
-        Ok(config) => {
-            println!("Auth: 嵌入式配置成功初始化。");
-            config
+impl GithubAuthConfig {
+    pub fn current() -> Self {
+        let config = config::current();
+        GithubAuthConfig {
+            client_id: get_github_client_id(),
+            client_secret: get_github_client_secret(),
+            token_exchange: config.token_exchange(),
+            endpoints: GithubEndpoints::from_config(&config),
+            worker: WorkerEndpoint::current(),
         }
-        Err(e) => {
-            // 打印更详细的错误信息
-            eprintln!("Auth: 严重错误 - 解析嵌入式 .env 内容失败: {}", e);
-            eprintln!("Auth: 使用的内容:\n---\n{}\n---", env_content); // 打印导致错误的内容
-            panic!("Auth: 严重错误 - 解析嵌入式 .env 内容失败: {}", e);
+    }
+}
+
+// --- opener 作用域 ---
+// 前端通过 opener 插件打开授权页和设备码验证页，capabilities/default.json 只放行了
+// github.com。GitHub Enterprise Server 和 OIDC 提供方的地址来自配置，需要在运行时授权
+const LOGIN_URL_WINDOWS: [&str; 2] = ["main", "screenshot_query_window"];
+static GRANTED_LOGIN_ORIGINS: Lazy<StdMutex<HashSet<String>>> =
+    Lazy::new(|| StdMutex::new(HashSet::new()));
+
+// 配置中除 github.com 以外的登录页来源 (scheme://host[:port])
+fn login_url_origins(config: &config::AppConfig) -> Vec<String> {
+    let github_web = config.github_web_url.as_ref().map(|url| url.to_string());
+    let mut origins: Vec<String> = [github_web, config.oidc_issuer.clone()]
+        .into_iter()
+        .flatten()
+        .filter_map(|url| Url::parse(&url).ok())
+        .map(|url| url.origin().ascii_serialization())
+        .filter(|origin| origin != "https://github.com")
+        .collect();
+    origins.sort();
+    origins.dedup();
+    origins
+}
+
+// 启动时和 reload_config 后调用。运行时 capability 无法撤销，已授权的来源不会重复添加
+pub fn allow_login_urls<R: Runtime>(app: &AppHandle<R>) {
+    let mut granted = GRANTED_LOGIN_ORIGINS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    for origin in login_url_origins(&config::current()) {
+        if granted.contains(&origin) {
+            continue;
+        }
+        let capability = CapabilityBuilder::new(format!("login-urls-{}", granted.len()))
+            .windows(LOGIN_URL_WINDOWS)
+            .permission_scoped(
+                "opener:allow-open-url",
+                vec![serde_json::json!({ "url": format!("{}/*", origin) })],
+                Vec::<serde_json::Value>::new(),
+            );
+        match app.add_capability(capability) {
+            Ok(()) => {
+                println!("Auth: 已允许 opener 打开 {}/*", origin);
+                granted.insert(origin);
+            }
+            Err(e) => eprintln!("Auth: 无法为 {} 添加 opener 作用域: {}", origin, e),
         }
     }
-});
+}
 
-// --- 嵌入式配置的访问器函数 ---
-// 这些函数提供了对惰性初始化的静态 CONFIG 的清晰访问
-fn get_github_client_id() -> &'static str {
-    &CONFIG.github_client_id
+const CSRF_STATE_EXPIRY_SECS: u64 = 300; // 5 分钟
+const PKCE_VERIFIER_LEN: usize = 64; // RFC 7636 要求 43..=128 个字符
+const GITHUB_SCOPE: &str = "read:user user:email"; // 请求基本个人资料和邮箱访问权限
+
+// --- 设备码流程 (RFC 8628) ---
+// 不需要本地回调服务器或 revision:// 深层链接，适用于远程会话和部分 Linux 桌面
+pub const GITHUB_DEVICE_CODE_EVENT: &str = "github_device_code";
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const DEVICE_DEFAULT_INTERVAL_SECS: u64 = 5;
+const DEVICE_SLOW_DOWN_SECS: u64 = 5; // 收到 slow_down 时间隔至少增加 5 秒
+
+// --- PKCE (RFC 7636) ---
+// 授权码只有配合生成它时的 code_verifier 才能兑换，被截获的 code 无法单独使用
+#[derive(Debug, Clone, PartialEq)]
+pub struct PkceChallenge {
+    pub code_verifier: String,
+    pub code_challenge: String, // BASE64URL(SHA256(code_verifier))，不带填充
 }
 
-fn get_github_client_secret() -> &'static str {
-    &CONFIG.github_client_secret
+impl PkceChallenge {
+    pub fn generate() -> Self {
+        Self::from_verifier(random_alphanumeric(PKCE_VERIFIER_LEN))
+    }
+
+    fn from_verifier(code_verifier: String) -> Self {
+        let code_challenge = URL_SAFE_NO_PAD.encode(digest(&SHA256, code_verifier.as_bytes()));
+        Self {
+            code_verifier,
+            code_challenge,
+        }
+    }
 }
 
-pub fn get_worker_api_url() -> &'static str {
-    &CONFIG.worker_api_url
+// 回调收到的授权码，连同发起请求时生成的 code_verifier
+#[derive(Debug)]
+pub struct AuthCode {
+    pub(crate) code: String,
+    pub(crate) code_verifier: String,
 }
 
-pub fn get_worker_api_key() -> &'static str {
-    &CONFIG.worker_api_key
+// --- 状态管理 ---
+// 一次进行中的登录：等待回调的 sender，以及与 CSRF state 一起保存的 PKCE 参数和提供方
+pub struct PendingAuth {
+    sender: oneshot::Sender<Result<AuthCode, AuthError>>,
+    pkce: PkceChallenge,
+    provider: String, // 回调出错时用它的 {provider}_auth_error 事件
 }
 
-// --- 基于构建类型的动态重定向 URI (保持不变) ---
-fn get_redirect_uri() -> &'static str {
-    if cfg!(debug_assertions) {
-        "http://127.0.0.1:54321/callback" // 开发: 本地服务器
-    } else {
-        "revision://github/callback" // 生产: 使用你的自定义 scheme "revision"
+impl PendingAuth {
+    // 本次登录的错误事件名，例如 oidc_auth_error
+    pub fn error_event(&self) -> String {
+        auth_event(&self.provider, "error")
+    }
+
+    // 把回调收到的 code 交给等待中的登录任务；任务已不再等待时返回 false
+    pub fn complete(self, code: String) -> bool {
+        self.sender
+            .send(Ok(AuthCode {
+                code,
+                code_verifier: self.pkce.code_verifier,
+            }))
+            .is_ok()
     }
 }
 
-const CSRF_STATE_EXPIRY_SECS: u64 = 300; // 5 分钟
+// 待处理请求的共享状态，以 CSRF state 为键 (开发服务器和深层链接处理器都使用)
+pub type PendingAuthState = Arc<StdMutex<HashMap<String, PendingAuth>>>;
 
-// --- 状态管理 ---
-// 待处理请求的共享状态 (开发服务器和深层链接处理器都使用)
-pub type PendingAuthState =
-    Arc<StdMutex<HashMap<String, oneshot::Sender<Result<String, AuthError>>>>>;
+// 回调无法对应到某次登录 (state 无效或缺少参数) 时，通知仍在等待回调的各提供方
+pub fn pending_error_events(pending_state: &PendingAuthState) -> Vec<String> {
+    let pending_map = pending_state.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut events: Vec<String> = pending_map.values().map(PendingAuth::error_event).collect();
+    events.sort();
+    events.dedup();
+    events
+}
+
+// 设备码登录的代数：每次开始或取消都会加一，旧的轮询任务发现代数变化后退出
+pub type DeviceLoginState = Arc<AtomicU64>;
 
 // --- 开发服务器特定状态 ---
 #[cfg(debug_assertions)]
@@ -270,13 +289,54 @@ struct CallbackParams {
     state: String,
 }
 
+// GitHub 在兑换失败时也可能返回 200，错误放在 error/error_description 中
 #[derive(Deserialize, Debug)]
-struct GithubTokenResponse {
-    access_token: String,
+pub(crate) struct GithubTokenResponse {
+    #[serde(default)]
+    pub(crate) access_token: String,
+    error: Option<String>,
+    error_description: Option<String>,
+    // 设备码流程中随 slow_down 返回的新轮询间隔
+    interval: Option<u64>,
     // scope: String, // 如果需要，保留
     // token_type: String, // 如果需要，保留
 }
 
+#[derive(Deserialize, Debug)]
+struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+// 发给前端显示的内容 (命令返回值和 github_device_code 事件)；device_code 只留在后端
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCodeInfo {
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+// 一次轮询的结果
+#[derive(Debug)]
+enum DevicePoll {
+    Token(String),
+    Wait(u64), // 按给定间隔 (秒) 继续轮询
+    Failed(AuthError),
+}
+
+// 委托 worker 兑换 code 时发送的内容；client secret 只保存在 worker 上
+#[derive(Serialize, Debug)]
+struct WorkerTokenExchangePayload<'a> {
+    code: &'a str,
+    code_verifier: &'a str,
+    redirect_uri: &'a str,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GithubUserProfile {
     login: String,
@@ -286,6 +346,38 @@ pub struct GithubUserProfile {
     email: Option<String>, // 确保请求了 'user:email' 作用域
 }
 
+// 转换为与提供方无关的 profile (会话和前端使用)
+impl From<GithubUserProfile> for UserProfile {
+    fn from(profile: GithubUserProfile) -> Self {
+        UserProfile {
+            provider: GITHUB_PROVIDER_ID.to_string(),
+            id: profile.id.to_string(),
+            login: profile.login,
+            name: profile.name,
+            email: profile.email,
+            avatar_url: Some(profile.avatar_url),
+        }
+    }
+}
+
+// 后端同步仍使用 GitHub 的 profile 格式 (数字 id)
+impl TryFrom<&UserProfile> for GithubUserProfile {
+    type Error = AuthError;
+
+    fn try_from(profile: &UserProfile) -> Result<Self, Self::Error> {
+        let id = profile.id.parse().map_err(|_| {
+            AuthError::InternalError(format!("无效的 GitHub 用户 id: {}", profile.id))
+        })?;
+        Ok(GithubUserProfile {
+            login: profile.login.clone(),
+            id,
+            name: profile.name.clone(),
+            avatar_url: profile.avatar_url.clone().unwrap_or_default(),
+            email: profile.email.clone(),
+        })
+    }
+}
+
 #[derive(Serialize, Debug)]
 struct BackendSyncPayload<'a> {
     profile: &'a GithubUserProfile,
@@ -323,6 +415,16 @@ pub enum AuthError {
     DeepLinkError(String),
     #[error("Tauri 操作失败: {0}")] // <--- 新增变体
     TauriError(String),
+    #[error("GitHub 拒绝了 access token (已过期或被撤销)")]
+    TokenRejected,
+    #[error("设备码已过期，请重新登录")]
+    DeviceCodeExpired,
+    #[error("身份提供方返回错误: {0}")]
+    ProviderError(String),
+    #[error("ID token 校验失败: {0}")]
+    InvalidIdToken(String),
+    #[error("未知的身份提供方: {0}")]
+    UnknownProvider(String),
     // #[error("配置错误: {0}")]
     // ConfigError(String),
 }
@@ -348,36 +450,73 @@ pub async fn login_with_github<R: Runtime>(
     pending_auth_state: State<'_, PendingAuthState>,
 ) -> Result<String, String> {
     // 返回 GitHub Auth URL 或错误字符串
-    println!("Auth: 启动 GitHub OAuth 流程...");
+    start_browser_login(app, pending_auth_state, GITHUB_PROVIDER_ID).await
+}
+
+// 使用任一已配置的身份提供方登录 (见 list_identity_providers)，返回待打开的授权 URL。
+// 结果通过 {provider}_auth_success / {provider}_auth_error 以及 session_changed 事件到达
+#[tauri::command]
+pub async fn login_with_provider<R: Runtime>(
+    app: AppHandle<R>,
+    pending_auth_state: State<'_, PendingAuthState>,
+    provider: String,
+) -> Result<String, String> {
+    start_browser_login(app, pending_auth_state, &provider).await
+}
+
+// 各提供方的事件名，例如 github_auth_success、oidc_auth_error
+fn auth_event(provider_id: &str, outcome: &str) -> String {
+    format!("{}_auth_{}", provider_id, outcome)
+}
+
+// 浏览器登录 (授权码 + PKCE)：返回授权 URL，后台等待回调/深层链接后完成登录
+async fn start_browser_login<R: Runtime>(
+    app: AppHandle<R>,
+    pending_auth_state: State<'_, PendingAuthState>,
+    provider_id: &str,
+) -> Result<String, String> {
+    println!("Auth: 启动 {} OAuth 流程...", provider_id);
+    let error_event = auth_event(provider_id, "error");
 
     // --- 确定重定向 URI ---
     let redirect_uri = get_redirect_uri();
     println!("Auth: 使用重定向 URI: {}", redirect_uri);
 
-    // --- 获取 Client ID 并仔细记录 ---
-    // 通过访问器函数访问嵌入式配置。
-    // 这会在首次调用时触发 Lazy 初始化。
-    let github_client_id = get_github_client_id();
-    // 记录从配置中获取的原始 ID 值，以验证其是否正确且非空。
-    println!("Auth: 使用配置中的 Client ID: '{}'", github_client_id);
-    // 确保 client_id 在获取后不为空，否则 URL 将无效。
-    if github_client_id.is_empty() {
-        let err_msg = "严重错误: 初始化后嵌入的 GITHUB_CLIENT_ID 为空。".to_string();
-        eprintln!("Auth: {}", err_msg);
-        // 可选地发出错误事件
-        let _ = app.emit(
-            "github_auth_error",
-            Some(AuthError::InternalError(err_msg.clone())),
-        );
-        return Err(err_msg); // 将错误返回给前端
-    }
-    // --- State 和 Channel 设置 ---
-    let state: String = thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32) // 生成一个随机 state 字符串
-        .map(char::from)
-        .collect();
-    let (code_tx, code_rx) = oneshot::channel::<Result<String, AuthError>>();
+    // --- 获取身份提供方 ---
+    // 每次登录都从当前运行时配置构建 (可通过 reload_config 重新加载)，未配置时返回具体原因
+    let provider = match identity::provider(provider_id) {
+        Ok(provider) => provider,
+        Err(e) => {
+            eprintln!("Auth: {}", e);
+            let _ = app.emit(&error_event, Some(e.clone()));
+            return Err(e.to_string());
+        }
+    };
+    // --- State、nonce、PKCE 和 Channel 设置 ---
+    let state = random_alphanumeric(32); // 生成一个随机 state 字符串
+    let nonce = random_alphanumeric(32); // 将 OIDC ID token 绑定到本次登录
+    let pkce = PkceChallenge::generate();
+    let http = app.state::<HttpClient>().inner().clone();
+
+    // --- 构建授权 URL ---
+    // 在启动开发服务器之前完成：OIDC 需要先获取 discovery 文档，失败时无需清理
+    let params = AuthorizeParams {
+        redirect_uri,
+        state: &state,
+        code_challenge: &pkce.code_challenge, // 只发送 challenge，verifier 留在本地
+        nonce: &nonce,
+    };
+    let auth_url = match provider.authorize_url(http.client(), &params).await {
+        Ok(auth_url) => auth_url,
+        Err(e) => {
+            eprintln!("Auth: 构建授权 URL 失败: {:?}", e);
+            let _ = app.emit(&error_event, Some(e.clone()));
+            return Err(e.to_string());
+        }
+    };
+    // --- !!! 打印最终的 URL 以进行调试 !!! ---
+    println!("Auth: 生成的待打开 Auth URL: {}", auth_url);
+    let (code_tx, code_rx) = oneshot::channel::<Result<AuthCode, AuthError>>();
 
     // --- 条件性：启动开发服务器 ---
     #[cfg(debug_assertions)]
@@ -385,7 +524,6 @@ pub async fn login_with_github<R: Runtime>(
         if let Some(server_state) = app.try_state::<AuthServerState>() {
             println!("Auth [Debug]: 尝试启动本地回调服务器...");
             let server_start_result = start_dev_server(
-                app.clone(),
                 pending_auth_state.inner().clone(), // 传递 Arc<StdMutex<...>>
                 server_state.inner().clone(),       // 传递 Arc<TokioMutex<...>>
             )
@@ -393,7 +531,7 @@ pub async fn login_with_github<R: Runtime>(
 
             if let Err(e) = server_start_result {
                 eprintln!("Auth [Debug]: 启动服务器失败: {:?}", e);
-                let _ = app.emit("github_auth_error", Some(e.clone())); // 发出特定错误
+                let _ = app.emit(&error_event, Some(e.clone())); // 发出特定错误
                 return Err(e.to_string()); // 将错误返回给前端 invoke
             }
             println!("Auth [Debug]: 本地回调服务器正在运行或已启动。");
@@ -401,7 +539,7 @@ pub async fn login_with_github<R: Runtime>(
             let err =
                 AuthError::InternalError("AuthServerState 在 debug 构建中未被管理".to_string());
             eprintln!("Auth [Debug]: 错误 - {}", err);
-            let _ = app.emit("github_auth_error", Some(err.clone()));
+            let _ = app.emit(&error_event, Some(err.clone()));
             return Err(err.to_string());
         }
     } // 结束 #[cfg(debug_assertions)] 块，用于启动服务器
@@ -411,33 +549,19 @@ pub async fn login_with_github<R: Runtime>(
         let mut pending_map = pending_auth_state
             .lock()
             .expect("锁定 pending auth state 失败");
-        pending_map.insert(state.clone(), code_tx);
-        println!("Auth: State '{}' 已存储。准备好进行回调/深层链接。", state);
-    }
-    // --- 编码 URL 所需的参数 ---
-    // 编码 redirect_uri
-    let encoded_redirect_uri = urlencoding::encode(redirect_uri);
-    println!("Auth: 编码后的 Redirect URI: {}", encoded_redirect_uri);
-
-    // 编码 scope
-    let scope = "read:user user:email"; // 请求基本个人资料和邮箱访问权限
-    let encoded_scope = urlencoding::encode(scope);
-    println!("Auth: 编码后的 Scope: {}", encoded_scope);
-
-    // State 通常*不需要*编码，除非它包含特殊的 URL 字符，
-    // 但如果你期望不寻常的 state 值，这样做更安全。标准的 Alphanumeric 是可以的。
-    // let encoded_state = urlencoding::encode(&state);
-    // --- 构建 GitHub 授权 URL ---
-    let auth_url = format!(
-        "https://github.com/login/oauth/authorize?client_id={}&redirect_uri={}&scope={}&state={}",
-        github_client_id,     // 使用经过验证的、非空的 client_id
-        encoded_redirect_uri, // 使用编码后的 redirect URI
-        encoded_scope,        // 使用编码后的 scope
-        state.clone()         // 使用原始的 state 字符串
-    );
-
-    // --- !!! 打印最终的 URL 以进行调试 !!! ---
-    println!("Auth: 生成的待打开 Auth URL: {}", auth_url);
+        pending_map.insert(
+            state.clone(),
+            PendingAuth {
+                sender: code_tx,
+                pkce,
+                provider: provider_id.to_string(),
+            },
+        );
+        println!(
+            "Auth: State '{}' 及 PKCE verifier 已存储。准备好进行回调/深层链接。",
+            state
+        );
+    }
 
     // --- 生成任务以等待回调/深层链接并处理流程 ---
     let task_app_handle = app.clone();
@@ -458,7 +582,7 @@ pub async fn login_with_github<R: Runtime>(
             Ok(Ok(code_res)) => {
                 // 成功从 channel接收
                 println!("Auth Task [{}]: 通过 channel 收到 Code。", task_state);
-                code_res // 这是 Result<String, AuthError>
+                code_res // 这是 Result<AuthCode, AuthError>
             }
             Ok(Err(_rx_err)) => {
                 // Channel sender 被丢弃
@@ -486,25 +610,20 @@ pub async fn login_with_github<R: Runtime>(
 
         // --- 处理结果 (交换 code, 获取 Profile, 同步, 发出事件) ---
         let final_result: Result<(), AuthError> = async {
-            let code = code_result?; // 传播错误
+            let auth_code = code_result?; // 传播错误
             println!("Auth Task [{}]: 正在用 code 交换 token...", task_state);
-            let token_info = exchange_code_for_token(&code).await?;
-            println!("Auth Task [{}]: 正在获取 GitHub profile...", task_state);
-            let profile = fetch_github_user_profile(&token_info.access_token).await?;
-            println!(
-                "Auth Task [{}]: 已为 '{}' 获取 Profile",
-                task_state, profile.login
-            );
-            println!("Auth Task [{}]: 正在将 profile 同步到后端...", task_state);
-            sync_user_profile_to_backend(&profile).await?;
-            println!("Auth Task [{}]: 身份验证成功。正在发出事件。", task_state);
-            task_app_handle.emit(
-                "github_auth_success",
-                Some(serde_json::json!({
-                    "profile": profile
-                })),
-            )?; // 使用 ? 传播 emit 错误
-            Ok(())
+            // 使用应用内共享的 HTTP 客户端 (带超时和连接池)
+            let tokens = provider
+                .exchange_code(http.client(), &auth_code, get_redirect_uri(), &nonce)
+                .await?;
+            complete_login(
+                &task_app_handle,
+                http.client(),
+                provider.as_ref(),
+                tokens,
+                &task_state,
+            )
+            .await
         }
         .await;
 
@@ -534,7 +653,7 @@ pub async fn login_with_github<R: Runtime>(
                     }
                 }
             }
-            let _ = task_app_handle.emit("github_auth_error", Some(final_err));
+            let _ = task_app_handle.emit(&error_event, Some(final_err));
         }
         // --- 条件性：关闭开发服务器 ---
         #[cfg(debug_assertions)]
@@ -557,11 +676,277 @@ pub async fn login_with_github<R: Runtime>(
     Ok(auth_url) // 返回 URL 供前端打开
 }
 
+// 设备码登录：返回用户码和验证地址 (同时发出 github_device_code 事件)，
+// 后台按服务器给定的间隔轮询，拿到 token 后与浏览器登录走同样的后续步骤
+#[tauri::command]
+pub async fn login_with_github_device<R: Runtime>(
+    app: AppHandle<R>,
+    device_login: State<'_, DeviceLoginState>,
+    http: State<'_, HttpClient>,
+) -> Result<DeviceCodeInfo, String> {
+    println!("Auth: 启动 GitHub 设备码登录流程...");
+    let github = GithubAuthConfig::current();
+    if github.client_id.is_empty() {
+        let err_msg = "GITHUB_CLIENT_ID 未配置 (请检查配置文件或环境变量)。".to_string();
+        eprintln!("Auth: {}", err_msg);
+        let _ = app.emit(
+            "github_auth_error",
+            Some(AuthError::InternalError(err_msg.clone())),
+        );
+        return Err(err_msg);
+    }
+
+    let client = http.client().clone();
+    let device_code = match request_device_code(&client, &github).await {
+        Ok(device_code) => device_code,
+        Err(e) => {
+            eprintln!("Auth: 请求设备码失败: {:?}", e);
+            let _ = app.emit("github_auth_error", Some(e.clone()));
+            return Err(e.to_string());
+        }
+    };
+    let info = DeviceCodeInfo {
+        user_code: device_code.user_code.clone(),
+        verification_uri: device_code.verification_uri.clone(),
+        expires_in: device_code.expires_in,
+        interval: device_code.interval.unwrap_or(DEVICE_DEFAULT_INTERVAL_SECS),
+    };
+    let _ = app.emit(GITHUB_DEVICE_CODE_EVENT, &info);
+
+    // 新的登录会让之前仍在轮询的任务退出
+    let generation = device_login.fetch_add(1, Ordering::SeqCst) + 1;
+    let task_device_login = device_login.inner().clone();
+    let task_app_handle = app.clone();
+    let task_id = format!("device-{}", generation);
+    let interval = info.interval;
+
+    tokio::spawn(async move {
+        println!("Auth Task [{}]: 已生成。等待用户在浏览器中授权...", task_id);
+        let result = async {
+            let access_token = poll_device_token(&client, &github, &device_code, interval, || {
+                task_device_login.load(Ordering::SeqCst) != generation
+            })
+            .await?;
+            let provider = identity::provider(GITHUB_PROVIDER_ID)?;
+            let tokens = TokenSet {
+                access_token,
+                subject: None,
+            };
+            complete_login(
+                &task_app_handle,
+                &client,
+                provider.as_ref(),
+                tokens,
+                &task_id,
+            )
+            .await
+        }
+        .await;
+        match result {
+            // 已被取消或被新的登录取代：前端已经不再等待这次登录，不发错误事件
+            Err(AuthError::Cancelled) if task_device_login.load(Ordering::SeqCst) != generation => {
+                println!("Auth Task [{}]: 设备码登录已取消。", task_id);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Auth Task [{}]: 设备码登录失败: {:?}", task_id, e);
+                let _ = task_app_handle.emit("github_auth_error", Some(e));
+            }
+            Ok(()) => {}
+        }
+        println!("Auth Task [{}]: 完成。", task_id);
+    });
+
+    println!("Auth: 将设备码返回给前端。");
+    Ok(info)
+}
+
+// 停止正在进行的设备码轮询 (例如用户关闭了登录对话框)
+#[tauri::command]
+pub fn cancel_device_login(device_login: State<'_, DeviceLoginState>) {
+    println!("Auth: 取消设备码登录。");
+    device_login.fetch_add(1, Ordering::SeqCst);
+}
+
+async fn request_device_code(
+    client: &reqwest::Client,
+    github: &GithubAuthConfig,
+) -> Result<DeviceCodeResponse, AuthError> {
+    let response = client
+        .post(github.endpoints.device_code_url())
+        .header(ACCEPT, "application/json")
+        .form(&[
+            ("client_id", github.client_id.as_str()),
+            ("scope", GITHUB_SCOPE),
+        ])
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "读取错误体失败".to_string());
+    if !status.is_success() {
+        return Err(AuthError::GitHubError(format!(
+            "请求设备码失败 (status {}): {}",
+            status, body
+        )));
+    }
+    // OAuth App 未启用 Device Flow 时，GitHub 同样以 200 返回 error 字段
+    if let Ok(GithubTokenResponse {
+        error: Some(error),
+        error_description,
+        ..
+    }) = serde_json::from_str::<GithubTokenResponse>(&body)
+    {
+        return Err(AuthError::GitHubError(format!(
+            "请求设备码失败: {} {}",
+            error,
+            error_description.unwrap_or_default()
+        )));
+    }
+    serde_json::from_str::<DeviceCodeResponse>(&body)
+        .map_err(|e| AuthError::ParseError(format!("解析设备码响应失败: {}", e)))
+}
+
+// 轮询直到用户授权、拒绝、设备码过期或登录被取消
+async fn poll_device_token(
+    client: &reqwest::Client,
+    github: &GithubAuthConfig,
+    device_code: &DeviceCodeResponse,
+    mut interval: u64,
+    is_cancelled: impl Fn() -> bool,
+) -> Result<String, AuthError> {
+    let deadline =
+        tokio::time::Instant::now() + std::time::Duration::from_secs(device_code.expires_in);
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        if is_cancelled() {
+            return Err(AuthError::Cancelled);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(AuthError::DeviceCodeExpired);
+        }
+
+        let params = [
+            ("client_id", github.client_id.as_str()),
+            ("device_code", device_code.device_code.as_str()),
+            ("grant_type", DEVICE_GRANT_TYPE),
+        ];
+        let sent = client
+            .post(github.endpoints.access_token_url())
+            .header(ACCEPT, "application/json")
+            .form(&params)
+            .send()
+            .await;
+        let body = match sent {
+            Ok(response) => response.text().await,
+            Err(e) => Err(e),
+        };
+        let response = match body
+            .map_err(|e| e.to_string())
+            .and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string()))
+        {
+            Ok(response) => response,
+            Err(e) => {
+                // 轮询本身就是重试，网络抖动时等下一轮即可
+                eprintln!("Auth: 设备码轮询失败，稍后重试: {}", e);
+                continue;
+            }
+        };
+        match device_poll_step(response, interval) {
+            DevicePoll::Token(access_token) => return Ok(access_token),
+            DevicePoll::Wait(next) => interval = next,
+            DevicePoll::Failed(e) => return Err(e),
+        }
+    }
+}
+
+fn device_poll_step(response: GithubTokenResponse, interval: u64) -> DevicePoll {
+    match response.error.as_deref() {
+        None if !response.access_token.is_empty() => DevicePoll::Token(response.access_token),
+        None => DevicePoll::Failed(AuthError::GitHubError(
+            "从 GitHub 收到空的 access token".to_string(),
+        )),
+        Some("authorization_pending") => DevicePoll::Wait(interval),
+        // 优先使用服务器给出的新间隔，否则自行增加 5 秒
+        Some("slow_down") => DevicePoll::Wait(
+            response
+                .interval
+                .unwrap_or(interval + DEVICE_SLOW_DOWN_SECS)
+                .max(interval + DEVICE_SLOW_DOWN_SECS),
+        ),
+        Some("expired_token") => DevicePoll::Failed(AuthError::DeviceCodeExpired),
+        Some("access_denied") => DevicePoll::Failed(AuthError::Cancelled),
+        Some(error) => DevicePoll::Failed(AuthError::GitHubError(format!(
+            "设备码登录失败: {} {}",
+            error,
+            response.error_description.as_deref().unwrap_or("")
+        ))),
+    }
+}
+
+// 拿到 access token 之后的公共步骤 (浏览器回调和设备码两种登录方式、所有提供方共用)：
+// 获取 profile、同步到后端、保存会话并发出成功事件
+async fn complete_login<R: Runtime>(
+    app: &AppHandle<R>,
+    client: &reqwest::Client,
+    provider: &dyn IdentityProvider,
+    tokens: TokenSet,
+    task_id: &str,
+) -> Result<(), AuthError> {
+    println!(
+        "Auth Task [{}]: 正在获取 {} profile...",
+        task_id,
+        provider.id()
+    );
+    let profile = provider.fetch_profile(client, &tokens.access_token).await?;
+    // userinfo 必须属于 ID token 中的同一用户
+    if let Some(subject) = &tokens.subject {
+        if &profile.id != subject {
+            return Err(AuthError::InvalidIdToken(format!(
+                "userinfo 的 sub '{}' 与 ID token 的 sub '{}' 不一致",
+                profile.id, subject
+            )));
+        }
+    }
+    println!(
+        "Auth Task [{}]: 已为 '{}' 获取 Profile",
+        task_id, profile.login
+    );
+    // 后端目前只接收 GitHub 用户
+    if provider.id() == GITHUB_PROVIDER_ID {
+        println!("Auth Task [{}]: 正在将 profile 同步到后端...", task_id);
+        let github_profile = GithubUserProfile::try_from(&profile)?;
+        sync_user_profile_to_backend(
+            client,
+            &WorkerEndpoint::current(),
+            &github_profile,
+            |attempt| {
+                let _ = app.emit(HTTP_RETRY_EVENT, attempt);
+            },
+        )
+        .await?;
+    }
+    // 保存会话 (token + profile)，下次启动时自动恢复，而不是用完即弃
+    if let Some(session) = app.try_state::<SessionState>() {
+        let info = session.sign_in(tokens.access_token, profile.clone());
+        emit_session_changed(app, Some(&info));
+    }
+    println!("Auth Task [{}]: 身份验证成功。正在发出事件。", task_id);
+    app.emit(
+        &auth_event(provider.id(), "success"),
+        Some(serde_json::json!({
+            "profile": profile
+        })),
+    )?; // 使用 ? 传播 emit 错误
+    Ok(())
+}
+
 // --- === 开发服务器特定代码 (仅在 debug 构建时编译) === ---
 
 #[cfg(debug_assertions)]
-async fn start_dev_server<R: Runtime>(
-    app_handle: AppHandle<R>,
+async fn start_dev_server(
     pending_state_clone: PendingAuthState, // Arc<StdMutex<...>>
     server_state_clone: AuthServerState,   // Arc<TokioMutex<...>>
 ) -> Result<(), AuthError> {
@@ -605,15 +990,7 @@ async fn start_dev_server<R: Runtime>(
         Err(e) => {
             let err_msg = format!("绑定到 {} 失败: {}", addr, e);
             eprintln!("Auth [Debug]: {}", err_msg);
-            // 出于某种原因，在 start_dev_server 内部直接使用原始 app_handle 发出事件
-            // 有时会导致奇怪的生命周期或借用问题，尤其是在复杂的异步场景或测试中。
-            // 克隆 app_handle (或只克隆 emitter) 可以帮助解决这些问题。
-            let emitter = app_handle.clone(); // 克隆 AppHandle 以便在错误路径中使用
-            let _ = emitter.emit(
-                // 使用克隆的 emitter
-                "github_auth_error",
-                Some(AuthError::ServerStartError(err_msg.clone())),
-            );
+            // 调用方 (start_browser_login) 会向发起登录的提供方发出错误事件
             return Err(AuthError::ServerStartError(err_msg));
         }
     };
@@ -690,13 +1067,12 @@ async fn github_callback_handler(
         params.state
     );
 
-    let sender = pending_state.lock().unwrap().remove(&params.state);
+    let pending = pending_state.lock().unwrap().remove(&params.state);
 
-    match sender {
-        Some(tx) => {
+    match pending {
+        Some(pending) => {
             println!("Auth [Debug] Callback: State 匹配。通过 channel 发送 code。");
-            let send_result = tx.send(Ok(params.code));
-            if send_result.is_err() {
+            if !pending.complete(params.code) {
                 eprintln!(
                     "Auth [Debug] Callback: Receiver 被丢弃 (任务可能超时/出错)。State: {}",
                     params.state
@@ -718,13 +1094,74 @@ async fn github_callback_handler(
     }
 }
 // --- === 核心 API 交互逻辑 (通过访问器使用嵌入式配置，带日志) === ---
-// 用授权码交换访问令牌
-async fn exchange_code_for_token(code: &str) -> Result<GithubTokenResponse, AuthError> {
-    let client = reqwest::Client::new();
+// GitHub 授权 URL；GitHub 不签发 ID token，因此不发送 nonce
+pub(crate) fn github_authorize_url(github: &GithubAuthConfig, params: &AuthorizeParams) -> String {
+    // challenge 是 base64url 字符，state 是字母数字，无需编码
+    format!(
+        "{}?client_id={}&redirect_uri={}&scope={}&state={}&code_challenge={}&code_challenge_method=S256",
+        github.endpoints.authorize_url(),
+        github.client_id,
+        urlencoding::encode(params.redirect_uri),
+        urlencoding::encode(GITHUB_SCOPE),
+        params.state,
+        params.code_challenge
+    )
+}
+
+// 用授权码 (及 PKCE verifier) 交换访问令牌。默认委托 worker 完成，
+// 这样桌面端永远不需要持有 GitHub client secret。
+pub(crate) async fn exchange_code_for_token(
+    client: &reqwest::Client,
+    github: &GithubAuthConfig,
+    auth_code: &AuthCode,
+) -> Result<GithubTokenResponse, AuthError> {
+    println!("Auth: 正在交换 code。兑换方式: {:?}", github.token_exchange);
+    println!("Auth: 正在交换 code。使用的 Code: [隐藏]"); // 不要记录 code 本身
+    match github.token_exchange {
+        TokenExchange::Worker => exchange_code_via_worker(client, &github.worker, auth_code).await,
+        TokenExchange::Direct => exchange_code_directly(client, github, auth_code).await,
+    }
+}
+
+// 由 worker 携带 client secret 向 GitHub 兑换 code
+async fn exchange_code_via_worker(
+    client: &reqwest::Client,
+    worker: &WorkerEndpoint,
+    auth_code: &AuthCode,
+) -> Result<GithubTokenResponse, AuthError> {
+    if worker.base_url.is_empty() {
+        return Err(AuthError::InternalError(config::describe_issues(
+            config::WORKER_API_URL,
+        )));
+    }
+    let exchange_url = worker.url("auth/github/token");
+    println!("Auth: 通过 worker 交换 code: {}", exchange_url);
+
+    let payload = WorkerTokenExchangePayload {
+        code: &auth_code.code,
+        code_verifier: &auth_code.code_verifier,
+        redirect_uri: get_redirect_uri(),
+    };
+    // code 只能兑换一次，因此不重试
+    let response = client
+        .post(&exchange_url)
+        .header(AUTHORIZATION, format!("Bearer {}", worker.api_key))
+        .header(CONTENT_TYPE, "application/json")
+        .json(&payload)
+        .send()
+        .await?;
+    read_token_response(response, "worker").await
+}
+
+// 桌面端自己持有 client secret 并直接向 GitHub 兑换 (旧行为，需显式配置或提供 secret)
+async fn exchange_code_directly(
+    client: &reqwest::Client,
+    github: &GithubAuthConfig,
+    auth_code: &AuthCode,
+) -> Result<GithubTokenResponse, AuthError> {
     let redirect_uri = get_redirect_uri();
-    // 使用访问器获取编译时嵌入的值
-    let github_client_id = get_github_client_id();
-    let github_client_secret = get_github_client_secret();
+    let github_client_id = &github.client_id;
+    let github_client_secret = &github.client_secret;
 
     // 记录用于请求的参数
     println!(
@@ -745,59 +1182,93 @@ async fn exchange_code_for_token(code: &str) -> Result<GithubTokenResponse, Auth
         "Auth: 正在交换 code。使用的 Redirect URI: '{}'",
         redirect_uri
     );
-    println!("Auth: 正在交换 code。使用的 Code: [隐藏]"); // 不要记录 code 本身
 
     let params = [
-        ("client_id", github_client_id),
-        ("client_secret", github_client_secret),
-        ("code", code),
+        ("client_id", github_client_id.as_str()),
+        ("client_secret", github_client_secret.as_str()),
+        ("code", auth_code.code.as_str()),
+        ("code_verifier", auth_code.code_verifier.as_str()),
         ("redirect_uri", redirect_uri),
     ];
 
     let response = client
-        .post("https://github.com/login/oauth/access_token")
+        .post(github.endpoints.access_token_url())
         .header(ACCEPT, "application/json")
-        .header(USER_AGENT, "Tauri GitHub Auth (Rust)")
         .form(&params)
         .send()
         .await?;
+    read_token_response(response, "GitHub").await
+}
 
+// 两种兑换方式共用：检查状态码、解析 token，并把 200 中的错误字段转换为 AuthError
+async fn read_token_response(
+    response: reqwest::Response,
+    source: &str,
+) -> Result<GithubTokenResponse, AuthError> {
     if response.status().is_success() {
-        let token_response = response
-            .json::<GithubTokenResponse>()
+        let body = response
+            .text()
             .await
-            .map_err(|e| AuthError::ParseError(format!("解析 token 响应失败: {}", e)))?;
-        if token_response.access_token.is_empty() {
-            eprintln!("Auth: Token 交换成功但收到空的 access token。");
-            Err(AuthError::GitHubError(
-                "从 GitHub 收到空的 access token".to_string(),
-            ))
-        } else {
-            println!("Auth: Token 交换成功。");
-            Ok(token_response)
-        }
+            .map_err(|e| AuthError::ParseError(format!("读取 token 响应失败: {}", e)))?;
+        parse_token_response(&body)
     } else {
         let status = response.status();
         let error_text = response
             .text()
             .await
             .unwrap_or_else(|_| "读取错误体失败".to_string());
-        eprintln!("Auth: GitHub token 交换错误 ({}): {}", status, error_text);
+        eprintln!(
+            "Auth: {} token 交换错误 ({}): {}",
+            source, status, error_text
+        );
         Err(AuthError::GitHubError(format!(
             "交换 code 失败 (status {}): {}",
             status, error_text
         )))
     }
 }
-// 使用访问令牌从 GitHub API 获取用户个人资料
-async fn fetch_github_user_profile(access_token: &str) -> Result<GithubUserProfile, AuthError> {
-    let client = reqwest::Client::new();
+
+fn parse_token_response(body: &str) -> Result<GithubTokenResponse, AuthError> {
+    let token_response = serde_json::from_str::<GithubTokenResponse>(body)
+        .map_err(|e| AuthError::ParseError(format!("解析 token 响应失败: {}", e)))?;
+    if let Some(error) = &token_response.error {
+        // 例如 bad_verification_code：code 过期或 verifier 不匹配
+        let description = token_response.error_description.as_deref().unwrap_or("");
+        eprintln!("Auth: GitHub 拒绝了 code: {} {}", error, description);
+        Err(AuthError::GitHubError(format!(
+            "GitHub 拒绝了 code: {} {}",
+            error, description
+        )))
+    } else if token_response.access_token.is_empty() {
+        eprintln!("Auth: Token 交换成功但收到空的 access token。");
+        Err(AuthError::GitHubError(
+            "从 GitHub 收到空的 access token".to_string(),
+        ))
+    } else {
+        println!("Auth: Token 交换成功。");
+        Ok(token_response)
+    }
+}
+
+// 生成随机的字母数字字符串 (用于 CSRF state 和 PKCE verifier)
+fn random_alphanumeric(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+// 使用访问令牌从 GitHub API 获取用户个人资料 (登录时以及 session 刷新时使用)
+pub(crate) async fn fetch_github_user_profile(
+    client: &reqwest::Client,
+    endpoints: &GithubEndpoints,
+    access_token: &str,
+) -> Result<GithubUserProfile, AuthError> {
     println!("Auth: 正在使用 token 获取 GitHub profile: Bearer ***"); // 不要记录 token
 
     let response = client
-        .get("https://api.github.com/user")
+        .get(endpoints.user_url())
         .header(AUTHORIZATION, format!("Bearer {}", access_token)) // 使用 Bearer token 认证
-        .header(USER_AGENT, "Tauri GitHub Auth (Rust)")
         .send()
         .await?;
 
@@ -808,6 +1279,9 @@ async fn fetch_github_user_profile(access_token: &str) -> Result<GithubUserProfi
             .map_err(|e| AuthError::ParseError(format!("解析 GitHub 用户 profile 失败: {}", e)))?;
         println!("Auth: 用户 profile 为 {} 获取成功。", profile.login);
         Ok(profile)
+    } else if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        eprintln!("Auth: GitHub 拒绝了 access token (401)。");
+        Err(AuthError::TokenRejected)
     } else {
         let status = response.status();
         let error_text = response
@@ -821,16 +1295,18 @@ async fn fetch_github_user_profile(access_token: &str) -> Result<GithubUserProfi
         )))
     }
 }
-// 将获取到的 GitHub profile 发送到你的后端 worker/API
-async fn sync_user_profile_to_backend(profile: &GithubUserProfile) -> Result<(), AuthError> {
+// 将获取到的 GitHub profile 发送到你的后端 worker/API；每次重试前调用 on_retry
+async fn sync_user_profile_to_backend(
+    client: &reqwest::Client,
+    worker: &WorkerEndpoint,
+    profile: &GithubUserProfile,
+    on_retry: impl FnMut(&RetryAttempt),
+) -> Result<(), AuthError> {
     println!("Auth: 尝试为用户 ID {} 进行后端同步", profile.id);
-    let client = reqwest::Client::new();
     let payload = BackendSyncPayload { profile };
 
-    // 使用访问器获取后端 API 的编译时嵌入值
-    let temp_url = get_worker_api_url();
-    let worker_api_url = format!("{}/sync-user", temp_url); // 假设后端同步端点是 /sync-user
-    let worker_api_key = get_worker_api_key();
+    let worker_api_url = worker.url("sync-user"); // 后端同步端点是 /sync-user
+    let worker_api_key = &worker.api_key;
 
     println!("Auth: 同步到后端 URL: {}", worker_api_url);
     let key_len = worker_api_key.len();
@@ -841,14 +1317,21 @@ async fn sync_user_profile_to_backend(profile: &GithubUserProfile) -> Result<(),
     };
     println!("Auth: 使用后端 API Key 进行同步: {}", masked_key);
 
-    let response = client
-        .post(worker_api_url)
-        .header(AUTHORIZATION, format!("Bearer {}", worker_api_key))
-        .header(CONTENT_TYPE, "application/json")
-        .header(USER_AGENT, "Tauri Backend Sync (Rust)")
-        .json(&payload)
-        .send()
-        .await?;
+    // 同步是 upsert，可以安全重试
+    let response = send_with_retry(
+        "sync_user_profile_to_backend",
+        &RetryPolicy::default(),
+        true,
+        || {
+            client
+                .post(&worker_api_url)
+                .header(AUTHORIZATION, format!("Bearer {}", worker_api_key))
+                .header(CONTENT_TYPE, "application/json")
+                .json(&payload)
+        },
+        on_retry,
+    )
+    .await?;
 
     let status = response.status();
     println!("Auth: 后端同步响应状态: {}", status);
@@ -889,14 +1372,32 @@ async fn sync_user_profile_to_backend(profile: &GithubUserProfile) -> Result<(),
 #[cfg(test)]
 mod tests {
     use super::*; // 导入父模块 (auth.rs) 中的项
+    use crate::config::{parse_env_vars, validate, AppConfig, ConfigIssue, ConfigParseError}; // 解析与校验逻辑位于 config 模块
+    use crate::identity::GithubProvider;
+    use crate::mock_server::{
+        MockResponse, MockServer, MOCK_ACCESS_TOKEN, MOCK_LOGIN, MOCK_USER_ID,
+    }; // 本地 mock worker / GitHub
+    use serde_json::json;
+
+    // 解析并校验 .env 内容：语法错误直接 panic，校验问题会一次性全部返回
+    fn parse_env_content(content: &str) -> Result<AppConfig, Vec<ConfigIssue>> {
+        let vars = parse_env_vars(content).expect(".env 语法应当有效");
+        let (config, issues) = validate(&vars);
+        if issues.is_empty() {
+            Ok(config)
+        } else {
+            Err(issues)
+        }
+    }
 
-    // 用于测试的辅助函数，创建预期的 EnvConfig
-    fn create_expected_config(id: &str, secret: &str, url: &str, key: &str) -> EnvConfig {
-        EnvConfig {
-            github_client_id: id.to_string(),
-            github_client_secret: secret.to_string(),
-            worker_api_url: url.to_string(),
-            worker_api_key: key.to_string(),
+    // 用于测试的辅助函数，创建预期的 AppConfig
+    fn create_expected_config(id: &str, secret: &str, url: &str, key: &str) -> AppConfig {
+        AppConfig {
+            github_client_id: Some(id.to_string()),
+            github_client_secret: Some(secret.to_string()),
+            worker_api_url: Some(Url::parse(url).unwrap()),
+            worker_api_key: Some(key.to_string()),
+            ..AppConfig::default()
         }
     }
 
@@ -976,48 +1477,50 @@ GITHUB_CLIENT_SECRET=secretABC
 WORKER_API_URL=http://localhost:8787
 WORKER_API_KEY=workerkeyXYZ
         ";
-        match parse_env_content(content) {
-            Err(ConfigParseError::MissingKey(key)) => {
-                assert_eq!(key, "GITHUB_CLIENT_ID", "错误的缺失键报告")
-            }
-            Ok(_) => panic!("本应因缺少键而失败"),
-            Err(e) => panic!("未预期的错误类型: {:?}", e),
-        }
+        // GitHub 登录是可选的，但 Client ID 与 Secret 必须成对出现
+        assert_eq!(
+            parse_env_content(content),
+            Err(vec![ConfigIssue::RequiredWith {
+                key: "GITHUB_CLIENT_ID".to_string(),
+                other: "GITHUB_CLIENT_SECRET".to_string(),
+            }]),
+            "错误的缺失键报告"
+        );
     }
 
     #[test]
     fn test_parse_empty_value_for_required_key() {
         let content = "
-GITHUB_CLIENT_ID=
+GITHUB_CLIENT_ID=id123
 GITHUB_CLIENT_SECRET=secretABC
 WORKER_API_URL=http://localhost:8787
-WORKER_API_KEY=workerkeyXYZ
+WORKER_API_KEY=
         ";
-        match parse_env_content(content) {
-            Err(ConfigParseError::EmptyValue(key)) => {
-                assert_eq!(key, "GITHUB_CLIENT_ID", "错误的空值键报告")
-            }
-            Ok(_) => panic!("本应因空值而失败"),
-            Err(e) => panic!("未预期的错误类型: {:?}", e),
-        }
+        assert_eq!(
+            parse_env_content(content),
+            Err(vec![ConfigIssue::Missing {
+                key: "WORKER_API_KEY".to_string()
+            }]),
+            "错误的空值键报告"
+        );
     }
 
     #[test]
     fn test_parse_empty_value_with_quotes_for_required_key() {
         let content = r#"
-GITHUB_CLIENT_ID="" 
+GITHUB_CLIENT_ID=id123
 GITHUB_CLIENT_SECRET=secretABC
 WORKER_API_URL=http://localhost:8787
-WORKER_API_KEY=workerkeyXYZ
+WORKER_API_KEY="" 
         "#;
-        // 当前逻辑会剥离引号，留下空字符串，然后空值检查失败。
-        match parse_env_content(content) {
-            Err(ConfigParseError::EmptyValue(key)) => {
-                assert_eq!(key, "GITHUB_CLIENT_ID", "带引号的空值处理不当")
-            }
-            Ok(_) => panic!("本应因剥离引号后值为空而失败"),
-            Err(e) => panic!("未预期的错误类型: {:?}", e),
-        }
+        // 剥离引号后留下空字符串，视为缺失。
+        assert_eq!(
+            parse_env_content(content),
+            Err(vec![ConfigIssue::Missing {
+                key: "WORKER_API_KEY".to_string()
+            }]),
+            "带引号的空值处理不当"
+        );
     }
 
     #[test]
@@ -1028,30 +1531,40 @@ GITHUB_CLIENT_SECRET=secretABC
 WORKER_API_URL=http://localhost:8787
 WORKER_API_KEY=workerkeyXYZ
         ";
-        match parse_env_content(content) {
-            Err(ConfigParseError::MalformedLine(line_num, line_content)) => {
+        match parse_env_vars(content) {
+            Err(ConfigParseError::MalformedLine {
+                line: line_num,
+                column,
+                content: line_content,
+                ..
+            }) => {
                 assert_eq!(line_num, 2, "错误的行号报告"); // 行号在错误中是1开始的
+                assert_eq!(column, 18, "错误的列号报告"); // 指向缺少 '=' 的位置
                 assert_eq!(
                     line_content, "GITHUB_CLIENT_ID id123_no_equals_sign",
                     "错误的行内容报告"
                 );
             }
             Ok(_) => panic!("本应因格式错误的行而失败"),
-            Err(e) => panic!("未预期的错误类型: {:?}", e),
         }
     }
 
     #[test]
     fn test_parse_empty_content() {
         let content = "";
-        // 期望对它检查的第一个键报告 MissingKey
-        match parse_env_content(content) {
-            Err(ConfigParseError::MissingKey(key)) => {
-                assert_eq!(key, "GITHUB_CLIENT_ID", "空内容应报告缺失键")
-            }
-            Ok(_) => panic!("本应因缺少键而失败"),
-            Err(e) => panic!("未预期的错误类型: {:?}", e),
-        }
+        // 期望一次性报告所有缺失的必填键，而不是只报告第一个
+        assert_eq!(
+            parse_env_content(content),
+            Err(vec![
+                ConfigIssue::Missing {
+                    key: "WORKER_API_URL".to_string()
+                },
+                ConfigIssue::Missing {
+                    key: "WORKER_API_KEY".to_string()
+                },
+            ]),
+            "空内容应报告所有缺失键"
+        );
     }
 
     #[test]
@@ -1061,48 +1574,427 @@ WORKER_API_KEY=workerkeyXYZ
 \n   \n
 # 另一个注释
         ";
-        match parse_env_content(content) {
-            Err(ConfigParseError::MissingKey(key)) => {
-                assert_eq!(key, "GITHUB_CLIENT_ID", "只有注释和空行应报告缺失键")
-            }
-            Ok(_) => panic!("本应因缺少键而失败"),
-            Err(e) => panic!("未预期的错误类型: {:?}", e),
+        let issues = parse_env_content(content).expect_err("本应因缺少键而失败");
+        assert_eq!(issues.len(), 2, "只有注释和空行应报告所有缺失键");
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_worker_url() {
+        let content = "
+WORKER_API_URL=htps://worker.example
+WORKER_API_KEY=workerkeyXYZ
+        ";
+        assert!(matches!(
+            &parse_env_content(content).unwrap_err()[..],
+            [ConfigIssue::InvalidUrl { key, .. }] if key == "WORKER_API_URL"
+        ));
+    }
+
+    #[test]
+    fn test_pkce_challenge_is_s256_of_verifier() {
+        let pkce =
+            PkceChallenge::from_verifier("dBjftJeZ4CVP-mB92K27uhbUjr0PqaNmTQ6amDmJ9q4".to_string());
+        assert_eq!(
+            pkce.code_challenge,
+            "8Y_iYjXlg3iR1FAYZDW2OjqwYX4hppD87CrPverHJ1E"
+        );
+
+        let generated = PkceChallenge::generate();
+        assert_eq!(generated.code_verifier.len(), PKCE_VERIFIER_LEN);
+        assert!(generated
+            .code_verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(generated.code_challenge.len(), 43); // 32 字节摘要，不带填充
+        assert_ne!(generated, PkceChallenge::generate());
+    }
+
+    #[test]
+    fn test_pending_auth_hands_verifier_to_task() {
+        let (tx, mut rx) = oneshot::channel();
+        let pkce = PkceChallenge::generate();
+        let pending = PendingAuth {
+            sender: tx,
+            pkce: pkce.clone(),
+            provider: GITHUB_PROVIDER_ID.to_string(),
+        };
+        assert!(pending.complete("the_code".to_string()));
+        let auth_code = rx.try_recv().unwrap().unwrap();
+        assert_eq!(auth_code.code, "the_code");
+        assert_eq!(auth_code.code_verifier, pkce.code_verifier);
+
+        // 登录任务已经结束 (receiver 被丢弃)
+        let (tx, rx) = oneshot::channel();
+        drop(rx);
+        let pending = PendingAuth {
+            sender: tx,
+            pkce,
+            provider: GITHUB_PROVIDER_ID.to_string(),
+        };
+        assert!(!pending.complete("late".to_string()));
+    }
+
+    #[test]
+    fn test_pending_error_events_follow_the_waiting_providers() {
+        let pending_state = PendingAuthState::default();
+        assert!(pending_error_events(&pending_state).is_empty());
+
+        let mut receivers = Vec::new();
+        for (state, provider) in [("s1", "oidc"), ("s2", GITHUB_PROVIDER_ID), ("s3", "oidc")] {
+            let (tx, rx) = oneshot::channel();
+            receivers.push(rx);
+            let pending = PendingAuth {
+                sender: tx,
+                pkce: PkceChallenge::generate(),
+                provider: provider.to_string(),
+            };
+            pending_state
+                .lock()
+                .unwrap()
+                .insert(state.to_string(), pending);
+        }
+        assert_eq!(
+            pending_error_events(&pending_state),
+            ["github_auth_error", "oidc_auth_error"]
+        );
+        let removed = pending_state.lock().unwrap().remove("s1").unwrap();
+        assert_eq!(removed.error_event(), "oidc_auth_error");
+    }
+
+    #[test]
+    fn test_parse_token_response() {
+        let token =
+            parse_token_response(r#"{"access_token":"gho_abc","token_type":"bearer"}"#).unwrap();
+        assert_eq!(token.access_token, "gho_abc");
+
+        // verifier 不匹配时 GitHub 仍返回 200
+        let rejected = parse_token_response(
+            r#"{"error":"bad_verification_code","error_description":"The code passed is incorrect or expired."}"#,
+        );
+        assert!(
+            matches!(rejected, Err(AuthError::GitHubError(message)) if message.contains("bad_verification_code"))
+        );
+        assert!(matches!(
+            parse_token_response(r#"{"access_token":""}"#),
+            Err(AuthError::GitHubError(_))
+        ));
+    }
+
+    #[test]
+    fn test_github_authorize_url() {
+        let params = AuthorizeParams {
+            redirect_uri: "revision://github/callback",
+            state: "state123",
+            code_challenge: "challenge",
+            nonce: "unused",
+        };
+        let mut github = github_config("https://ghe.example", "", TokenExchange::Worker);
+        github.client_id = "client123".to_string();
+        let url = Url::parse(&github_authorize_url(&github, &params)).unwrap();
+        assert_eq!(url.path(), "/login/oauth/authorize");
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["redirect_uri"], "revision://github/callback");
+        assert_eq!(query["scope"], GITHUB_SCOPE);
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(!query.contains_key("nonce"));
+    }
+
+    #[tokio::test]
+    async fn test_github_enterprise_endpoints_from_config() {
+        let github = MockServer::github().await;
+        let profile = serde_json::json!({
+            "login": MOCK_LOGIN, "id": MOCK_USER_ID, "name": null, "avatar_url": "", "email": null
+        });
+        github.route("/api/v3/user", MockResponse::ok(profile));
+        let config = AppConfig {
+            github_web_url: Some(Url::parse(github.url()).unwrap()),
+            ..Default::default()
+        };
+
+        let endpoints = GithubEndpoints::from_config(&config);
+        assert_eq!(endpoints.web_url, github.url());
+        assert_eq!(
+            endpoints.access_token_url(),
+            format!("{}/login/oauth/access_token", github.url())
+        );
+        let client = reqwest::Client::new();
+        let user = fetch_github_user_profile(&client, &endpoints, MOCK_ACCESS_TOKEN)
+            .await
+            .unwrap();
+        assert_eq!(user.login, MOCK_LOGIN);
+        assert!(github.requests("/user").is_empty());
+
+        let endpoints = GithubEndpoints::from_config(&AppConfig::default());
+        assert_eq!(
+            endpoints.authorize_url(),
+            "https://github.com/login/oauth/authorize"
+        );
+        assert_eq!(endpoints.user_url(), "https://api.github.com/user");
+    }
+
+    #[test]
+    fn test_login_url_origins() {
+        assert!(login_url_origins(&AppConfig::default()).is_empty());
+
+        let config = AppConfig {
+            github_web_url: Some(Url::parse("https://ghe.example.com/").unwrap()),
+            oidc_issuer: Some("https://gitlab.example.com:8443/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            login_url_origins(&config),
+            ["https://ghe.example.com", "https://gitlab.example.com:8443"]
+        );
+
+        let config = AppConfig {
+            github_web_url: Some(Url::parse("https://github.com").unwrap()),
+            oidc_issuer: Some("https://sso.example.com/realms/staff".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(login_url_origins(&config), ["https://sso.example.com"]);
+
+        // GHES and its OIDC issuer on the same host
+        let config = AppConfig {
+            github_web_url: Some(Url::parse("https://ghe.example.com/").unwrap()),
+            oidc_issuer: Some("https://ghe.example.com/oidc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(login_url_origins(&config), ["https://ghe.example.com"]);
+    }
+
+    #[test]
+    fn test_github_profile_round_trips_through_user_profile() {
+        let github: GithubUserProfile = serde_json::from_value(serde_json::json!({
+            "login": "octocat", "id": 42, "name": null,
+            "avatar_url": "https://avatars.example/42", "email": null
+        }))
+        .unwrap();
+        let profile = UserProfile::from(github);
+        assert_eq!(profile.provider, GITHUB_PROVIDER_ID);
+        assert_eq!(profile.id, "42");
+        assert_eq!(GithubUserProfile::try_from(&profile).unwrap().id, 42);
+    }
+
+    // 指向给定地址 (通常是 mock 服务器) 的 GitHub 登录配置
+    fn github_config(
+        github_url: &str,
+        worker_url: &str,
+        token_exchange: TokenExchange,
+    ) -> GithubAuthConfig {
+        GithubAuthConfig {
+            client_id: "mock-client".to_string(),
+            client_secret: "mock-secret".to_string(),
+            token_exchange,
+            endpoints: GithubEndpoints {
+                web_url: github_url.to_string(),
+                api_url: github_url.to_string(),
+            },
+            worker: WorkerEndpoint {
+                base_url: worker_url.to_string(),
+                api_key: "mock-worker-key".to_string(),
+            },
+        }
+    }
+
+    fn mock_auth_code() -> AuthCode {
+        AuthCode {
+            code: "mock-code".to_string(),
+            code_verifier: "mock-verifier".to_string(),
+        }
+    }
+
+    // 完整的浏览器登录流程：授权 URL -> worker 兑换 code -> 获取 profile -> 同步到后端
+    #[tokio::test]
+    async fn test_login_flow_against_mock_servers() {
+        let github = MockServer::github().await;
+        let worker = MockServer::worker().await;
+        let config = github_config(github.url(), worker.url(), TokenExchange::Worker);
+        let provider = GithubProvider::new(config.clone());
+        let client = reqwest::Client::new();
+
+        let params = AuthorizeParams {
+            redirect_uri: get_redirect_uri(),
+            state: "state123",
+            code_challenge: "challenge",
+            nonce: "unused",
+        };
+        let auth_url = provider.authorize_url(&client, &params).await.unwrap();
+        assert!(auth_url.starts_with(&format!("{}/login/oauth/authorize?", github.url())));
+
+        let tokens = provider
+            .exchange_code(&client, &mock_auth_code(), get_redirect_uri(), "unused")
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, MOCK_ACCESS_TOKEN);
+        let exchange = &worker.requests("/auth/github/token")[0];
+        assert_eq!(
+            exchange.header("authorization"),
+            Some("Bearer mock-worker-key")
+        );
+        assert_eq!(exchange.json()["code_verifier"], "mock-verifier");
+        // worker 模式下桌面端不直接联系 GitHub 的 token 端点
+        assert!(github.requests("/login/oauth/access_token").is_empty());
+
+        let profile = provider
+            .fetch_profile(&client, &tokens.access_token)
+            .await
+            .unwrap();
+        assert_eq!(profile.login, MOCK_LOGIN);
+        assert_eq!(
+            github.requests("/user")[0].header("authorization"),
+            Some(format!("Bearer {}", MOCK_ACCESS_TOKEN).as_str())
+        );
+
+        let github_profile = GithubUserProfile::try_from(&profile).unwrap();
+        sync_user_profile_to_backend(&client, &config.worker, &github_profile, |_| {})
+            .await
+            .unwrap();
+        let sync = &worker.requests("/sync-user")[0];
+        assert_eq!(sync.json()["profile"]["id"], MOCK_USER_ID);
+    }
+
+    #[tokio::test]
+    async fn test_direct_exchange_and_github_errors() {
+        let github = MockServer::github().await;
+        let config = github_config(github.url(), "", TokenExchange::Direct);
+        let client = reqwest::Client::new();
+
+        let token = exchange_code_for_token(&client, &config, &mock_auth_code())
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, MOCK_ACCESS_TOKEN);
+        let form = github.requests("/login/oauth/access_token")[0].form();
+        assert_eq!(form["client_secret"], "mock-secret");
+        assert_eq!(form["code_verifier"], "mock-verifier");
+
+        // GitHub 以 200 返回的错误、被截断的 JSON 以及服务器错误
+        github
+            .enqueue(
+                "/login/oauth/access_token",
+                MockResponse::ok(json!({ "error": "bad_verification_code" })),
+            )
+            .enqueue("/login/oauth/access_token", MockResponse::malformed(200))
+            .enqueue(
+                "/login/oauth/access_token",
+                MockResponse::text(502, "text/html", "<html>Bad Gateway</html>"),
+            );
+        for expected in ["GitHubError", "ParseError", "GitHubError"] {
+            let err = exchange_code_for_token(&client, &config, &mock_auth_code())
+                .await
+                .unwrap_err();
+            assert!(format!("{:?}", err).starts_with(expected), "{:?}", err);
         }
+
+        github.enqueue("/user", MockResponse::text(401, "application/json", "{}"));
+        assert!(matches!(
+            fetch_github_user_profile(&client, &config.endpoints, "revoked").await,
+            Err(AuthError::TokenRejected)
+        ));
+    }
+
+    // worker 兑换端点未配置时不发出请求
+    #[tokio::test]
+    async fn test_worker_exchange_requires_worker_url() {
+        let config = github_config("http://127.0.0.1:9", "", TokenExchange::Worker);
+        let err = exchange_code_for_token(&reqwest::Client::new(), &config, &mock_auth_code())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AuthError::InternalError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_sync_retries_then_reports_backend_failure() {
+        let worker = MockServer::worker().await;
+        worker
+            .enqueue(
+                "/sync-user",
+                MockResponse::text(503, "text/plain", "busy").with_header("retry-after", "0"),
+            )
+            .enqueue(
+                "/sync-user",
+                MockResponse::ok(json!({ "success": false, "message": "user banned" })),
+            );
+        let config = github_config("", worker.url(), TokenExchange::Worker);
+        let profile: GithubUserProfile = serde_json::from_value(json!({
+            "login": MOCK_LOGIN, "id": MOCK_USER_ID, "name": null,
+            "avatar_url": "", "email": null
+        }))
+        .unwrap();
+
+        let mut retries = 0;
+        let err =
+            sync_user_profile_to_backend(&reqwest::Client::new(), &config.worker, &profile, |_| {
+                retries += 1
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, AuthError::BackendSyncFailed(message) if message.contains("user banned"))
+        );
+        assert_eq!(retries, 1);
+        assert_eq!(worker.requests("/sync-user").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_device_flow_against_mock_github() {
+        let github = MockServer::github().await;
+        github.enqueue(
+            "/login/oauth/access_token",
+            MockResponse::ok(json!({ "error": "authorization_pending" })),
+        );
+        let config = github_config(github.url(), "", TokenExchange::Worker);
+        let client = reqwest::Client::new();
+
+        let device_code = request_device_code(&client, &config).await.unwrap();
+        assert_eq!(device_code.user_code, "ABCD-1234");
+        let token = poll_device_token(&client, &config, &device_code, 0, || false)
+            .await
+            .unwrap();
+        assert_eq!(token, MOCK_ACCESS_TOKEN);
+
+        let polls = github.requests("/login/oauth/access_token");
+        assert_eq!(polls.len(), 2);
+        assert_eq!(polls[1].form()["device_code"], "mock-device-code");
+    }
+
+    fn token_response(json: &str) -> GithubTokenResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_device_poll_step() {
+        assert!(matches!(
+            device_poll_step(token_response(r#"{"error":"authorization_pending"}"#), 5),
+            DevicePoll::Wait(5)
+        ));
+        // slow_down：使用服务器返回的间隔，缺失时自行加 5 秒
+        assert!(matches!(
+            device_poll_step(token_response(r#"{"error":"slow_down","interval":15}"#), 5),
+            DevicePoll::Wait(15)
+        ));
+        assert!(matches!(
+            device_poll_step(token_response(r#"{"error":"slow_down"}"#), 5),
+            DevicePoll::Wait(10)
+        ));
+        assert!(matches!(
+            device_poll_step(token_response(r#"{"error":"expired_token"}"#), 5),
+            DevicePoll::Failed(AuthError::DeviceCodeExpired)
+        ));
+        assert!(matches!(
+            device_poll_step(token_response(r#"{"error":"access_denied"}"#), 5),
+            DevicePoll::Failed(AuthError::Cancelled)
+        ));
+        assert!(matches!(
+            device_poll_step(token_response(r#"{"access_token":"gho_abc"}"#), 5),
+            DevicePoll::Token(token) if token == "gho_abc"
+        ));
     }
 
     #[test]
     fn test_config_accessor_functions_after_successful_parse() {
-        // 这个测试依赖于 CONFIG 静态变量的初始化。
-        // 它会使用实际的 .env.development 或 .env.production 文件。
-        // 因此，请确保你的 .env.development (如果运行 `cargo test`) 具有有效值。
-        // 这更像是一个针对访问器与静态 CONFIG 的集成测试，但有总比没有好。
-
-        // 为了使这个测试更独立于实际的 .env 文件 (尽管 CONFIG 编译时仍需要它们)，
-        // 我们实际上是测试访问器简单地返回 `CONFIG` 所持有的内容。
-
-        // 当这些访问器函数首次被调用时，CONFIG 静态变量会基于你的实际 .env 文件进行初始化。
-        // 我们无法轻易地为 *这个特定的测试函数* 更改 CONFIG 加载的内容。
-        // 我们 *能够* 验证的是，如果 CONFIG 初始化了，访问器会返回其字段。
-        // 这是一个简单的测试，因为访问器本身很简单。
-
-        // 如果 .env.development 和 .env.production 不存在，或者为了控制测试值，可以创建虚拟文件。
-        // 对于 CI，你可能需要在运行测试前创建这些文件。
-        // 在这个例子中，我们假设它们存在且可解析。
-
-        // 触发 CONFIG 的初始化 (如果尚未完成)
-        // 如果 `parse_env_content` 失败 (例如，.env 文件缺失或格式错误)，这里会 panic。
-        // 为了使测试更健壮，实际项目中你可能需要确保测试环境中有有效的 .env 文件，
-        // 或者使用更复杂的 mocking/setup。
-        if get_github_client_id().is_empty()
-            && get_github_client_secret().is_empty()
-            && get_worker_api_url().is_empty()
-            && get_worker_api_key().is_empty()
-        {
-            // 这表明 CONFIG 可能由于某种原因未能成功加载任何值
-            // (例如，真实的 .env 文件内容为空或者 include_str! 失败但编译通过)
-            // 这种情况下，下面的断言会失败，这其实是期望的行为。
-            // 另一种选择是，如果CONFIG初始化失败，Lazy会panic，测试根本不会执行到这里。
-        }
+        // 这个测试依赖于 config 模块的全局配置。测试中不会调用 config::init，
+        // 因此只有环境变量和嵌入的 .env.development (或 .env.production) 生效。
+        // 请确保运行 `cargo test` 时这些文件具有有效值。
 
         // 现在检查访问器
         // 如果你的 .env 文件能被 `parse_env_content` 解析，并且包含这些键的非空值，
@@ -0,0 +1,302 @@
+// src-tauri/src/capture.rs
+
+// --- Dependencies ---
+use crate::image_pipeline::{check_file_size, validate_image, ImageError};
+use image::{DynamicImage, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+use thiserror::Error;
+
+// Same directory the screenshots plugin writes to, so the asset protocol scope covers it
+pub const SCREENSHOT_DIR_NAME: &str = "tauri-plugin-screenshots";
+
+// --- Error handling ---
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+pub enum CaptureError {
+    #[error("{0}")]
+    Image(ImageError),
+    #[error("Selected area is outside the screenshot: {0}")]
+    InvalidRegion(String),
+    #[error("Failed to save cropped screenshot: {0}")]
+    WriteFailed(String),
+    #[error("Failed to resolve screenshot directory: {0}")]
+    PathError(String),
+}
+
+impl From<ImageError> for CaptureError {
+    fn from(err: ImageError) -> Self {
+        CaptureError::Image(err)
+    }
+}
+
+// --- Geometry ---
+// A rectangle in screen coordinates. Origins may be negative on multi-monitor setups.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Pixel area of the captured image to keep, already clamped to the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Maps a selection made in the capture (in logical pixels when `scale_factor` is given)
+// onto image pixels, clipping whatever falls outside the image.
+pub fn region_to_crop(
+    image_width: u32,
+    image_height: u32,
+    region: ScreenRect,
+    scale_factor: f64,
+) -> Result<CropRect, CaptureError> {
+    if !scale_factor.is_finite() || scale_factor <= 0.0 {
+        return Err(CaptureError::InvalidRegion(format!(
+            "invalid scale factor {}",
+            scale_factor
+        )));
+    }
+    let scale = |v: f64| (v * scale_factor).round();
+    let left = scale(region.x as f64).max(0.0);
+    let top = scale(region.y as f64).max(0.0);
+    let right = scale(region.x as f64 + region.width as f64).min(image_width as f64);
+    let bottom = scale(region.y as f64 + region.height as f64).min(image_height as f64);
+
+    if right <= left || bottom <= top {
+        return Err(CaptureError::InvalidRegion(format!(
+            "{}x{} at ({}, {}) on a {}x{} image",
+            region.width, region.height, region.x, region.y, image_width, image_height
+        )));
+    }
+    Ok(CropRect {
+        x: left as u32,
+        y: top as u32,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+    })
+}
+
+// Converts a window's screen bounds into a selection relative to the captured monitor.
+// Both rectangles must use the same coordinate space; the returned scale factor maps
+// that space onto the pixels of the monitor capture.
+pub fn window_to_region(
+    monitor: ScreenRect,
+    window: ScreenRect,
+    image_width: u32,
+) -> Result<(ScreenRect, f64), CaptureError> {
+    if monitor.width == 0 || monitor.height == 0 {
+        return Err(CaptureError::InvalidRegion(
+            "monitor has no size".to_string(),
+        ));
+    }
+    let region = ScreenRect {
+        x: window.x - monitor.x,
+        y: window.y - monitor.y,
+        width: window.width,
+        height: window.height,
+    };
+    Ok((region, image_width as f64 / monitor.width as f64))
+}
+
+// Crops an encoded screenshot and returns it as PNG. Lossless on purpose: the query
+// pipeline shrinks and re-encodes it before upload.
+pub fn crop_image(
+    bytes: &[u8],
+    region: ScreenRect,
+    scale_factor: f64,
+) -> Result<Vec<u8>, CaptureError> {
+    let (format, width, height) = validate_image(bytes)?;
+    let crop = region_to_crop(width, height, region, scale_factor)?;
+    let decoded = ImageReader::with_format(Cursor::new(bytes), format)
+        .decode()
+        .map_err(ImageError::from)?;
+    let cropped = decoded.crop_imm(crop.x, crop.y, crop.width, crop.height);
+
+    let mut output = Vec::new();
+    DynamicImage::ImageRgba8(cropped.to_rgba8())
+        .write_to(&mut Cursor::new(&mut output), ImageFormat::Png)
+        .map_err(|e| ImageError::EncodeFailed(e.to_string()))?;
+    Ok(output)
+}
+
+fn screenshot_dir(app: &AppHandle) -> Result<PathBuf, CaptureError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(SCREENSHOT_DIR_NAME))
+        .map_err(|e| CaptureError::PathError(e.to_string()))
+}
+
+// Checks the size on disk before reading, so oversized captures are never loaded
+async fn read_capture(image_path: &str) -> Result<Vec<u8>, ImageError> {
+    let metadata = tokio::fs::metadata(image_path)
+        .await
+        .map_err(|e| ImageError::ReadFailed(format!("'{}': {}", image_path, e)))?;
+    check_file_size(metadata.len())?;
+    tokio::fs::read(image_path)
+        .await
+        .map_err(|e| ImageError::ReadFailed(format!("'{}': {}", image_path, e)))
+}
+
+// Reads the monitor capture, crops it and writes the result next to the other screenshots.
+// `select` picks the area from the capture's pixel width, once the image is validated.
+async fn crop_to_file(
+    app: &AppHandle,
+    image_path: &str,
+    prefix: &str,
+    select: impl FnOnce(u32) -> Result<(ScreenRect, f64), CaptureError> + Send + 'static,
+) -> Result<String, CaptureError> {
+    let source = read_capture(image_path).await?;
+    let (png, region) = tokio::task::spawn_blocking(move || {
+        let (_, width, _) = validate_image(&source)?;
+        let (region, scale_factor) = select(width)?;
+        crop_image(&source, region, scale_factor).map(|png| (png, region))
+    })
+    .await
+    .map_err(|e| ImageError::EncodeFailed(format!("crop task failed: {}", e)))??;
+
+    let dir = screenshot_dir(app)?;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| CaptureError::WriteFailed(e.to_string()))?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let output_path = dir.join(format!("{}-{}.png", prefix, millis));
+    tokio::fs::write(&output_path, &png)
+        .await
+        .map_err(|e| CaptureError::WriteFailed(e.to_string()))?;
+
+    let output_path = path_to_string(&output_path);
+    println!(
+        "[{}] Cropped '{}' to {}x{} at ({}, {}) -> {}",
+        prefix, image_path, region.width, region.height, region.x, region.y, output_path
+    );
+    Ok(output_path)
+}
+
+fn path_to_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+// --- Tauri Commands ---
+
+// Crops a monitor capture to a user-selected rectangle. `region` is relative to the
+// captured monitor; pass `scale_factor` when it is in logical (CSS) pixels.
+// Returns the path of the new image, usable as `image_path` for the query commands.
+#[tauri::command]
+pub async fn crop_screenshot_region(
+    image_path: String,
+    region: ScreenRect,
+    scale_factor: Option<f64>,
+    app: AppHandle,
+) -> Result<String, CaptureError> {
+    let scale_factor = scale_factor.unwrap_or(1.0);
+    crop_to_file(&app, &image_path, "crop_screenshot_region", move |_| {
+        Ok((region, scale_factor))
+    })
+    .await
+}
+
+// Crops a monitor capture to one window. `monitor` and `window` are screen bounds in
+// the same coordinate space, e.g. as reported by the screenshots plugin.
+#[tauri::command]
+pub async fn crop_screenshot_to_window(
+    image_path: String,
+    monitor: ScreenRect,
+    window: ScreenRect,
+    app: AppHandle,
+) -> Result<String, CaptureError> {
+    crop_to_file(
+        &app,
+        &image_path,
+        "crop_screenshot_to_window",
+        move |image_width| window_to_region(monitor, window, image_width),
+    )
+    .await
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, RgbaImage};
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> ScreenRect {
+        ScreenRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_region_to_crop_scales_and_clamps() {
+        // Logical selection on a 2x display
+        assert_eq!(
+            region_to_crop(2000, 1000, rect(10, 20, 100, 50), 2.0).unwrap(),
+            CropRect {
+                x: 20,
+                y: 40,
+                width: 200,
+                height: 100
+            }
+        );
+        // Selection hanging off the top-left corner is clipped
+        assert_eq!(
+            region_to_crop(100, 100, rect(-10, -10, 30, 30), 1.0).unwrap(),
+            CropRect {
+                x: 0,
+                y: 0,
+                width: 20,
+                height: 20
+            }
+        );
+        assert!(matches!(
+            region_to_crop(100, 100, rect(150, 0, 10, 10), 1.0),
+            Err(CaptureError::InvalidRegion(_))
+        ));
+        assert!(region_to_crop(100, 100, rect(0, 0, 0, 10), 1.0).is_err());
+    }
+
+    #[test]
+    fn test_window_to_region_on_secondary_monitor() {
+        // Monitor to the left of the primary one, captured at 2x
+        let monitor = rect(-1440, 0, 1440, 900);
+        let window = rect(-1000, 100, 400, 300);
+        let (region, scale) = window_to_region(monitor, window, 2880).unwrap();
+        assert_eq!(region, rect(440, 100, 400, 300));
+        assert_eq!(scale, 2.0);
+    }
+
+    #[test]
+    fn test_crop_image_returns_png_of_selected_area() {
+        let img = RgbaImage::from_fn(50, 40, |x, _| {
+            if x < 25 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            }
+        });
+        let mut source = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut source), ImageFormat::Png)
+            .unwrap();
+
+        let png = crop_image(&source, rect(30, 5, 10, 10), 1.0).unwrap();
+        let cropped = image::load_from_memory(&png).unwrap();
+        assert_eq!(cropped.dimensions(), (10, 10));
+        assert_eq!(cropped.get_pixel(0, 0), image::Rgba([0, 0, 255, 255]));
+    }
+}
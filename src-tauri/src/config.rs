@@ -0,0 +1,2043 @@
+// src-tauri/src/config.rs
+
+// --- Dependencies ---
+use crate::image_pipeline::OutputFormat;
+use crate::secrets::{SecretKey, SecretStore, SecretStoreState};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+use tauri::{AppHandle, Runtime};
+use thiserror::Error;
+use url::Url;
+
+// --- Configuration layers ---
+// Values are resolved per key, highest precedence first:
+//   1. environment variables (including a `.env` next to the app, loaded by dotenvy)
+//   2. the secret store (OS keyring or encrypted file), for secret keys only
+//   3. `config.env` in the app config dir
+//   4. the .env file embedded at build time, as a fallback for keys nothing else sets;
+//      release builds ignore secret keys in it
+//   5. schema defaults
+// The result is then validated against `CONFIG_SCHEMA` into a typed `AppConfig`.
+// Problems never panic: they are reported through `ConfigStatus`, and a value that is
+// missing or invalid is simply absent from `AppConfig`.
+
+pub const CONFIG_FILE_NAME: &str = "config.env";
+
+pub const GITHUB_CLIENT_ID: &str = "GITHUB_CLIENT_ID";
+pub const GITHUB_CLIENT_SECRET: &str = "GITHUB_CLIENT_SECRET";
+pub const GITHUB_TOKEN_EXCHANGE: &str = "GITHUB_TOKEN_EXCHANGE";
+pub const GITHUB_WEB_URL: &str = "GITHUB_WEB_URL";
+pub const GITHUB_API_URL: &str = "GITHUB_API_URL";
+pub const WORKER_API_URL: &str = "WORKER_API_URL";
+pub const WORKER_API_KEY: &str = "WORKER_API_KEY";
+pub const OIDC_ISSUER: &str = "OIDC_ISSUER";
+pub const OIDC_CLIENT_ID: &str = "OIDC_CLIENT_ID";
+pub const OIDC_SCOPES: &str = "OIDC_SCOPES";
+pub const OIDC_DISPLAY_NAME: &str = "OIDC_DISPLAY_NAME";
+pub const QUERY_PROFILE: &str = "QUERY_PROFILE";
+pub const OPENAI_BASE_URL: &str = "OPENAI_BASE_URL";
+pub const OPENAI_API_KEY: &str = "OPENAI_API_KEY";
+pub const OPENAI_MODEL: &str = "OPENAI_MODEL";
+pub const OPENAI_STREAM_USAGE: &str = "OPENAI_STREAM_USAGE";
+pub const ANTHROPIC_BASE_URL: &str = "ANTHROPIC_BASE_URL";
+pub const ANTHROPIC_API_KEY: &str = "ANTHROPIC_API_KEY";
+pub const ANTHROPIC_MODEL: &str = "ANTHROPIC_MODEL";
+pub const OLLAMA_BASE_URL: &str = "OLLAMA_BASE_URL";
+pub const OLLAMA_MODEL: &str = "OLLAMA_MODEL";
+pub const PIPELINE_VISION_PROFILE: &str = "PIPELINE_VISION_PROFILE";
+pub const PIPELINE_VISION_MODEL: &str = "PIPELINE_VISION_MODEL";
+pub const PIPELINE_VISION_PROMPT: &str = "PIPELINE_VISION_PROMPT";
+pub const PIPELINE_REASONING_PROFILE: &str = "PIPELINE_REASONING_PROFILE";
+pub const PIPELINE_REASONING_MODEL: &str = "PIPELINE_REASONING_MODEL";
+pub const PIPELINE_REASONING_PROMPT: &str = "PIPELINE_REASONING_PROMPT";
+pub const QUERY_HISTORY_BYTE_BUDGET: &str = "QUERY_HISTORY_BYTE_BUDGET";
+pub const QUERY_IMAGE_MAX_DIMENSION: &str = "QUERY_IMAGE_MAX_DIMENSION";
+pub const QUERY_IMAGE_QUALITY: &str = "QUERY_IMAGE_QUALITY";
+pub const QUERY_IMAGE_FORMAT: &str = "QUERY_IMAGE_FORMAT";
+pub const QUERY_MAX_IMAGES: &str = "QUERY_MAX_IMAGES";
+pub const QUERY_MAX_IMAGE_PAYLOAD_BYTES: &str = "QUERY_MAX_IMAGE_PAYLOAD_BYTES";
+pub const HTTP_CONNECT_TIMEOUT_MS: &str = "HTTP_CONNECT_TIMEOUT_MS";
+pub const HTTP_READ_TIMEOUT_MS: &str = "HTTP_READ_TIMEOUT_MS";
+pub const HTTP_TOTAL_TIMEOUT_MS: &str = "HTTP_TOTAL_TIMEOUT_MS";
+pub const HTTP_MAX_RETRIES: &str = "HTTP_MAX_RETRIES";
+pub const HTTP_RETRY_BASE_DELAY_MS: &str = "HTTP_RETRY_BASE_DELAY_MS";
+pub const HTTP_RETRY_MAX_DELAY_MS: &str = "HTTP_RETRY_MAX_DELAY_MS";
+
+const GITHUB_DOT_COM_WEB_URL: &str = "https://github.com";
+const GITHUB_DOT_COM_API_URL: &str = "https://api.github.com";
+// Where GitHub Enterprise Server serves its REST API, relative to the web URL
+const GHES_API_PATH: &str = "api/v3";
+
+// Everything in these files ends up inside the binary, so release builds should keep
+// secrets out of `.env.production` and ship them via the config file or the environment.
+const EMBEDDED_ENV: &str = if cfg!(debug_assertions) {
+    include_str!("../../.env.development")
+} else {
+    RELEASE_EMBEDDED_ENV
+};
+// `.env.production` with the secret keys removed by build.rs
+const RELEASE_EMBEDDED_ENV: &str = include_str!(concat!(env!("OUT_DIR"), "/env.production"));
+
+// --- Schema ---
+pub struct ConfigField {
+    pub key: &'static str,
+    pub required: bool,
+    pub secret: bool,                  // Also looked up in the secret store
+    pub default: Option<&'static str>, // Only non-secret values belong here
+}
+
+// Keys read from the layers; anything else in the files is ignored.
+// GitHub login is optional. The client secret is only needed when the app exchanges
+// login codes itself; by default the worker does it and the app only knows the client ID.
+// The GitHub URLs are only set for GitHub Enterprise Server; github.com is the default.
+// The OIDC provider (e.g. GitLab) is optional too; its issuer and client ID go together.
+// Queries go through the worker unless another profile is chosen, which talks to an
+// OpenAI-compatible API, Anthropic or Ollama directly and then needs that provider's
+// settings instead. Each provider is offered once its endpoint (or key) is set.
+// The `pipeline` profile runs the worker's two steps in the app: each stage names a
+// direct provider, and may pick a model and a prompt template file of its own.
+// The remaining keys tune queries and HTTP; their defaults match the `DEFAULT_*`
+// constants of `conversation`, `image_pipeline` and `http`. The HTTP timeouts are read
+// once, when the shared client is built at startup.
+pub const CONFIG_SCHEMA: [ConfigField; 39] = [
+    ConfigField {
+        key: GITHUB_CLIENT_ID,
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: GITHUB_CLIENT_SECRET,
+        required: false,
+        secret: true,
+        default: None,
+    },
+    ConfigField {
+        key: GITHUB_TOKEN_EXCHANGE, // `worker` or `direct`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: GITHUB_WEB_URL, // e.g. `https://ghe.example.com`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: GITHUB_API_URL, // e.g. `https://ghe.example.com/api/v3`; derived from the web URL
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: WORKER_API_URL,
+        required: true,
+        secret: false,
+        default: if cfg!(debug_assertions) {
+            Some("http://127.0.0.1:8787") // `wrangler dev`
+        } else {
+            None
+        },
+    },
+    ConfigField {
+        key: WORKER_API_KEY,
+        required: true,
+        secret: true,
+        default: None,
+    },
+    ConfigField {
+        key: OIDC_ISSUER, // e.g. `https://gitlab.com`; discovery is fetched from it
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: OIDC_CLIENT_ID, // A public client; the app uses PKCE and holds no secret
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: OIDC_SCOPES,
+        required: false,
+        secret: false,
+        default: Some("openid profile email"),
+    },
+    ConfigField {
+        key: OIDC_DISPLAY_NAME,
+        required: false,
+        secret: false,
+        default: Some("OpenID Connect"),
+    },
+    ConfigField {
+        key: QUERY_PROFILE, // `worker`, `openai`, `anthropic`, `ollama` or `pipeline`
+        required: false,
+        secret: false,
+        default: Some("worker"),
+    },
+    ConfigField {
+        key: OPENAI_BASE_URL, // e.g. `https://api.openai.com/v1` or `http://127.0.0.1:11434/v1`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: OPENAI_API_KEY, // Optional; local servers usually don't check it
+        required: false,
+        secret: true,
+        default: None,
+    },
+    ConfigField {
+        key: OPENAI_MODEL, // Must accept images, e.g. `gpt-4o-mini`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: OPENAI_STREAM_USAGE, // `true` asks streams for token counts; not every server accepts it
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: ANTHROPIC_BASE_URL, // Only for proxies; defaults to `https://api.anthropic.com`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: ANTHROPIC_API_KEY,
+        required: false,
+        secret: true,
+        default: None,
+    },
+    ConfigField {
+        key: ANTHROPIC_MODEL,
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: OLLAMA_BASE_URL, // e.g. `http://127.0.0.1:11434`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: OLLAMA_MODEL, // A vision model, e.g. `llava`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: PIPELINE_VISION_PROFILE, // `openai`, `anthropic` or `ollama`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: PIPELINE_VISION_MODEL, // Defaults to the provider's model
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: PIPELINE_VISION_PROMPT, // Template file, relative to the config file; defaults to `Prompt/V1.md`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: PIPELINE_REASONING_PROFILE,
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: PIPELINE_REASONING_MODEL, // Need not accept images
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: PIPELINE_REASONING_PROMPT, // Template file, relative to the config file; defaults to `Prompt/R1.md`
+        required: false,
+        secret: false,
+        default: None,
+    },
+    ConfigField {
+        key: QUERY_HISTORY_BYTE_BUDGET, // Prior turns forwarded with a query; each call may lower it
+        required: false,
+        secret: false,
+        default: Some("16384"),
+    },
+    ConfigField {
+        key: QUERY_IMAGE_MAX_DIMENSION, // Longest side in pixels; larger screenshots are scaled down
+        required: false,
+        secret: false,
+        default: Some("1600"),
+    },
+    ConfigField {
+        key: QUERY_IMAGE_QUALITY, // 1-100
+        required: false,
+        secret: false,
+        default: Some("80"),
+    },
+    ConfigField {
+        key: QUERY_IMAGE_FORMAT, // `webp` or `jpeg`
+        required: false,
+        secret: false,
+        default: Some("webp"),
+    },
+    ConfigField {
+        key: QUERY_MAX_IMAGES,
+        required: false,
+        secret: false,
+        default: Some("4"),
+    },
+    ConfigField {
+        key: QUERY_MAX_IMAGE_PAYLOAD_BYTES, // All encoded images of one query together (20 MiB)
+        required: false,
+        secret: false,
+        default: Some("20971520"),
+    },
+    ConfigField {
+        key: HTTP_CONNECT_TIMEOUT_MS,
+        required: false,
+        secret: false,
+        default: Some("10000"),
+    },
+    ConfigField {
+        key: HTTP_READ_TIMEOUT_MS, // Longest silence between two reads
+        required: false,
+        secret: false,
+        default: Some("60000"),
+    },
+    ConfigField {
+        key: HTTP_TOTAL_TIMEOUT_MS, // Per request; a query may set its own
+        required: false,
+        secret: false,
+        default: Some("180000"),
+    },
+    ConfigField {
+        key: HTTP_MAX_RETRIES,
+        required: false,
+        secret: false,
+        default: Some("2"),
+    },
+    ConfigField {
+        key: HTTP_RETRY_BASE_DELAY_MS, // Doubled on every retry, plus jitter
+        required: false,
+        secret: false,
+        default: Some("500"),
+    },
+    ConfigField {
+        key: HTTP_RETRY_MAX_DELAY_MS,
+        required: false,
+        secret: false,
+        default: Some("8000"),
+    },
+];
+
+// Keys holding file paths; see `ResolvedConfig::anchor_paths`
+const PATH_KEYS: [&str; 2] = [PIPELINE_VISION_PROMPT, PIPELINE_REASONING_PROMPT];
+
+// Validated configuration. `None` means not configured or invalid; see `ConfigStatus`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppConfig {
+    pub github_client_id: Option<String>,
+    pub github_client_secret: Option<String>,
+    pub github_token_exchange: Option<TokenExchange>, // `None` when not set explicitly
+    pub github_web_url: Option<Url>,                  // `None` means github.com
+    pub github_api_url: Option<Url>,                  // Including the path prefix, if any
+    pub worker_api_url: Option<Url>,                  // Normalized, without a trailing slash
+    pub worker_api_key: Option<String>,
+    pub oidc_issuer: Option<String>, // As configured; must match the discovery document
+    pub oidc_client_id: Option<String>,
+    pub oidc_scopes: Option<String>,
+    pub oidc_display_name: Option<String>,
+    pub query_profile: Option<QueryProfile>,
+    pub openai_base_url: Option<Url>, // Normalized, without a trailing slash
+    pub openai_api_key: Option<String>,
+    pub openai_model: Option<String>,
+    pub openai_stream_usage: bool,
+    pub anthropic_base_url: Option<Url>, // `None` means api.anthropic.com
+    pub anthropic_api_key: Option<String>,
+    pub anthropic_model: Option<String>,
+    pub ollama_base_url: Option<Url>,
+    pub ollama_model: Option<String>,
+    pub pipeline_vision_profile: Option<QueryProfile>, // Always a direct provider
+    pub pipeline_vision_model: Option<String>,
+    pub pipeline_vision_prompt: Option<PathBuf>,
+    pub pipeline_reasoning_profile: Option<QueryProfile>,
+    pub pipeline_reasoning_model: Option<String>,
+    pub pipeline_reasoning_prompt: Option<PathBuf>,
+    // Tunables; `None` only when invalid (or not resolved, as in tests), in which case
+    // the owning module falls back to its `DEFAULT_*` constant
+    pub query_history_byte_budget: Option<usize>,
+    pub query_image_max_dimension: Option<u32>,
+    pub query_image_quality: Option<u8>,
+    pub query_image_format: Option<OutputFormat>,
+    pub query_max_images: Option<usize>,
+    pub query_max_image_payload_bytes: Option<usize>,
+    pub http_connect_timeout_ms: Option<u64>,
+    pub http_read_timeout_ms: Option<u64>,
+    pub http_total_timeout_ms: Option<u64>,
+    pub http_max_retries: Option<u32>,
+    pub http_retry_base_delay_ms: Option<u64>,
+    pub http_retry_max_delay_ms: Option<u64>,
+}
+
+impl AppConfig {
+    // e.g. `worker_endpoint("query")` -> `https://worker.example/api/query`
+    pub fn worker_endpoint(&self, path: &str) -> Option<String> {
+        self.worker_api_url
+            .as_ref()
+            .map(|base| join_url(base, path))
+    }
+
+    // Base URL of the GitHub web UI and OAuth endpoints, without a trailing slash
+    pub fn github_web_base(&self) -> String {
+        self.github_web_url
+            .as_ref()
+            .map_or(GITHUB_DOT_COM_WEB_URL.to_string(), |url| {
+                url.as_str().trim_end_matches('/').to_string()
+            })
+    }
+
+    // Base URL of the GitHub REST API, without a trailing slash. A GitHub Enterprise
+    // Server without an explicit API URL serves it under `/api/v3` on the web host.
+    pub fn github_api_base(&self) -> String {
+        match (&self.github_api_url, &self.github_web_url) {
+            (Some(api), _) => api.as_str().trim_end_matches('/').to_string(),
+            (None, Some(web)) => join_url(web, GHES_API_PATH),
+            (None, None) => GITHUB_DOT_COM_API_URL.to_string(),
+        }
+    }
+
+    // Without an explicit choice, the app only talks to GitHub directly when it has
+    // been given the client secret
+    pub fn token_exchange(&self) -> TokenExchange {
+        self.github_token_exchange
+            .unwrap_or(if self.github_client_secret.is_some() {
+                TokenExchange::Direct
+            } else {
+                TokenExchange::Worker
+            })
+    }
+}
+
+// Who redeems the OAuth code for an access token. Both send the PKCE verifier.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenExchange {
+    Worker, // The worker holds the client secret; the desktop binary never sees it
+    Direct, // The app posts the client secret to GitHub itself
+}
+
+impl TokenExchange {
+    const CHOICES: &'static str = "worker, direct";
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "worker" => Some(TokenExchange::Worker),
+            "direct" => Some(TokenExchange::Direct),
+            _ => None,
+        }
+    }
+}
+
+// Where a query is sent. The config picks the default; each query may override it.
+// Every profile but `worker` and `pipeline` is a direct provider, see `provider`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryProfile {
+    #[default]
+    Worker, // The Cloudflare worker's `/query`, which holds the AI API key
+    OpenAi,    // An OpenAI-compatible chat-completions API, called by the app itself
+    Anthropic, // The Anthropic Messages API
+    Ollama,    // A local Ollama server
+    Pipeline,  // A vision stage and a reasoning stage on direct providers, see `pipeline`
+}
+
+impl QueryProfile {
+    pub const ALL: [QueryProfile; 5] = [
+        QueryProfile::Worker,
+        QueryProfile::OpenAi,
+        QueryProfile::Anthropic,
+        QueryProfile::Ollama,
+        QueryProfile::Pipeline,
+    ];
+    const CHOICES: &'static str = "worker, openai, anthropic, ollama, pipeline";
+    const DIRECT_CHOICES: &'static str = "openai, anthropic, ollama";
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "worker" => Some(QueryProfile::Worker),
+            "openai" => Some(QueryProfile::OpenAi),
+            "anthropic" => Some(QueryProfile::Anthropic),
+            "ollama" => Some(QueryProfile::Ollama),
+            "pipeline" => Some(QueryProfile::Pipeline),
+            _ => None,
+        }
+    }
+
+    // Whether the profile is a single provider the app calls itself
+    pub fn is_direct(self) -> bool {
+        !matches!(self, QueryProfile::Worker | QueryProfile::Pipeline)
+    }
+
+    // Keys the profile needs when it is the default, as (key, whether it is set)
+    pub fn required_settings(self, config: &AppConfig) -> Vec<(&'static str, bool)> {
+        match self {
+            QueryProfile::Worker => Vec::new(), // Required by the schema
+            QueryProfile::OpenAi => vec![
+                (OPENAI_BASE_URL, config.openai_base_url.is_some()),
+                (OPENAI_MODEL, config.openai_model.is_some()),
+            ],
+            QueryProfile::Anthropic => vec![
+                (ANTHROPIC_API_KEY, config.anthropic_api_key.is_some()),
+                (ANTHROPIC_MODEL, config.anthropic_model.is_some()),
+            ],
+            QueryProfile::Ollama => vec![
+                (OLLAMA_BASE_URL, config.ollama_base_url.is_some()),
+                (OLLAMA_MODEL, config.ollama_model.is_some()),
+            ],
+            // The stage providers' own settings are checked when a query runs
+            QueryProfile::Pipeline => vec![
+                (
+                    PIPELINE_VISION_PROFILE,
+                    config.pipeline_vision_profile.is_some(),
+                ),
+                (
+                    PIPELINE_REASONING_PROFILE,
+                    config.pipeline_reasoning_profile.is_some(),
+                ),
+            ],
+        }
+    }
+}
+
+// --- Error handling ---
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+pub enum ConfigError {
+    #[error("Failed to read config file '{0}': {1}")]
+    FileRead(String, String),
+    #[error("Invalid config file '{0}': {1}")]
+    FileParse(String, String),
+    #[error("Embedded configuration is invalid: {0}")]
+    EmbeddedParse(String),
+    #[error("Failed to read from the secret store: {0}")]
+    SecretStore(String),
+}
+
+// One problem with one key. Serialized like `QueryError`, e.g.
+// `{"kind":"invalidUrl","key":"WORKER_API_URL","message":"..."}`.
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ConfigIssue {
+    #[error("{key} is required")]
+    Missing { key: String },
+    #[error("{key} is not a valid URL: {message}")]
+    InvalidUrl { key: String, message: String },
+    #[error("{key} must use https unless it points to this machine: {value}")]
+    InsecureUrl { key: String, value: String },
+    #[error("{key} must be set together with {other}")]
+    RequiredWith { key: String, other: String },
+    #[error("{key} must be one of {choices}, got '{value}'")]
+    InvalidChoice {
+        key: String,
+        value: String,
+        choices: String,
+    },
+    #[error("{key} must be {expected}, got '{value}'")]
+    InvalidNumber {
+        key: String,
+        value: String,
+        expected: String, // e.g. "a whole number from 1 to 100"
+    },
+}
+
+impl ConfigIssue {
+    pub fn key(&self) -> &str {
+        match self {
+            ConfigIssue::Missing { key }
+            | ConfigIssue::InvalidUrl { key, .. }
+            | ConfigIssue::InsecureUrl { key, .. }
+            | ConfigIssue::RequiredWith { key, .. }
+            | ConfigIssue::InvalidChoice { key, .. }
+            | ConfigIssue::InvalidNumber { key, .. } => key,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigParseError {
+    // 1-based position of the offending character and the text of that line
+    MalformedLine {
+        line: usize,
+        column: usize,
+        reason: String,
+        content: String,
+    },
+}
+
+impl std::fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigParseError::MalformedLine {
+                line,
+                column,
+                reason,
+                content,
+            } => write!(
+                f,
+                "line {}, column {}: {}: {}",
+                line, column, reason, content
+            ),
+        }
+    }
+}
+impl std::error::Error for ConfigParseError {}
+
+// --- .env parsing ---
+// Parses .env content the way `dotenvy` does, so a file behaves the same whether it is
+// loaded at startup by dotenvy or read here as a config layer:
+// - `KEY=value` with optional leading `export ` and whitespace around `=`
+// - `#` starts a comment at the beginning of a line or after whitespace (`a#b` is a value)
+// - single quotes are literal; double quotes allow escapes, `$` substitution and newlines
+// - escapes: `\\`, `\'`, `\"`, `\$`, `\ ` and `\n`, outside single quotes
+// - `${NAME}` and `$NAME` (alphanumerics only) read the process environment first, then
+//   keys defined earlier in the file; unknown names expand to nothing
+// - later duplicates win
+// Known divergences: dotenvy rejects these, this parser accepts them:
+// - `B="$A"`: a substitution as the whole double-quoted value (here `B` is `A`'s value)
+// - trailing whitespace after the last value in the file (here it is dropped)
+// - `A='a\'`: a backslash right before a closing single quote (here `A` is `a\`)
+pub fn parse_env_vars(env_content: &str) -> Result<HashMap<String, String>, ConfigParseError> {
+    EnvParser::new(env_content).parse()
+}
+
+struct EnvParser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    vars: HashMap<String, String>,
+}
+
+impl<'a> EnvParser<'a> {
+    fn new(source: &'a str) -> Self {
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        EnvParser {
+            source,
+            chars: source.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+            vars: HashMap::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<HashMap<String, String>, ConfigParseError> {
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None => break,
+                Some('#') => self.skip_to_line_end(),
+                Some(_) if self.at_line_end() => {}
+                Some(_) => {
+                    let (key, value) = self.parse_entry()?;
+                    self.vars.insert(key, value);
+                }
+            }
+            self.skip_line_end();
+        }
+        Ok(self.vars)
+    }
+
+    fn parse_entry(&mut self) -> Result<(String, String), ConfigParseError> {
+        let mut key = self.parse_key()?;
+        self.skip_blanks();
+        // `export` is either a prefix or a key of its own
+        if key == "export" && self.peek() != Some('=') {
+            key = self.parse_key()?;
+            self.skip_blanks();
+        }
+        if self.peek() != Some('=') {
+            return Err(self.error_here("expected '=' after the key"));
+        }
+        self.bump();
+        self.skip_blanks();
+
+        if self.peek() == Some('#') || self.at_line_end() {
+            self.skip_to_line_end();
+            return Ok((key, String::new()));
+        }
+        let value = self.parse_value()?;
+        Ok((key, value))
+    }
+
+    fn parse_key(&mut self) -> Result<String, ConfigParseError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+            _ => return Err(self.error_here("expected a key")),
+        }
+        let mut key = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+        {
+            key.push(c);
+            self.bump();
+        }
+        Ok(key)
+    }
+
+    // Reads up to the end of the line, or further while inside quotes
+    fn parse_value(&mut self) -> Result<String, ConfigParseError> {
+        let mut value = String::new();
+        loop {
+            let (line, column) = (self.line, self.column);
+            match self.peek() {
+                None => break,
+                Some(_) if self.at_line_end() => break,
+                Some(' ') | Some('\t') => {
+                    self.skip_blanks();
+                    if self.peek() == Some('#') {
+                        self.skip_to_line_end();
+                    } else if !self.at_line_end() {
+                        return Err(self
+                            .error_here("unexpected character after whitespace; quote the value"));
+                    }
+                    break;
+                }
+                Some('\'') => {
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('\'') => break,
+                            Some(c) => value.push(c),
+                            None => {
+                                return Err(self.error_at(
+                                    line,
+                                    column,
+                                    "unterminated single quote",
+                                ))
+                            }
+                        }
+                    }
+                }
+                Some('"') => {
+                    self.bump();
+                    loop {
+                        match self.peek() {
+                            Some('"') => {
+                                self.bump();
+                                break;
+                            }
+                            Some('\\') => self.parse_escape(&mut value)?,
+                            Some('$') => self.parse_substitution(&mut value)?,
+                            Some(c) => {
+                                value.push(c);
+                                self.bump();
+                            }
+                            None => {
+                                return Err(self.error_at(
+                                    line,
+                                    column,
+                                    "unterminated double quote",
+                                ))
+                            }
+                        }
+                    }
+                }
+                Some('\\') => self.parse_escape(&mut value)?,
+                Some('$') => self.parse_substitution(&mut value)?,
+                Some(c) => {
+                    value.push(c);
+                    self.bump();
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_escape(&mut self, value: &mut String) -> Result<(), ConfigParseError> {
+        let (line, column) = (self.line, self.column);
+        self.bump(); // '\'
+        match self.peek() {
+            Some(c @ ('\\' | '\'' | '"' | '$' | ' ')) => value.push(c),
+            Some('n') => value.push('\n'),
+            Some(c) if !self.at_line_end() => {
+                return Err(self.error_at(line, column, &format!("invalid escape '\\{}'", c)))
+            }
+            _ => return Err(self.error_at(line, column, "escape at end of line")),
+        }
+        self.bump();
+        Ok(())
+    }
+
+    fn parse_substitution(&mut self, value: &mut String) -> Result<(), ConfigParseError> {
+        let (line, column) = (self.line, self.column);
+        self.bump(); // '$'
+        let mut name = String::new();
+        if self.peek() == Some('{') {
+            self.bump();
+            loop {
+                match self.bump() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(self.error_at(line, column, "unterminated '${'")),
+                }
+            }
+        } else {
+            while let Some(c) = self.peek().filter(|c| c.is_alphanumeric()) {
+                name.push(c);
+                self.bump();
+            }
+        }
+        let substituted = std::env::var(&name)
+            .ok()
+            .or_else(|| self.vars.get(&name).cloned())
+            .unwrap_or_default();
+        value.push_str(&substituted);
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn at_line_end(&self) -> bool {
+        match self.peek() {
+            None | Some('\n') => true,
+            Some('\r') => self.chars.get(self.pos + 1) == Some(&'\n'),
+            Some(_) => false,
+        }
+    }
+
+    fn skip_blanks(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| c.is_whitespace() && !self.at_line_end())
+        {
+            self.bump();
+        }
+    }
+
+    fn skip_to_line_end(&mut self) {
+        while !self.at_line_end() {
+            self.bump();
+        }
+    }
+
+    fn skip_line_end(&mut self) {
+        if self.peek() == Some('\r') {
+            self.bump();
+        }
+        self.bump();
+    }
+
+    fn error_here(&self, reason: &str) -> ConfigParseError {
+        self.error_at(self.line, self.column, reason)
+    }
+
+    fn error_at(&self, line: usize, column: usize, reason: &str) -> ConfigParseError {
+        ConfigParseError::MalformedLine {
+            line,
+            column,
+            reason: reason.to_string(),
+            content: self
+                .source
+                .lines()
+                .nth(line - 1)
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+// --- Validation ---
+
+// Parses an http(s) base URL and drops trailing slashes, so joining paths never
+// produces `//query`
+pub fn normalize_base_url(value: &str) -> Result<Url, String> {
+    let mut url = Url::parse(value).map_err(|e| e.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme '{}'", url.scheme()));
+    }
+    if url.host_str().is_none() {
+        return Err("missing host".to_string());
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err("credentials are not allowed in the URL".to_string());
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err("query strings and fragments are not allowed".to_string());
+    }
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&path);
+    Ok(url)
+}
+
+pub fn join_url(base: &Url, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base.as_str().trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => domain == "localhost",
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+// A valid http(s) URL, and the value it was parsed from. Plain http is only accepted
+// for this machine.
+fn field_url(
+    values: &HashMap<String, String>,
+    key: &str,
+    issues: &mut Vec<ConfigIssue>,
+) -> Option<(String, Url)> {
+    let value = field_text(values, key, issues)?;
+    match normalize_base_url(value.trim()) {
+        Ok(url) if url.scheme() == "http" && !is_loopback(&url) => {
+            issues.push(ConfigIssue::InsecureUrl {
+                key: key.to_string(),
+                value,
+            });
+            None
+        }
+        Ok(url) => Some((value, url)),
+        Err(message) => {
+            issues.push(ConfigIssue::InvalidUrl {
+                key: key.to_string(),
+                message,
+            });
+            None
+        }
+    }
+}
+
+// A direct provider for a pipeline stage
+fn field_stage_profile(
+    values: &HashMap<String, String>,
+    key: &str,
+    issues: &mut Vec<ConfigIssue>,
+) -> Option<QueryProfile> {
+    let value = field_text(values, key, issues)?;
+    let choice = QueryProfile::parse(&value).filter(|profile| profile.is_direct());
+    if choice.is_none() {
+        issues.push(ConfigIssue::InvalidChoice {
+            key: key.to_string(),
+            value,
+            choices: QueryProfile::DIRECT_CHOICES.to_string(),
+        });
+    }
+    choice
+}
+
+// `true` or `false`; unset means `false`
+fn field_flag(values: &HashMap<String, String>, key: &str, issues: &mut Vec<ConfigIssue>) -> bool {
+    let Some(value) = field_text(values, key, issues) else {
+        return false;
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" => true,
+        "false" => false,
+        _ => {
+            issues.push(ConfigIssue::InvalidChoice {
+                key: key.to_string(),
+                value,
+                choices: "true, false".to_string(),
+            });
+            false
+        }
+    }
+}
+
+// A whole number of at least `min` and, if given, at most `max`
+fn field_number<T: TryFrom<u64>>(
+    values: &HashMap<String, String>,
+    key: &str,
+    min: u64,
+    max: Option<u64>,
+    issues: &mut Vec<ConfigIssue>,
+) -> Option<T> {
+    let value = field_text(values, key, issues)?;
+    let number = value
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|number| *number >= min && max.is_none_or(|max| *number <= max))
+        .and_then(|number| T::try_from(number).ok());
+    if number.is_none() {
+        let expected = match max {
+            Some(max) => format!("a whole number from {} to {}", min, max),
+            None => format!("a whole number of at least {}", min),
+        };
+        issues.push(ConfigIssue::InvalidNumber {
+            key: key.to_string(),
+            value,
+            expected,
+        });
+    }
+    number
+}
+
+// A non-blank value, or `None` (reported as missing when the schema requires it)
+fn field_text(
+    values: &HashMap<String, String>,
+    key: &str,
+    issues: &mut Vec<ConfigIssue>,
+) -> Option<String> {
+    match values.get(key) {
+        Some(value) if !value.trim().is_empty() => Some(value.clone()),
+        _ => {
+            if CONFIG_SCHEMA
+                .iter()
+                .any(|field| field.key == key && field.required)
+            {
+                issues.push(ConfigIssue::Missing {
+                    key: key.to_string(),
+                });
+            }
+            None
+        }
+    }
+}
+
+// Checks every field and cross-field rule, collecting all problems instead of stopping
+// at the first one. Invalid values are left out of the returned config.
+pub fn validate(values: &HashMap<String, String>) -> (AppConfig, Vec<ConfigIssue>) {
+    let mut issues = Vec::new();
+
+    let github_client_id = field_text(values, GITHUB_CLIENT_ID, &mut issues);
+    let github_client_secret = field_text(values, GITHUB_CLIENT_SECRET, &mut issues);
+    let github_token_exchange =
+        field_text(values, GITHUB_TOKEN_EXCHANGE, &mut issues).and_then(|value| {
+            let choice = TokenExchange::parse(&value);
+            if choice.is_none() {
+                issues.push(ConfigIssue::InvalidChoice {
+                    key: GITHUB_TOKEN_EXCHANGE.to_string(),
+                    value,
+                    choices: TokenExchange::CHOICES.to_string(),
+                });
+            }
+            choice
+        });
+    let github_web_url = field_url(values, GITHUB_WEB_URL, &mut issues).map(|(_, url)| url);
+    let github_api_url = field_url(values, GITHUB_API_URL, &mut issues).map(|(_, url)| url);
+    let worker_api_url = field_url(values, WORKER_API_URL, &mut issues).map(|(_, url)| url);
+    let worker_api_key = field_text(values, WORKER_API_KEY, &mut issues);
+    let oidc_issuer =
+        field_url(values, OIDC_ISSUER, &mut issues).map(|(value, _)| value.trim().to_string());
+    let oidc_client_id = field_text(values, OIDC_CLIENT_ID, &mut issues);
+    let oidc_scopes = field_text(values, OIDC_SCOPES, &mut issues);
+    let oidc_display_name = field_text(values, OIDC_DISPLAY_NAME, &mut issues);
+    let query_profile = field_text(values, QUERY_PROFILE, &mut issues).and_then(|value| {
+        let choice = QueryProfile::parse(&value);
+        if choice.is_none() {
+            issues.push(ConfigIssue::InvalidChoice {
+                key: QUERY_PROFILE.to_string(),
+                value,
+                choices: QueryProfile::CHOICES.to_string(),
+            });
+        }
+        choice
+    });
+    let openai_base_url = field_url(values, OPENAI_BASE_URL, &mut issues).map(|(_, url)| url);
+    let openai_api_key = field_text(values, OPENAI_API_KEY, &mut issues);
+    let openai_model = field_text(values, OPENAI_MODEL, &mut issues);
+    let openai_stream_usage = field_flag(values, OPENAI_STREAM_USAGE, &mut issues);
+    let anthropic_base_url = field_url(values, ANTHROPIC_BASE_URL, &mut issues).map(|(_, url)| url);
+    let anthropic_api_key = field_text(values, ANTHROPIC_API_KEY, &mut issues);
+    let anthropic_model = field_text(values, ANTHROPIC_MODEL, &mut issues);
+    let ollama_base_url = field_url(values, OLLAMA_BASE_URL, &mut issues).map(|(_, url)| url);
+    let ollama_model = field_text(values, OLLAMA_MODEL, &mut issues);
+    let pipeline_vision_profile = field_stage_profile(values, PIPELINE_VISION_PROFILE, &mut issues);
+    let pipeline_vision_model = field_text(values, PIPELINE_VISION_MODEL, &mut issues);
+    let pipeline_vision_prompt =
+        field_text(values, PIPELINE_VISION_PROMPT, &mut issues).map(PathBuf::from);
+    let pipeline_reasoning_profile =
+        field_stage_profile(values, PIPELINE_REASONING_PROFILE, &mut issues);
+    let pipeline_reasoning_model = field_text(values, PIPELINE_REASONING_MODEL, &mut issues);
+    let pipeline_reasoning_prompt =
+        field_text(values, PIPELINE_REASONING_PROMPT, &mut issues).map(PathBuf::from);
+    let query_history_byte_budget =
+        field_number(values, QUERY_HISTORY_BYTE_BUDGET, 0, None, &mut issues);
+    let query_image_max_dimension =
+        field_number(values, QUERY_IMAGE_MAX_DIMENSION, 1, None, &mut issues);
+    let query_image_quality = field_number(values, QUERY_IMAGE_QUALITY, 1, Some(100), &mut issues);
+    let query_image_format =
+        field_text(values, QUERY_IMAGE_FORMAT, &mut issues).and_then(|value| {
+            let choice = OutputFormat::parse(&value);
+            if choice.is_none() {
+                issues.push(ConfigIssue::InvalidChoice {
+                    key: QUERY_IMAGE_FORMAT.to_string(),
+                    value,
+                    choices: OutputFormat::CHOICES.to_string(),
+                });
+            }
+            choice
+        });
+    let query_max_images = field_number(values, QUERY_MAX_IMAGES, 0, None, &mut issues);
+    let query_max_image_payload_bytes =
+        field_number(values, QUERY_MAX_IMAGE_PAYLOAD_BYTES, 0, None, &mut issues);
+    let http_connect_timeout_ms =
+        field_number(values, HTTP_CONNECT_TIMEOUT_MS, 1, None, &mut issues);
+    let http_read_timeout_ms = field_number(values, HTTP_READ_TIMEOUT_MS, 1, None, &mut issues);
+    let http_total_timeout_ms = field_number(values, HTTP_TOTAL_TIMEOUT_MS, 1, None, &mut issues);
+    let http_max_retries = field_number(values, HTTP_MAX_RETRIES, 0, None, &mut issues);
+    let http_retry_base_delay_ms =
+        field_number(values, HTTP_RETRY_BASE_DELAY_MS, 0, None, &mut issues);
+    let http_retry_max_delay_ms =
+        field_number(values, HTTP_RETRY_MAX_DELAY_MS, 0, None, &mut issues);
+
+    let mut config = AppConfig {
+        github_client_id,
+        github_client_secret,
+        github_token_exchange,
+        github_web_url,
+        github_api_url,
+        worker_api_url,
+        worker_api_key,
+        oidc_issuer,
+        oidc_client_id,
+        oidc_scopes,
+        oidc_display_name,
+        query_profile,
+        openai_base_url,
+        openai_api_key,
+        openai_model,
+        openai_stream_usage,
+        anthropic_base_url,
+        anthropic_api_key,
+        anthropic_model,
+        ollama_base_url,
+        ollama_model,
+        pipeline_vision_profile,
+        pipeline_vision_model,
+        pipeline_vision_prompt,
+        pipeline_reasoning_profile,
+        pipeline_reasoning_model,
+        pipeline_reasoning_prompt,
+        query_history_byte_budget,
+        query_image_max_dimension,
+        query_image_quality,
+        query_image_format,
+        query_max_images,
+        query_max_image_payload_bytes,
+        http_connect_timeout_ms,
+        http_read_timeout_ms,
+        http_total_timeout_ms,
+        http_max_retries,
+        http_retry_base_delay_ms,
+        http_retry_max_delay_ms,
+    };
+
+    // A client secret is useless without the client ID it belongs to
+    if config.github_client_id.is_none() && config.github_client_secret.is_some() {
+        issues.push(ConfigIssue::RequiredWith {
+            key: GITHUB_CLIENT_ID.to_string(),
+            other: GITHUB_CLIENT_SECRET.to_string(),
+        });
+        config.github_client_secret = None;
+    }
+    // Exchanging codes in the app needs the secret; through the worker it does not
+    if config.github_client_id.is_some()
+        && config.github_client_secret.is_none()
+        && config.github_token_exchange == Some(TokenExchange::Direct)
+    {
+        issues.push(ConfigIssue::Missing {
+            key: GITHUB_CLIENT_SECRET.to_string(),
+        });
+        config.github_client_id = None;
+    }
+    // An API URL on its own would send github.com tokens to another server
+    if config.github_api_url.is_some() && config.github_web_url.is_none() {
+        issues.push(ConfigIssue::RequiredWith {
+            key: GITHUB_WEB_URL.to_string(),
+            other: GITHUB_API_URL.to_string(),
+        });
+        config.github_api_url = None;
+    }
+    // OIDC sign-in needs both the issuer and the client registered with it
+    let missing_oidc_half = match (&config.oidc_issuer, &config.oidc_client_id) {
+        (Some(_), None) => Some((OIDC_CLIENT_ID, OIDC_ISSUER)),
+        (None, Some(_)) => Some((OIDC_ISSUER, OIDC_CLIENT_ID)),
+        _ => None,
+    };
+    if let Some((key, other)) = missing_oidc_half {
+        issues.push(ConfigIssue::RequiredWith {
+            key: key.to_string(),
+            other: other.to_string(),
+        });
+        config.oidc_issuer = None;
+        config.oidc_client_id = None;
+    }
+    // Any other profile replaces the worker for queries, so it needs its own settings,
+    // and the worker is no longer required
+    if let Some(profile) = config.query_profile.filter(|p| *p != QueryProfile::Worker) {
+        for (key, set) in profile.required_settings(&config) {
+            if !set && !issues.iter().any(|issue| issue.key() == key) {
+                issues.push(ConfigIssue::Missing {
+                    key: key.to_string(),
+                });
+            }
+        }
+        issues.retain(|issue| {
+            !matches!(issue, ConfigIssue::Missing { key }
+                if key == WORKER_API_URL || key == WORKER_API_KEY)
+        });
+    }
+
+    (config, issues)
+}
+
+// --- Resolution ---
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigSource {
+    Default,
+    File,
+    Env,
+    SecretStore,
+    Embedded,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ResolvedConfig {
+    values: HashMap<String, (String, ConfigSource)>,
+    errors: Vec<ConfigError>,
+}
+
+impl ResolvedConfig {
+    fn fill(&mut self, key: &str, value: &str, source: ConfigSource) {
+        if !value.is_empty() && !self.values.contains_key(key) {
+            self.values
+                .insert(key.to_string(), (value.to_string(), source));
+        }
+    }
+
+    fn fill_from(
+        &mut self,
+        vars: &HashMap<String, String>,
+        source: ConfigSource,
+        include_secrets: bool,
+    ) {
+        for field in CONFIG_SCHEMA
+            .iter()
+            .filter(|field| include_secrets || !field.secret)
+        {
+            if let Some(value) = vars.get(field.key) {
+                self.fill(field.key, value, source);
+            }
+        }
+    }
+
+    // Relative paths from the config file are relative to its directory, not to
+    // wherever the app happens to be started from
+    fn anchor_paths(&mut self, config_file: &Path) {
+        let Some(dir) = config_file.parent() else {
+            return;
+        };
+        for key in PATH_KEYS {
+            if let Some((value, ConfigSource::File)) = self.values.get_mut(key) {
+                if Path::new(value.as_str()).is_relative() {
+                    *value = dir.join(value.as_str()).to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        CONFIG_SCHEMA
+            .iter()
+            .all(|field| self.values.contains_key(field.key))
+    }
+
+    fn raw_values(&self) -> HashMap<String, String> {
+        self.values
+            .iter()
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+// Resolves every key from the layers, collecting problems instead of failing.
+// A config file that can't be read or parsed is skipped as a whole.
+fn resolve(
+    config_file: Option<&Path>,
+    env: impl Fn(&str) -> Option<String>,
+    secrets: Option<&SecretStore>,
+    embedded: &str,
+) -> ResolvedConfig {
+    let mut resolved = ResolvedConfig::default();
+
+    for field in &CONFIG_SCHEMA {
+        if let Some(value) = env(field.key) {
+            resolved.fill(field.key, value.trim(), ConfigSource::Env);
+        }
+    }
+
+    if let Some(store) = secrets {
+        for field in CONFIG_SCHEMA.iter().filter(|field| field.secret) {
+            let Some(key) = SecretKey::from_config_key(field.key) else {
+                continue;
+            };
+            match store.get(key) {
+                Ok(Some(value)) => resolved.fill(field.key, &value, ConfigSource::SecretStore),
+                Ok(None) => {}
+                Err(e) => resolved
+                    .errors
+                    .push(ConfigError::SecretStore(e.to_string())),
+            }
+        }
+    }
+
+    if let Some(path) = config_file {
+        let display = path.to_string_lossy().into_owned();
+        match std::fs::read_to_string(path) {
+            Ok(content) => match parse_env_vars(&content) {
+                Ok(vars) => {
+                    resolved.fill_from(&vars, ConfigSource::File, true);
+                    resolved.anchor_paths(path);
+                }
+                Err(e) => resolved
+                    .errors
+                    .push(ConfigError::FileParse(display, e.to_string())),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {} // The file is optional
+            Err(e) => resolved
+                .errors
+                .push(ConfigError::FileRead(display, e.to_string())),
+        }
+    }
+
+    // The embedded file is only parsed when something is still missing
+    if !resolved.is_complete() && !embedded.trim().is_empty() {
+        match parse_env_vars(embedded) {
+            // Keep secrets out of release binaries: they belong in the secret store
+            Ok(vars) => resolved.fill_from(&vars, ConfigSource::Embedded, cfg!(debug_assertions)),
+            Err(e) => resolved
+                .errors
+                .push(ConfigError::EmbeddedParse(e.to_string())),
+        }
+    }
+
+    for field in &CONFIG_SCHEMA {
+        if let Some(value) = field.default {
+            resolved.fill(field.key, value, ConfigSource::Default);
+        }
+    }
+    resolved
+}
+
+// --- Global state ---
+struct ConfigState {
+    config_file: Option<PathBuf>,
+    secrets: Option<SecretStoreState>,
+    resolved: ResolvedConfig,
+    config: AppConfig,
+    issues: Vec<ConfigIssue>,
+}
+
+impl ConfigState {
+    fn load(config_file: Option<PathBuf>, secrets: Option<SecretStoreState>) -> Self {
+        let resolved = resolve(
+            config_file.as_deref(),
+            |key| std::env::var(key).ok(),
+            secrets.as_deref(),
+            EMBEDDED_ENV,
+        );
+        let (config, issues) = validate(&resolved.raw_values());
+        for error in &resolved.errors {
+            eprintln!("Config: {}", error);
+        }
+        for issue in &issues {
+            eprintln!("Config: {}", issue);
+        }
+        ConfigState {
+            config_file,
+            secrets,
+            resolved,
+            config,
+            issues,
+        }
+    }
+
+    fn status(&self) -> ConfigStatus {
+        ConfigStatus {
+            ready: self.issues.is_empty(),
+            config_file: self
+                .config_file
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned()),
+            sources: self
+                .resolved
+                .values
+                .iter()
+                .map(|(key, (_, source))| (key.clone(), *source))
+                .collect(),
+            errors: self.resolved.errors.clone(),
+            issues: self.issues.clone(),
+        }
+    }
+}
+
+// Until `init` runs (e.g. in unit tests) only the environment and embedded layers apply
+static CONFIG: Lazy<RwLock<ConfigState>> = Lazy::new(|| RwLock::new(ConfigState::load(None, None)));
+
+// What the UI gets to see: where each value came from, never the values themselves
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigStatus {
+    pub ready: bool,
+    pub config_file: Option<String>,
+    pub sources: BTreeMap<String, ConfigSource>,
+    pub errors: Vec<ConfigError>, // Layers that could not be read
+    pub issues: Vec<ConfigIssue>, // Every validation problem, not just the first
+}
+
+pub fn current() -> AppConfig {
+    CONFIG
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .config
+        .clone()
+}
+
+// Explains why `key` is absent from `current()`, for error messages
+pub fn describe_issues(key: &str) -> String {
+    let state = CONFIG.read().unwrap_or_else(PoisonError::into_inner);
+    let messages: Vec<String> = state
+        .issues
+        .iter()
+        .filter(|issue| issue.key() == key)
+        .map(|issue| issue.to_string())
+        .collect();
+    if messages.is_empty() {
+        format!("{} is not configured", key)
+    } else {
+        messages.join("; ")
+    }
+}
+
+// Points the file and secret store layers at their backing storage and loads
+// everything again
+pub fn init(config_file: Option<PathBuf>, secrets: Option<SecretStoreState>) -> ConfigStatus {
+    let state = ConfigState::load(config_file, secrets);
+    let status = state.status();
+    *CONFIG.write().unwrap_or_else(PoisonError::into_inner) = state;
+    status
+}
+
+fn storage() -> (Option<PathBuf>, Option<SecretStoreState>) {
+    let state = CONFIG.read().unwrap_or_else(PoisonError::into_inner);
+    (state.config_file.clone(), state.secrets.clone())
+}
+
+pub fn reload() -> ConfigStatus {
+    let (config_file, secrets) = storage();
+    init(config_file, secrets)
+}
+
+// --- Tauri Commands ---
+
+// Re-reads the config file and environment, e.g. after the user edited `config.env`
+#[tauri::command]
+pub fn reload_config<R: Runtime>(app: AppHandle<R>) -> ConfigStatus {
+    let status = reload();
+    crate::auth::allow_login_urls(&app);
+    println!(
+        "[reload_config] Reloaded configuration (ready: {}, {} error(s), {} issue(s))",
+        status.ready,
+        status.errors.len(),
+        status.issues.len()
+    );
+    status
+}
+
+#[tauri::command]
+pub fn get_config_status() -> ConfigStatus {
+    CONFIG
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .status()
+}
+
+// Checks the config file and environment as they are now and reports every problem,
+// without applying the result. Use `reload_config` to apply it.
+#[tauri::command]
+pub fn validate_config() -> ConfigStatus {
+    let (config_file, secrets) = storage();
+    let status = ConfigState::load(config_file, secrets).status();
+    println!(
+        "[validate_config] {} error(s), {} issue(s)",
+        status.errors.len(),
+        status.issues.len()
+    );
+    status
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::tests::MemoryBackend;
+
+    const FULL: &str = "GITHUB_CLIENT_ID=embedded_id\nGITHUB_CLIENT_SECRET=embedded_secret\nWORKER_API_URL=https://embedded.example\nWORKER_API_KEY=embedded_key";
+
+    fn temp_config(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("config-test-{}-{}.env", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn value(resolved: &ResolvedConfig, key: &str) -> (String, ConfigSource) {
+        resolved.values.get(key).cloned().unwrap()
+    }
+
+    #[test]
+    fn test_layers_precedence() {
+        let file = temp_config(
+            "precedence",
+            "WORKER_API_URL=https://file.example\nWORKER_API_KEY=file_key\n",
+        );
+        let env = |key: &str| (key == WORKER_API_KEY).then(|| "env_key".to_string());
+        let resolved = resolve(Some(&file), env, None, FULL);
+        std::fs::remove_file(&file).ok();
+
+        assert_eq!(
+            value(&resolved, WORKER_API_KEY),
+            ("env_key".to_string(), ConfigSource::Env)
+        );
+        assert_eq!(
+            value(&resolved, WORKER_API_URL),
+            ("https://file.example".to_string(), ConfigSource::File)
+        );
+        assert_eq!(
+            value(&resolved, GITHUB_CLIENT_SECRET),
+            ("embedded_secret".to_string(), ConfigSource::Embedded)
+        );
+        assert!(resolved.errors.is_empty());
+    }
+
+    #[test]
+    fn test_secret_store_layer() {
+        let store = SecretStore::new(Box::new(MemoryBackend::default()));
+        store.set(SecretKey::WorkerApiKey, "stored_key").unwrap();
+        store
+            .set(SecretKey::GithubClientSecret, "stored_secret")
+            .unwrap();
+        let file = temp_config(
+            "secrets",
+            "WORKER_API_KEY=file_key\nGITHUB_CLIENT_SECRET=file_secret\n",
+        );
+        let env = |key: &str| (key == GITHUB_CLIENT_SECRET).then(|| "env_secret".to_string());
+        let resolved = resolve(Some(&file), env, Some(&store), FULL);
+        std::fs::remove_file(&file).ok();
+
+        assert_eq!(
+            value(&resolved, WORKER_API_KEY),
+            ("stored_key".to_string(), ConfigSource::SecretStore)
+        );
+        assert_eq!(value(&resolved, GITHUB_CLIENT_SECRET).1, ConfigSource::Env);
+    }
+
+    #[test]
+    fn test_relative_paths_follow_the_config_file() {
+        let file = temp_config(
+            "paths",
+            "PIPELINE_VISION_PROMPT=prompts/vision.md\nPIPELINE_REASONING_PROMPT=/abs/r.md\n",
+        );
+        let resolved = resolve(Some(&file), |_| None, None, FULL);
+        std::fs::remove_file(&file).ok();
+
+        let dir = file.parent().unwrap();
+        assert_eq!(
+            PathBuf::from(value(&resolved, PIPELINE_VISION_PROMPT).0),
+            dir.join("prompts/vision.md")
+        );
+        assert_eq!(value(&resolved, PIPELINE_REASONING_PROMPT).0, "/abs/r.md");
+
+        // Relative paths from the environment are left to the working directory
+        let env = |key: &str| (key == PIPELINE_VISION_PROMPT).then(|| "vision.md".to_string());
+        let resolved = resolve(None, env, None, FULL);
+        assert_eq!(value(&resolved, PIPELINE_VISION_PROMPT).0, "vision.md");
+    }
+
+    #[test]
+    fn test_missing_values_are_reported_not_fatal() {
+        let env = |key: &str| (key == GITHUB_CLIENT_ID).then(|| "env_id".to_string());
+        let resolved = resolve(None, env, None, "");
+        let (config, issues) = validate(&resolved.raw_values());
+        assert!(config.worker_api_key.is_none());
+        assert!(issues.contains(&ConfigIssue::Missing {
+            key: WORKER_API_KEY.to_string()
+        }));
+        // A client ID alone is enough when the worker exchanges the code
+        assert!(!issues
+            .iter()
+            .any(|issue| issue.key().starts_with("GITHUB_")));
+        assert_eq!(config.github_client_id.as_deref(), Some("env_id"));
+        assert_eq!(config.token_exchange(), TokenExchange::Worker);
+    }
+
+    #[test]
+    fn test_validate_token_exchange() {
+        let base = [
+            (GITHUB_CLIENT_ID, "id"),
+            (WORKER_API_URL, "https://worker.example"),
+            (WORKER_API_KEY, "key"),
+        ];
+        let with = |extra: &[(&str, &str)]| {
+            let values: HashMap<String, String> = base
+                .iter()
+                .chain(extra)
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            validate(&values)
+        };
+
+        let (config, issues) = with(&[(GITHUB_CLIENT_SECRET, "secret")]);
+        assert!(issues.is_empty());
+        assert_eq!(config.token_exchange(), TokenExchange::Direct);
+
+        let (config, issues) = with(&[
+            (GITHUB_CLIENT_SECRET, "secret"),
+            (GITHUB_TOKEN_EXCHANGE, "Worker"),
+        ]);
+        assert!(issues.is_empty());
+        assert_eq!(config.token_exchange(), TokenExchange::Worker);
+
+        let (config, issues) = with(&[(GITHUB_TOKEN_EXCHANGE, "direct")]);
+        assert!(config.github_client_id.is_none());
+        assert_eq!(
+            issues,
+            vec![ConfigIssue::Missing {
+                key: GITHUB_CLIENT_SECRET.to_string()
+            }]
+        );
+
+        let (_, issues) = with(&[(GITHUB_TOKEN_EXCHANGE, "github")]);
+        assert_eq!(issues[0].key(), GITHUB_TOKEN_EXCHANGE);
+    }
+
+    #[test]
+    fn test_github_enterprise_urls() {
+        let validate_with = |extra: &[(&str, &str)]| {
+            let values: HashMap<String, String> = [
+                (WORKER_API_URL, "https://worker.example"),
+                (WORKER_API_KEY, "key"),
+            ]
+            .iter()
+            .chain(extra)
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+            validate(&values)
+        };
+
+        let (config, issues) = validate_with(&[]);
+        assert!(issues.is_empty());
+        assert_eq!(config.github_web_base(), "https://github.com");
+        assert_eq!(config.github_api_base(), "https://api.github.com");
+
+        let (config, issues) = validate_with(&[(GITHUB_WEB_URL, "https://ghe.example.com/")]);
+        assert!(issues.is_empty());
+        assert_eq!(config.github_web_base(), "https://ghe.example.com");
+        assert_eq!(config.github_api_base(), "https://ghe.example.com/api/v3");
+
+        let (config, issues) = validate_with(&[
+            (GITHUB_WEB_URL, "https://ghe.example.com"),
+            (GITHUB_API_URL, "https://api.ghe.example.com/v3/"),
+        ]);
+        assert!(issues.is_empty());
+        assert_eq!(config.github_api_base(), "https://api.ghe.example.com/v3");
+
+        let (config, issues) = validate_with(&[(GITHUB_API_URL, "https://ghe.example.com/api/v3")]);
+        assert_eq!(
+            issues,
+            vec![ConfigIssue::RequiredWith {
+                key: GITHUB_WEB_URL.to_string(),
+                other: GITHUB_API_URL.to_string(),
+            }]
+        );
+        assert_eq!(config.github_api_base(), "https://api.github.com");
+
+        let (_, issues) = validate_with(&[(GITHUB_WEB_URL, "ghe.example.com")]);
+        assert_eq!(issues[0].key(), GITHUB_WEB_URL);
+    }
+
+    #[test]
+    fn test_direct_profiles_replace_the_worker() {
+        let validate_with = |pairs: &[(&str, &str)]| {
+            let values: HashMap<String, String> = pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            validate(&values)
+        };
+
+        let (config, issues) = validate_with(&[
+            (QUERY_PROFILE, "OpenAI"),
+            (OPENAI_BASE_URL, "http://127.0.0.1:11434/v1/"),
+            (OPENAI_MODEL, "llava"),
+        ]);
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(config.query_profile, Some(QueryProfile::OpenAi));
+        assert_eq!(
+            join_url(config.openai_base_url.as_ref().unwrap(), "chat/completions"),
+            "http://127.0.0.1:11434/v1/chat/completions"
+        );
+        assert!(config.openai_api_key.is_none());
+        assert!(!config.openai_stream_usage);
+
+        let (config, issues) = validate_with(&[
+            (QUERY_PROFILE, "openai"),
+            (OPENAI_BASE_URL, "http://api.example.com/v1"),
+            (OPENAI_STREAM_USAGE, "yes"),
+        ]);
+        let keys: Vec<&str> = issues.iter().map(ConfigIssue::key).collect();
+        assert_eq!(keys, [OPENAI_BASE_URL, OPENAI_STREAM_USAGE, OPENAI_MODEL]);
+        assert!(!config.openai_stream_usage);
+        let (config, _) = validate_with(&[(OPENAI_STREAM_USAGE, "True")]);
+        assert!(config.openai_stream_usage);
+
+        let (_, issues) = validate_with(&[(QUERY_PROFILE, "anthropic")]);
+        let keys: Vec<&str> = issues.iter().map(ConfigIssue::key).collect();
+        assert_eq!(keys, [ANTHROPIC_API_KEY, ANTHROPIC_MODEL]);
+
+        let (config, issues) = validate_with(&[
+            (QUERY_PROFILE, "ollama"),
+            (OLLAMA_BASE_URL, "http://localhost:11434"),
+            (OLLAMA_MODEL, "llava"),
+        ]);
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(config.query_profile, Some(QueryProfile::Ollama));
+
+        let (config, issues) = validate_with(&[
+            (QUERY_PROFILE, "pipeline"),
+            (PIPELINE_VISION_PROFILE, "ollama"),
+            (PIPELINE_VISION_PROMPT, "/prompts/vision.md"),
+            (PIPELINE_REASONING_PROFILE, "worker"),
+        ]);
+        let keys: Vec<&str> = issues.iter().map(ConfigIssue::key).collect();
+        assert_eq!(keys, [PIPELINE_REASONING_PROFILE]);
+        assert!(matches!(issues[0], ConfigIssue::InvalidChoice { .. }));
+        assert_eq!(config.pipeline_vision_profile, Some(QueryProfile::Ollama));
+        assert_eq!(
+            config.pipeline_vision_prompt,
+            Some(PathBuf::from("/prompts/vision.md"))
+        );
+
+        // The worker profile still requires the worker
+        let (_, issues) = validate_with(&[(QUERY_PROFILE, "worker")]);
+        let keys: Vec<&str> = issues.iter().map(ConfigIssue::key).collect();
+        assert_eq!(keys, [WORKER_API_URL, WORKER_API_KEY]);
+
+        let (_, issues) = validate_with(&[
+            (QUERY_PROFILE, "direct"),
+            (WORKER_API_URL, "https://worker.example"),
+            (WORKER_API_KEY, "key"),
+        ]);
+        assert_eq!(issues[0].key(), QUERY_PROFILE);
+    }
+
+    #[test]
+    fn test_invalid_file_is_skipped() {
+        let file = temp_config("invalid", "WORKER_API_KEY=file_key\nnot a pair\n");
+        let resolved = resolve(Some(&file), |_| None, None, FULL);
+        std::fs::remove_file(&file).ok();
+
+        assert!(matches!(resolved.errors[..], [ConfigError::FileParse(..)]));
+        assert_eq!(value(&resolved, WORKER_API_KEY).1, ConfigSource::Embedded);
+    }
+
+    #[test]
+    fn test_missing_file_and_invalid_embedded() {
+        let file = std::env::temp_dir().join("config-test-does-not-exist.env");
+        let resolved = resolve(Some(&file), |_| None, None, "GITHUB_CLIENT_ID only_id");
+        assert!(matches!(
+            resolved.errors[..],
+            [ConfigError::EmbeddedParse(_)]
+        ));
+    }
+
+    #[test]
+    fn test_release_embedded_env_has_no_secrets() {
+        let vars = parse_env_vars(RELEASE_EMBEDDED_ENV).unwrap();
+        for field in CONFIG_SCHEMA.iter().filter(|field| field.secret) {
+            assert!(!vars.contains_key(field.key), "{} is embedded", field.key);
+        }
+    }
+
+    // build.rs strips these from the embedded `.env.production`
+    include!("../secret_keys.rs");
+
+    #[test]
+    fn test_build_script_strips_every_secret_key() {
+        let mut schema_secrets: Vec<&str> = CONFIG_SCHEMA
+            .iter()
+            .filter(|field| field.secret)
+            .map(|field| field.key)
+            .collect();
+        let mut stripped = SECRET_KEYS.to_vec();
+        schema_secrets.sort_unstable();
+        stripped.sort_unstable();
+        assert_eq!(stripped, schema_secrets);
+    }
+
+    #[test]
+    fn test_validate_normalizes_worker_url() {
+        let values = HashMap::from([
+            (
+                WORKER_API_URL.to_string(),
+                "https://worker.example/api//".to_string(),
+            ),
+            (WORKER_API_KEY.to_string(), "key".to_string()),
+        ]);
+        let (config, issues) = validate(&values);
+        assert!(issues.is_empty());
+        assert_eq!(
+            config.worker_endpoint("query").unwrap(),
+            "https://worker.example/api/query"
+        );
+
+        let values = HashMap::from([
+            (
+                WORKER_API_URL.to_string(),
+                "https://worker.example/".to_string(),
+            ),
+            (WORKER_API_KEY.to_string(), "key".to_string()),
+        ]);
+        assert_eq!(
+            validate(&values).0.worker_endpoint("/query").unwrap(),
+            "https://worker.example/query"
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_every_issue() {
+        let values = HashMap::from([
+            (GITHUB_CLIENT_SECRET.to_string(), "secret".to_string()),
+            (
+                WORKER_API_URL.to_string(),
+                "htps://worker.example".to_string(),
+            ),
+        ]);
+        let (config, issues) = validate(&values);
+        assert_eq!(config, AppConfig::default());
+        assert!(matches!(
+            &issues[..],
+            [
+                ConfigIssue::InvalidUrl { .. },
+                ConfigIssue::Missing { .. },
+                ConfigIssue::RequiredWith { .. }
+            ]
+        ));
+        assert_eq!(
+            serde_json::to_value(&issues[1]).unwrap(),
+            serde_json::json!({"kind": "missing", "key": "WORKER_API_KEY"})
+        );
+
+        for (url, insecure) in [
+            ("http://worker.example", true),
+            ("http://localhost:8787", false),
+            ("http://127.0.0.1:8787", false),
+        ] {
+            let values = HashMap::from([(WORKER_API_URL.to_string(), url.to_string())]);
+            let (_, issues) = validate(&values);
+            assert_eq!(
+                issues
+                    .iter()
+                    .any(|issue| matches!(issue, ConfigIssue::InsecureUrl { .. })),
+                insecure,
+                "{}",
+                url
+            );
+        }
+        assert!(normalize_base_url("https://user:pw@worker.example").is_err());
+        assert!(normalize_base_url("https://worker.example/?a=1").is_err());
+    }
+
+    #[test]
+    fn test_tunables() {
+        // The schema defaults are the modules' own defaults
+        let defaults: HashMap<String, String> = CONFIG_SCHEMA
+            .iter()
+            .filter_map(|field| Some((field.key.to_string(), field.default?.to_string())))
+            .collect();
+        let (config, _) = validate(&defaults);
+        assert_eq!(
+            config.query_history_byte_budget,
+            Some(crate::conversation::DEFAULT_HISTORY_BYTE_BUDGET)
+        );
+        use crate::image_pipeline as images;
+        assert_eq!(
+            config.query_image_max_dimension,
+            Some(images::DEFAULT_MAX_DIMENSION)
+        );
+        assert_eq!(config.query_image_quality, Some(images::DEFAULT_QUALITY));
+        assert_eq!(config.query_image_format, Some(OutputFormat::Webp));
+        assert_eq!(config.query_max_images, Some(images::DEFAULT_MAX_IMAGES));
+        assert_eq!(
+            config.query_max_image_payload_bytes,
+            Some(images::DEFAULT_MAX_TOTAL_IMAGE_BYTES)
+        );
+        use crate::http;
+        assert_eq!(
+            config.http_connect_timeout_ms,
+            Some(http::DEFAULT_CONNECT_TIMEOUT_MS)
+        );
+        assert_eq!(
+            config.http_read_timeout_ms,
+            Some(http::DEFAULT_READ_TIMEOUT_MS)
+        );
+        assert_eq!(
+            config.http_total_timeout_ms,
+            Some(http::DEFAULT_TOTAL_TIMEOUT_MS)
+        );
+        assert_eq!(config.http_max_retries, Some(http::DEFAULT_MAX_RETRIES));
+        assert_eq!(
+            config.http_retry_base_delay_ms,
+            Some(http::DEFAULT_RETRY_BASE_DELAY_MS)
+        );
+        assert_eq!(
+            config.http_retry_max_delay_ms,
+            Some(http::DEFAULT_RETRY_MAX_DELAY_MS)
+        );
+
+        let values = HashMap::from([
+            (QUERY_IMAGE_QUALITY.to_string(), "101".to_string()),
+            (QUERY_IMAGE_FORMAT.to_string(), " JPG ".to_string()),
+            (QUERY_IMAGE_MAX_DIMENSION.to_string(), "0".to_string()),
+            (HTTP_MAX_RETRIES.to_string(), "99999999999".to_string()),
+            (HTTP_READ_TIMEOUT_MS.to_string(), " 2500 ".to_string()),
+        ]);
+        let (config, issues) = validate(&values);
+        assert_eq!(config.query_image_format, Some(OutputFormat::Jpeg));
+        assert_eq!(config.http_read_timeout_ms, Some(2500));
+        assert_eq!(config.query_image_quality, None);
+        assert_eq!(config.query_image_max_dimension, None);
+        assert_eq!(config.http_max_retries, None); // Does not fit a u32
+        assert_eq!(
+            issues.iter().map(ConfigIssue::key).collect::<Vec<_>>(),
+            [
+                WORKER_API_URL,
+                WORKER_API_KEY,
+                QUERY_IMAGE_MAX_DIMENSION,
+                QUERY_IMAGE_QUALITY,
+                HTTP_MAX_RETRIES
+            ]
+        );
+        assert_eq!(
+            issues[3].to_string(),
+            "QUERY_IMAGE_QUALITY must be a whole number from 1 to 100, got '101'"
+        );
+    }
+
+    // --- dotenvy conformance ---
+    fn dotenvy_vars(content: &str) -> Result<HashMap<String, String>, dotenvy::Error> {
+        dotenvy::from_read_iter(content.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parser_matches_dotenvy() {
+        let cases = [
+            "A=1\nB=two\n",
+            "A=\"double\"\nB='single'\nC=plain",
+            "export A=1\nexport=\"export as key\"\nexport   B=2",
+            "A  =  spaced\nB=    \"after =\"",
+            "A=a#b\nB=a #b\nC=\"a#b\"\nD='a#b'\nE=   # comment only\nF=",
+            "# comment\n  # indented comment\n\nA=1\n",
+            "A=\"line1\nline2\"\nB='multi\nline'\nC=after",
+            "A=\"a\\nb\"\nB=a\\nb\nC=\"say \\\"hi\\\"\"\nD=s\\ ix\nE=\\$HOME",
+            "A=1\nB=${A}x\nC=$A_X\nD='$A'\nE=\"x $A y\"\nF=${A}${A}\nG=$\nH=$UNDEFINED_DOTENV_TEST_VAR",
+            "A.B=dotted\n_C=underscore",
+            "A=1\r\nB=\"crlf\"\r\n",
+            "A=first\nA=second",
+            "A=\"a\"'b'c",
+            "HOME=from_file\nB=$HOME",
+            "A=\"\"\nB=''",
+            "A=\"${A}\"\nB=x",
+            "A=\"tab\there\"",
+        ];
+        for case in cases {
+            assert_eq!(
+                parse_env_vars(case).ok(),
+                Some(dotenvy_vars(case).unwrap()),
+                "{:?}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_rejects_what_dotenvy_rejects() {
+        let cases = [
+            "A=a b",
+            "1A=x",
+            "A",
+            "=x",
+            "A=\"unterminated",
+            "A='unterminated\nB=1",
+            "A=\\",
+            "A=\"\\t\"",
+            "A=${",
+            "A=ok\nB c",
+        ];
+        for case in cases {
+            assert!(dotenvy_vars(case).is_err(), "dotenvy accepted {:?}", case);
+            assert!(parse_env_vars(case).is_err(), "accepted {:?}", case);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_report_line_and_column() {
+        let cases = [
+            ("A=1\nB=\"unterminated", 2, 3),
+            ("A=1\n\nB=\"bad \\t escape\"", 3, 8),
+            ("A=1\n  9B=x", 2, 3),
+            ("A=value trailing", 1, 9),
+        ];
+        for (case, line, column) in cases {
+            match parse_env_vars(case) {
+                Err(ConfigParseError::MalformedLine {
+                    line: l, column: c, ..
+                }) => assert_eq!((l, c), (line, column), "{:?}", case),
+                other => panic!("{:?} => {:?}", case, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// src-tauri/src/conversation.rs
+
+// --- Dependencies ---
+use crate::config;
+use serde::{Deserialize, Serialize};
+
+// --- Defaults ---
+// Byte budget for prior turns forwarded to the worker. Can be overridden with the
+// QUERY_HISTORY_BYTE_BUDGET config key or per call.
+pub const DEFAULT_HISTORY_BYTE_BUDGET: usize = 16 * 1024;
+// Rough conversion used when the caller specifies a token budget
+const BYTES_PER_TOKEN: usize = 4;
+
+// --- Message history structures ---
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ChatRole> {
+        match value {
+            "system" => Some(ChatRole::System),
+            "user" => Some(ChatRole::User),
+            "assistant" => Some(ChatRole::Assistant),
+            _ => None,
+        }
+    }
+}
+
+// One prior turn, as sent by the frontend and forwarded to the worker
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatTurn {
+    pub role: ChatRole,
+    pub text: String,
+    // Reference to the screenshot used for that turn (path or id); the image itself
+    // is not re-uploaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_ref: Option<String>,
+}
+
+impl ChatTurn {
+    fn byte_size(&self) -> usize {
+        self.text.len() + self.image_ref.as_ref().map_or(0, |r| r.len())
+    }
+}
+
+// Per-call override of the history window. Whichever limit is smaller wins.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryBudget {
+    pub max_bytes: Option<usize>,
+    pub max_tokens: Option<usize>,
+}
+
+impl HistoryBudget {
+    pub fn byte_limit(&self) -> usize {
+        let from_tokens = self
+            .max_tokens
+            .map(|tokens| tokens.saturating_mul(BYTES_PER_TOKEN));
+        match (self.max_bytes, from_tokens) {
+            (Some(bytes), Some(tokens)) => bytes.min(tokens),
+            (Some(bytes), None) => bytes,
+            (None, Some(tokens)) => tokens,
+            (None, None) => default_history_byte_budget(),
+        }
+    }
+}
+
+fn default_history_byte_budget() -> usize {
+    config::current()
+        .query_history_byte_budget
+        .unwrap_or(DEFAULT_HISTORY_BYTE_BUDGET)
+}
+
+// Keeps the most recent turns that fit in `byte_limit`.
+// Leading system turns are kept first when they fit, and the window never starts
+// with an assistant reply whose question was trimmed away.
+pub fn trim_history(history: Vec<ChatTurn>, byte_limit: usize) -> Vec<ChatTurn> {
+    let system_count = history
+        .iter()
+        .take_while(|turn| turn.role == ChatRole::System)
+        .count();
+
+    let mut remaining = byte_limit;
+    let mut kept_system = Vec::new();
+    for turn in &history[..system_count] {
+        if turn.byte_size() > remaining {
+            break;
+        }
+        remaining -= turn.byte_size();
+        kept_system.push(turn.clone());
+    }
+
+    // Walk backwards from the newest turn until the budget is used up
+    let conversation = &history[system_count..];
+    let mut start = conversation.len();
+    for (index, turn) in conversation.iter().enumerate().rev() {
+        if turn.byte_size() > remaining {
+            break;
+        }
+        remaining -= turn.byte_size();
+        start = index;
+    }
+    while start < conversation.len() && conversation[start].role == ChatRole::Assistant {
+        start += 1;
+    }
+
+    kept_system.extend_from_slice(&conversation[start..]);
+    let dropped = history.len() - kept_system.len();
+    if dropped > 0 {
+        println!(
+            "[conversation] Trimmed {} of {} history turns to fit {} bytes.",
+            dropped,
+            history.len(),
+            byte_limit
+        );
+    }
+    kept_system
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(role: ChatRole, text: &str) -> ChatTurn {
+        ChatTurn {
+            role,
+            text: text.to_string(),
+            image_ref: None,
+        }
+    }
+
+    #[test]
+    fn test_trim_history_keeps_newest_turns() {
+        let history = vec![
+            turn(ChatRole::User, "aaaa"),
+            turn(ChatRole::Assistant, "bbbb"),
+            turn(ChatRole::User, "cccc"),
+            turn(ChatRole::Assistant, "dddd"),
+        ];
+        let trimmed = trim_history(history, 9);
+        assert_eq!(
+            trimmed,
+            vec![
+                turn(ChatRole::User, "cccc"),
+                turn(ChatRole::Assistant, "dddd")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trim_history_does_not_start_with_assistant() {
+        let history = vec![
+            turn(ChatRole::User, "question one"),
+            turn(ChatRole::Assistant, "ok"),
+            turn(ChatRole::User, "q2"),
+        ];
+        assert_eq!(trim_history(history, 5), vec![turn(ChatRole::User, "q2")]);
+    }
+
+    #[test]
+    fn test_trim_history_keeps_system_prompt() {
+        let history = vec![
+            turn(ChatRole::System, "sys"),
+            turn(ChatRole::User, "old question"),
+            turn(ChatRole::User, "new"),
+        ];
+        assert_eq!(
+            trim_history(history, 8),
+            vec![turn(ChatRole::System, "sys"), turn(ChatRole::User, "new")]
+        );
+    }
+
+    #[test]
+    fn test_history_budget_uses_smallest_limit() {
+        let budget = HistoryBudget {
+            max_bytes: Some(1000),
+            max_tokens: Some(100),
+        };
+        assert_eq!(budget.byte_limit(), 400);
+        let budget = HistoryBudget {
+            max_bytes: Some(10),
+            max_tokens: None,
+        };
+        assert_eq!(budget.byte_limit(), 10);
+    }
+
+    #[test]
+    fn test_chat_turn_deserializes_camel_case() {
+        let parsed: ChatTurn =
+            serde_json::from_str(r#"{"role":"user","text":"hi","imageRef":"/tmp/a.png"}"#).unwrap();
+        assert_eq!(parsed.role, ChatRole::User);
+        assert_eq!(parsed.image_ref.as_deref(), Some("/tmp/a.png"));
+    }
+}
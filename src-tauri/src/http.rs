@@ -0,0 +1,447 @@
+// src-tauri/src/http.rs
+
+// --- Dependencies ---
+use crate::config;
+use crate::query_error::retry_after;
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use std::time::Duration;
+
+// --- Defaults ---
+// Overridable through the HTTP_* config keys
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 10_000;
+pub const DEFAULT_READ_TIMEOUT_MS: u64 = 60_000; // Longest silence between two reads
+pub const DEFAULT_TOTAL_TIMEOUT_MS: u64 = 180_000;
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+const APP_NAME: &str = "revision";
+
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 500;
+pub const DEFAULT_RETRY_MAX_DELAY_MS: u64 = 8_000;
+// A server asking us to wait longer than this is treated as a final answer
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// Set to `true` by the worker on 5xx responses it produced before calling the AI provider
+pub const RETRY_SAFE_HEADER: &str = "x-retry-safe";
+
+// Event sent before each retry so the UI can show progress
+pub const HTTP_RETRY_EVENT: &str = "http_retry";
+
+// --- Shared client ---
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpClientConfig {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub total_timeout: Duration, // Per request; can be overridden per call
+    pub user_agent: String,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        let config = config::current();
+        let millis =
+            |value: Option<u64>, default: u64| Duration::from_millis(value.unwrap_or(default));
+        HttpClientConfig {
+            connect_timeout: millis(config.http_connect_timeout_ms, DEFAULT_CONNECT_TIMEOUT_MS),
+            read_timeout: millis(config.http_read_timeout_ms, DEFAULT_READ_TIMEOUT_MS),
+            total_timeout: millis(config.http_total_timeout_ms, DEFAULT_TOTAL_TIMEOUT_MS),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+// e.g. "revision/0.1.0 (macos)"; sent on every request, GitHub rejects calls without one
+pub fn default_user_agent() -> String {
+    format!(
+        "{}/{} ({})",
+        APP_NAME,
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS
+    )
+}
+
+// One pooled client for the whole app, kept in Tauri state. Cloning is cheap and
+// shares the connection pool.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpClientConfig) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout)
+            .timeout(config.total_timeout)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+            .tcp_keepalive(TCP_KEEPALIVE)
+            .user_agent(config.user_agent.clone())
+            .build()?;
+        Ok(HttpClient { client })
+    }
+
+    // Wraps an existing client, e.g. one set up by a test against a local mock server
+    #[cfg(test)]
+    pub fn from_client(client: reqwest::Client) -> Self {
+        HttpClient { client }
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+// Applies a per-call override of the total timeout, if any
+pub fn with_timeout(builder: RequestBuilder, timeout: Option<Duration>) -> RequestBuilder {
+    match timeout {
+        Some(timeout) => builder.timeout(timeout),
+        None => builder,
+    }
+}
+
+// --- Retries ---
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        let config = config::current();
+        RetryPolicy {
+            max_retries: config.http_max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            base_delay: Duration::from_millis(
+                config
+                    .http_retry_base_delay_ms
+                    .unwrap_or(DEFAULT_RETRY_BASE_DELAY_MS),
+            ),
+            max_delay: Duration::from_millis(
+                config
+                    .http_retry_max_delay_ms
+                    .unwrap_or(DEFAULT_RETRY_MAX_DELAY_MS),
+            ),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Delay before retry number `retry` (0-based): exponential, capped, with jitter.
+    // At least half of the step is always waited so retries still spread out.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let step = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let half = step / 2;
+        let jitter_ms = rand::rng().random_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_ms)
+    }
+}
+
+// Payload of `http_retry`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryAttempt {
+    pub operation: String,
+    pub request_id: Option<String>, // Set for queries started with a request id
+    pub attempt: u32,               // 1 for the first retry
+    pub max_retries: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+}
+
+// Whether a failed response may be sent again, and how long the server asked us to wait.
+// 429 and 503 mean the request was turned away before any work was done. Other 5xx
+// may have reached the AI provider already, so a non-idempotent request is only
+// retried if the worker says so.
+pub fn retryable_status(
+    status: StatusCode,
+    headers: &HeaderMap,
+    idempotent: bool,
+) -> Option<Option<Duration>> {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            Some(retry_after(headers))
+        }
+        StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::GATEWAY_TIMEOUT
+            if idempotent || marked_retry_safe(headers) =>
+        {
+            Some(None)
+        }
+        _ => None,
+    }
+}
+
+fn marked_retry_safe(headers: &HeaderMap) -> bool {
+    headers
+        .get(RETRY_SAFE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+// A failed connect never sent anything. Timeouts and dropped connections may have,
+// so those are only retried for idempotent requests.
+fn retryable_error(err: &reqwest::Error, idempotent: bool) -> bool {
+    err.is_connect() || (idempotent && (err.is_timeout() || err.is_request()))
+}
+
+// Sends the request built by `build`, retrying transient failures according to
+// `policy`. `on_retry` runs before each wait. The last response is returned as-is,
+// successful or not, so callers keep their own status handling.
+pub async fn send_with_retry(
+    operation: &str,
+    policy: &RetryPolicy,
+    idempotent: bool,
+    build: impl Fn() -> RequestBuilder,
+    mut on_retry: impl FnMut(&RetryAttempt),
+) -> Result<Response, reqwest::Error> {
+    let mut retry = 0;
+    loop {
+        let result = build().send().await;
+        let (reason, server_delay) = match &result {
+            Ok(response) if response.status().is_success() => return result,
+            Ok(response) => {
+                match retryable_status(response.status(), response.headers(), idempotent) {
+                    Some(server_delay) => (format!("status {}", response.status()), server_delay),
+                    None => return result,
+                }
+            }
+            Err(err) if retryable_error(err, idempotent) => (err.to_string(), None),
+            Err(_) => return result,
+        };
+        if retry >= policy.max_retries {
+            return result;
+        }
+        let delay = match server_delay {
+            Some(wait) if wait > MAX_RETRY_AFTER => return result,
+            Some(wait) => wait,
+            None => policy.backoff(retry),
+        };
+
+        retry += 1;
+        let attempt = RetryAttempt {
+            operation: operation.to_string(),
+            request_id: None,
+            attempt: retry,
+            max_retries: policy.max_retries,
+            delay_ms: delay.as_millis() as u64,
+            reason,
+        };
+        println!(
+            "[{}] Attempt failed ({}); retry {}/{} in {} ms.",
+            operation, attempt.reason, attempt.attempt, attempt.max_retries, attempt.delay_ms
+        );
+        on_retry(&attempt);
+        drop(result);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode as AxumStatus, routing::post, Router};
+    use reqwest::header::{HeaderValue, RETRY_AFTER};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn test_config(total_timeout: Duration) -> HttpClientConfig {
+        HttpClientConfig {
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(5),
+            total_timeout,
+            user_agent: default_user_agent(),
+        }
+    }
+
+    // Local server whose `/slow` route takes a second and `/ua` echoes the User-Agent
+    async fn echo_server() -> String {
+        let app = Router::new()
+            .route(
+                "/slow",
+                axum::routing::get(|| async {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    "late"
+                }),
+            )
+            .route(
+                "/ua",
+                axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                    headers
+                        .get("user-agent")
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string()
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    // Local server answering 503 (with Retry-After: 0) until `failures` requests were seen
+    async fn flaky_server(failures: u32) -> (String, Arc<AtomicU32>) {
+        let hits = Arc::new(AtomicU32::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/query",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                        (
+                            AxumStatus::SERVICE_UNAVAILABLE,
+                            [("retry-after", "0")],
+                            "busy",
+                        )
+                    } else {
+                        (AxumStatus::OK, [("retry-after", "0")], "ok")
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/query", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, hits)
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        };
+        for _ in 0..20 {
+            let first = policy.backoff(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let third = policy.backoff(2);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+            let capped = policy.backoff(10);
+            assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_millis(1000));
+        }
+    }
+
+    #[test]
+    fn test_retryable_status_respects_idempotency() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(
+            retryable_status(StatusCode::TOO_MANY_REQUESTS, &headers, false),
+            Some(Some(Duration::from_secs(3)))
+        );
+
+        let headers = HeaderMap::new();
+        assert_eq!(
+            retryable_status(StatusCode::SERVICE_UNAVAILABLE, &headers, false),
+            Some(None)
+        );
+        // A 502 on a query may already have cost an AI call
+        assert_eq!(
+            retryable_status(StatusCode::BAD_GATEWAY, &headers, false),
+            None
+        );
+        assert_eq!(
+            retryable_status(StatusCode::BAD_GATEWAY, &headers, true),
+            Some(None)
+        );
+        assert_eq!(
+            retryable_status(StatusCode::BAD_REQUEST, &headers, true),
+            None
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_SAFE_HEADER, HeaderValue::from_static("true"));
+        assert_eq!(
+            retryable_status(StatusCode::INTERNAL_SERVER_ERROR, &headers, false),
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_recovers_and_reports_attempts() {
+        let (url, hits) = flaky_server(2).await;
+        let http = HttpClient::from_client(reqwest::Client::new());
+        let client = http.client();
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut attempts = Vec::new();
+        let response = send_with_retry(
+            "test",
+            &policy,
+            false,
+            || client.post(&url),
+            |attempt| attempts.push(attempt.attempt),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(attempts, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_gives_up_after_max_retries() {
+        let (url, hits) = flaky_server(10).await;
+        let http = HttpClient::from_client(reqwest::Client::new());
+        let client = http.client();
+        let policy = RetryPolicy {
+            max_retries: 1,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let response = send_with_retry("test", &policy, false, || client.post(&url), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_http_client_applies_timeouts_and_user_agent() {
+        let base = echo_server().await;
+        let http = HttpClient::new(&test_config(Duration::from_millis(200))).unwrap();
+
+        let ua = http
+            .client()
+            .get(format!("{}/ua", base))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(ua, default_user_agent());
+        assert!(ua.contains(env!("CARGO_PKG_VERSION")));
+
+        let err = http
+            .client()
+            .get(format!("{}/slow", base))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+
+        // A per-call override lifts the client-wide limit
+        let request = with_timeout(
+            http.client().get(format!("{}/slow", base)),
+            Some(Duration::from_secs(5)),
+        );
+        assert_eq!(request.send().await.unwrap().text().await.unwrap(), "late");
+    }
+}
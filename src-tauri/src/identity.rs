@@ -0,0 +1,179 @@
+// src-tauri/src/identity.rs
+
+// --- Dependencies ---
+use crate::auth::{
+    exchange_code_for_token, fetch_github_user_profile, github_authorize_url, AuthCode, AuthError,
+    GithubAuthConfig,
+};
+use crate::config::{self, GITHUB_CLIENT_ID, OIDC_ISSUER};
+use crate::oidc::{OidcProvider, OIDC_PROVIDER_ID};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+// --- Identity providers ---
+// Everything the login flows in `auth` need from a provider: where to send the user,
+// how to redeem the code, and how to read the signed-in user's profile. Providers are
+// built from the current config each time, so `reload_config` takes effect on the next
+// login without restarting.
+
+pub const GITHUB_PROVIDER_ID: &str = "github";
+
+// The provider-neutral profile kept in the session and sent to the UI.
+// Field names match the old GitHub profile so existing UI code keeps working.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserProfile {
+    pub provider: String,
+    pub id: String, // Stable id at the provider: GitHub's numeric id, the OIDC `sub`
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+// Parameters of one authorization request
+pub struct AuthorizeParams<'a> {
+    pub redirect_uri: &'a str,
+    pub state: &'a str,
+    pub code_challenge: &'a str, // S256
+    pub nonce: &'a str,          // Echoed back in the OIDC ID token
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenSet {
+    pub access_token: String,
+    pub subject: Option<String>, // From a validated ID token; the profile must match it
+}
+
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    fn id(&self) -> &str;
+
+    fn display_name(&self) -> String;
+
+    // GitHub-only for now; see `login_with_github_device`
+    fn supports_device_flow(&self) -> bool {
+        false
+    }
+
+    async fn authorize_url(
+        &self,
+        client: &reqwest::Client,
+        params: &AuthorizeParams<'_>,
+    ) -> Result<String, AuthError>;
+
+    async fn exchange_code(
+        &self,
+        client: &reqwest::Client,
+        code: &AuthCode,
+        redirect_uri: &str,
+        nonce: &str,
+    ) -> Result<TokenSet, AuthError>;
+
+    // Also used to refresh a restored session. A rejected token is `AuthError::TokenRejected`.
+    async fn fetch_profile(
+        &self,
+        client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<UserProfile, AuthError>;
+}
+
+// --- GitHub ---
+pub struct GithubProvider {
+    config: GithubAuthConfig,
+}
+
+impl GithubProvider {
+    pub fn new(config: GithubAuthConfig) -> Self {
+        GithubProvider { config }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for GithubProvider {
+    fn id(&self) -> &str {
+        GITHUB_PROVIDER_ID
+    }
+
+    fn display_name(&self) -> String {
+        "GitHub".to_string()
+    }
+
+    fn supports_device_flow(&self) -> bool {
+        true
+    }
+
+    async fn authorize_url(
+        &self,
+        _client: &reqwest::Client,
+        params: &AuthorizeParams<'_>,
+    ) -> Result<String, AuthError> {
+        Ok(github_authorize_url(&self.config, params))
+    }
+
+    // GitHub issues no ID token; the nonce is unused
+    async fn exchange_code(
+        &self,
+        client: &reqwest::Client,
+        code: &AuthCode,
+        _redirect_uri: &str,
+        _nonce: &str,
+    ) -> Result<TokenSet, AuthError> {
+        let token = exchange_code_for_token(client, &self.config, code).await?;
+        Ok(TokenSet {
+            access_token: token.access_token,
+            subject: None,
+        })
+    }
+
+    async fn fetch_profile(
+        &self,
+        client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<UserProfile, AuthError> {
+        Ok(
+            fetch_github_user_profile(client, &self.config.endpoints, access_token)
+                .await?
+                .into(),
+        )
+    }
+}
+
+// --- Registry ---
+// The provider with this id, or why it can't be used
+pub fn provider(id: &str) -> Result<Box<dyn IdentityProvider>, AuthError> {
+    let config = config::current();
+    match id {
+        GITHUB_PROVIDER_ID if config.github_client_id.is_some() => {
+            Ok(Box::new(GithubProvider::new(GithubAuthConfig::current())))
+        }
+        GITHUB_PROVIDER_ID => Err(AuthError::InternalError(config::describe_issues(
+            GITHUB_CLIENT_ID,
+        ))),
+        OIDC_PROVIDER_ID => OidcProvider::from_config(&config)
+            .map(|provider| Box::new(provider) as Box<dyn IdentityProvider>)
+            .ok_or_else(|| AuthError::InternalError(config::describe_issues(OIDC_ISSUER))),
+        _ => Err(AuthError::UnknownProvider(id.to_string())),
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityProviderInfo {
+    pub id: String,
+    pub name: String,
+    pub device_flow: bool,
+}
+
+// Providers that are configured and can be used to sign in
+#[tauri::command]
+pub fn list_identity_providers() -> Vec<IdentityProviderInfo> {
+    [GITHUB_PROVIDER_ID, OIDC_PROVIDER_ID]
+        .iter()
+        .filter_map(|id| provider(id).ok())
+        .map(|provider| IdentityProviderInfo {
+            id: provider.id().to_string(),
+            name: provider.display_name(),
+            device_flow: provider.supports_device_flow(),
+        })
+        .collect()
+}
@@ -0,0 +1,464 @@
+// src-tauri/src/image_pipeline.rs
+
+// --- Dependencies ---
+use crate::config;
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{ImageError as DecodeError, LimitErrorKind};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader, Limits};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use thiserror::Error;
+
+// --- Defaults ---
+// Overridable through the QUERY_IMAGE_* and QUERY_MAX_* config keys or per call
+pub const DEFAULT_MAX_DIMENSION: u32 = 1600;
+pub const DEFAULT_QUALITY: u8 = 80;
+pub const DEFAULT_MAX_IMAGES: usize = 4;
+pub const DEFAULT_MAX_TOTAL_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+// --- Input limits ---
+// Checked before decoding so a huge or hostile file is rejected without allocating for it
+pub const MAX_IMAGE_FILE_BYTES: u64 = 25 * 1024 * 1024;
+// libwebp rejects a larger side, and a source this size may be re-encoded unscaled
+pub const MAX_SOURCE_DIMENSION: u32 = WEBP_MAX_DIMENSION;
+const WEBP_MAX_DIMENSION: u32 = 16_383;
+const MAX_SOURCE_PIXELS: u64 = 100_000_000;
+// Formats the capture tools and the worker's vision models can handle
+const SUPPORTED_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+];
+
+// Event sent to the calling window once a screenshot has been prepared for upload
+pub const IMAGE_PREPARED_EVENT: &str = "query_image_prepared";
+
+// --- Error handling ---
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+pub enum ImageError {
+    #[error("Failed to read image file: {0}")]
+    ReadFailed(String),
+    #[error("Image file is empty")]
+    Empty,
+    #[error("Unsupported image format: {0}")]
+    UnsupportedFormat(String),
+    #[error("Image file is corrupt: {0}")]
+    Corrupt(String),
+    #[error("Image file is {0} bytes, the limit is {1} bytes")]
+    FileTooLarge(u64, u64),
+    #[error("Image is {0}x{1} pixels, which exceeds the allowed dimensions")]
+    DimensionsTooLarge(u32, u32),
+    #[error("Image has invalid dimensions {0}x{1}")]
+    InvalidDimensions(u32, u32),
+    #[error("Failed to encode image: {0}")]
+    EncodeFailed(String),
+    #[error("Too many images: {0} given, at most {1} allowed per query")]
+    TooManyImages(usize, usize),
+    #[error("Skipped: images would exceed the {0} byte limit per query")]
+    TotalSizeExceeded(usize),
+}
+
+impl From<DecodeError> for ImageError {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            DecodeError::Unsupported(e) => ImageError::UnsupportedFormat(e.to_string()),
+            DecodeError::Limits(e) if matches!(e.kind(), LimitErrorKind::DimensionError) => {
+                ImageError::DimensionsTooLarge(0, 0)
+            }
+            other => ImageError::Corrupt(other.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+
+    pub(crate) const CHOICES: &'static str = "webp, jpeg";
+
+    pub(crate) fn parse(value: &str) -> Option<OutputFormat> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "webp" => Some(OutputFormat::Webp),
+            _ => None,
+        }
+    }
+}
+
+// How a screenshot should be shrunk before upload. Missing fields use the defaults.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImageOptions {
+    pub max_dimension: u32, // Longest side in pixels; larger images are scaled down
+    pub quality: u8,        // 1-100, used by both encoders
+    pub format: OutputFormat,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        let config = config::current();
+        ImageOptions {
+            max_dimension: config
+                .query_image_max_dimension
+                .unwrap_or(DEFAULT_MAX_DIMENSION),
+            quality: config.query_image_quality.unwrap_or(DEFAULT_QUALITY),
+            format: config.query_image_format.unwrap_or(OutputFormat::Webp),
+        }
+    }
+}
+
+// Caps applied to all images of one query. Missing fields use the defaults.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImageLimits {
+    pub max_images: usize,
+    pub max_total_bytes: usize, // Sum of the encoded data URLs sent to the worker
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        let config = config::current();
+        ImageLimits {
+            max_images: config.query_max_images.unwrap_or(DEFAULT_MAX_IMAGES),
+            max_total_bytes: config
+                .query_max_image_payload_bytes
+                .unwrap_or(DEFAULT_MAX_TOTAL_IMAGE_BYTES),
+        }
+    }
+}
+
+impl ImageLimits {
+    pub fn check_count(&self, count: usize) -> Result<(), ImageError> {
+        if count > self.max_images {
+            return Err(ImageError::TooManyImages(count, self.max_images));
+        }
+        Ok(())
+    }
+}
+
+// Sizes before and after processing, reported back to the UI
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageReport {
+    pub original_bytes: usize,
+    pub final_bytes: usize,
+    pub original_width: u32,
+    pub original_height: u32,
+    pub final_width: u32,
+    pub final_height: u32,
+    pub mime_type: String,
+    pub reencoded: bool, // False when the original file was smaller and sent as-is
+}
+
+// What happened to one image of a query
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ImageOutcome {
+    Prepared(ImageReport),
+    Failed { error: ImageError },
+}
+
+// Payload of `query_image_prepared`, sent once per image. `request_id` is set when the
+// query was given one; `index` is the position of the image in the query.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePreparedPayload {
+    pub request_id: Option<String>,
+    pub index: usize,
+    pub path: String,
+    #[serde(flatten)]
+    pub outcome: ImageOutcome,
+}
+
+pub struct PreparedImage {
+    pub bytes: Vec<u8>,
+    pub mime_type: String,
+    pub report: ImageReport,
+}
+
+// Rejects a file by its size alone, before it is read into memory
+pub fn check_file_size(bytes: u64) -> Result<(), ImageError> {
+    match bytes {
+        0 => Err(ImageError::Empty),
+        n if n > MAX_IMAGE_FILE_BYTES => Err(ImageError::FileTooLarge(n, MAX_IMAGE_FILE_BYTES)),
+        _ => Ok(()),
+    }
+}
+
+// Identifies the format from the file header, ignoring the file name
+pub fn sniff_format(bytes: &[u8]) -> Result<ImageFormat, ImageError> {
+    if bytes.is_empty() {
+        return Err(ImageError::Empty);
+    }
+    let format = image::guess_format(bytes)
+        .map_err(|_| ImageError::UnsupportedFormat("not a recognised image file".to_string()))?;
+    if !SUPPORTED_FORMATS.contains(&format) {
+        return Err(ImageError::UnsupportedFormat(
+            format.to_mime_type().to_string(),
+        ));
+    }
+    Ok(format)
+}
+
+// Checks the header-declared dimensions against the limits without decoding pixel data
+pub fn validate_image(bytes: &[u8]) -> Result<(ImageFormat, u32, u32), ImageError> {
+    check_file_size(bytes.len() as u64)?;
+    let format = sniff_format(bytes)?;
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format).into_dimensions()?;
+    if width == 0 || height == 0 {
+        return Err(ImageError::InvalidDimensions(width, height));
+    }
+    if width > MAX_SOURCE_DIMENSION
+        || height > MAX_SOURCE_DIMENSION
+        || u64::from(width) * u64::from(height) > MAX_SOURCE_PIXELS
+    {
+        return Err(ImageError::DimensionsTooLarge(width, height));
+    }
+    Ok((format, width, height))
+}
+
+// Validates, decodes, downscales and re-encodes a screenshot. CPU heavy: call from a
+// blocking task.
+pub fn prepare_image(
+    original: Vec<u8>,
+    options: &ImageOptions,
+) -> Result<PreparedImage, ImageError> {
+    let (format, original_width, original_height) = validate_image(&original)?;
+
+    let mut reader = ImageReader::with_format(Cursor::new(&original), format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    reader.limits(limits);
+    let decoded = reader.decode().map_err(|e| match ImageError::from(e) {
+        ImageError::DimensionsTooLarge(..) => {
+            ImageError::DimensionsTooLarge(original_width, original_height)
+        }
+        other => other,
+    })?;
+
+    let max_dimension = match options.format {
+        OutputFormat::Webp => options.max_dimension.clamp(1, WEBP_MAX_DIMENSION),
+        OutputFormat::Jpeg => options.max_dimension.max(1),
+    };
+    let needs_resize = original_width > max_dimension || original_height > max_dimension;
+    let resized = if needs_resize {
+        // `resize` keeps the aspect ratio and fits the image inside the bounds
+        decoded.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    } else {
+        decoded
+    };
+    let (final_width, final_height) = resized.dimensions();
+
+    let quality = options.quality.clamp(1, 100);
+    let encoded = encode(&resized, options.format, quality)?;
+    let original_bytes = original.len();
+
+    // Re-encoding a small, already compressed file can make it bigger
+    let (bytes, mime_type, reencoded) = if !needs_resize && encoded.len() >= original_bytes {
+        (original, format.to_mime_type().to_string(), false)
+    } else {
+        (encoded, options.format.mime_type().to_string(), true)
+    };
+
+    let report = ImageReport {
+        original_bytes,
+        final_bytes: bytes.len(),
+        original_width,
+        original_height,
+        final_width,
+        final_height,
+        mime_type: mime_type.clone(),
+        reencoded,
+    };
+    Ok(PreparedImage {
+        bytes,
+        mime_type,
+        report,
+    })
+}
+
+fn encode(image: &DynamicImage, format: OutputFormat, quality: u8) -> Result<Vec<u8>, ImageError> {
+    let mut buffer = Vec::new();
+    match format {
+        OutputFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let rgb = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut buffer, quality)
+                .encode_image(&rgb)
+                .map_err(|e| ImageError::EncodeFailed(e.to_string()))?;
+        }
+        OutputFormat::Webp => {
+            let rgba = image.to_rgba8();
+            // `Encoder::encode` unwraps libwebp's error; keep it an error instead
+            let encoded = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                .encode_simple(false, quality as f32)
+                .map_err(|e| ImageError::EncodeFailed(format!("{:?}", e)))?;
+            buffer.extend_from_slice(&encoded);
+        }
+    }
+    Ok(buffer)
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_prepare_image_downscales_to_max_dimension() {
+        let options = ImageOptions {
+            max_dimension: 400,
+            quality: 70,
+            format: OutputFormat::Jpeg,
+        };
+        let prepared = prepare_image(png_bytes(1200, 600), &options).unwrap();
+        assert_eq!(prepared.mime_type, "image/jpeg");
+        assert_eq!(
+            (prepared.report.final_width, prepared.report.final_height),
+            (400, 200)
+        );
+        assert_eq!(
+            (
+                prepared.report.original_width,
+                prepared.report.original_height
+            ),
+            (1200, 600)
+        );
+        assert!(prepared.report.reencoded);
+        assert_eq!(
+            image::guess_format(&prepared.bytes).unwrap(),
+            ImageFormat::Jpeg
+        );
+    }
+
+    #[test]
+    fn test_prepare_image_webp_output() {
+        let options = ImageOptions {
+            max_dimension: 100,
+            quality: 60,
+            format: OutputFormat::Webp,
+        };
+        let prepared = prepare_image(png_bytes(300, 300), &options).unwrap();
+        assert_eq!(prepared.mime_type, "image/webp");
+        assert_eq!(
+            image::guess_format(&prepared.bytes).unwrap(),
+            ImageFormat::WebP
+        );
+    }
+
+    #[test]
+    fn test_image_limits_and_outcome_payload() {
+        let limits = ImageLimits {
+            max_images: 2,
+            max_total_bytes: 1024,
+        };
+        assert!(limits.check_count(2).is_ok());
+        assert_eq!(limits.check_count(3), Err(ImageError::TooManyImages(3, 2)));
+
+        let payload = ImagePreparedPayload {
+            request_id: None,
+            index: 1,
+            path: "/tmp/missing.png".to_string(),
+            outcome: ImageOutcome::Failed {
+                error: ImageError::ReadFailed("not found".to_string()),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            serde_json::json!({
+                "requestId": null,
+                "index": 1,
+                "path": "/tmp/missing.png",
+                "status": "failed",
+                "error": { "ReadFailed": "not found" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_sniff_format_ignores_file_name() {
+        assert_eq!(sniff_format(&png_bytes(4, 4)).unwrap(), ImageFormat::Png);
+        assert_eq!(
+            sniff_format(b"%PDF-1.7 not an image"),
+            Err(ImageError::UnsupportedFormat(
+                "not a recognised image file".to_string()
+            ))
+        );
+        assert_eq!(sniff_format(&[]), Err(ImageError::Empty));
+    }
+
+    #[test]
+    fn test_prepare_image_rejects_corrupt_and_oversized_images() {
+        let mut truncated = png_bytes(64, 64);
+        truncated.truncate(truncated.len() / 2);
+        assert!(matches!(
+            prepare_image(truncated, &ImageOptions::default()),
+            Err(ImageError::Corrupt(_))
+        ));
+
+        // Cheap to encode, but wider than any screen the app would capture
+        assert_eq!(
+            validate_image(&png_bytes(20_000, 1)),
+            Err(ImageError::DimensionsTooLarge(20_000, 1))
+        );
+
+        assert_eq!(
+            check_file_size(MAX_IMAGE_FILE_BYTES + 1),
+            Err(ImageError::FileTooLarge(
+                MAX_IMAGE_FILE_BYTES + 1,
+                MAX_IMAGE_FILE_BYTES
+            ))
+        );
+    }
+
+    #[test]
+    fn test_webp_dimension_limit() {
+        assert!(validate_image(&png_bytes(WEBP_MAX_DIMENSION, 1)).is_ok());
+        assert_eq!(
+            validate_image(&png_bytes(WEBP_MAX_DIMENSION + 1, 1)),
+            Err(ImageError::DimensionsTooLarge(WEBP_MAX_DIMENSION + 1, 1))
+        );
+
+        // The widest accepted source, with no downscaling asked for
+        let options = ImageOptions {
+            max_dimension: u32::MAX,
+            quality: 80,
+            format: OutputFormat::Webp,
+        };
+        let prepared = prepare_image(png_bytes(WEBP_MAX_DIMENSION, 1), &options).unwrap();
+        assert_eq!(prepared.report.final_width, WEBP_MAX_DIMENSION);
+
+        // One pixel more is an error, not a panic in the encoder
+        let too_wide = DynamicImage::ImageRgba8(RgbaImage::new(WEBP_MAX_DIMENSION + 1, 1));
+        assert!(matches!(
+            encode(&too_wide, OutputFormat::Webp, 80),
+            Err(ImageError::EncodeFailed(_))
+        ));
+    }
+}
@@ -2,21 +2,78 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 // Declare modules
+mod anthropic;
 mod auth;
+mod capture;
+mod config;
+mod conversation;
+mod http;
+mod identity;
+mod image_pipeline;
+#[cfg(test)]
+mod mock_server;
+mod oidc;
+mod ollama;
+mod openai;
+mod pipeline;
+mod provider;
+mod query_error;
+mod query_registry;
+mod secrets;
+mod session;
+mod store;
+mod stream;
 
 // Use necessary items
 #[cfg(debug_assertions)]
 use auth::AuthServerState;
 use auth::{
-    get_worker_api_key, get_worker_api_url, login_with_github, AuthError, PendingAuthState,
+    cancel_device_login, login_with_github, login_with_github_device, login_with_provider,
+    pending_error_events, AuthError, DeviceLoginState, PendingAuthState,
 };
 use base64::{engine::general_purpose::STANDARD, Engine as _}; // Import base64 Engine trait
+use capture::{crop_screenshot_region, crop_screenshot_to_window};
+use config::{
+    get_config_status, reload_config, validate_config, QueryProfile, CONFIG_FILE_NAME,
+    WORKER_API_KEY, WORKER_API_URL,
+};
+use conversation::{trim_history, ChatRole, ChatTurn, HistoryBudget};
 use dotenvy::dotenv;
+use http::{
+    send_with_retry, with_timeout, HttpClient, HttpClientConfig, RetryAttempt, RetryPolicy,
+    HTTP_RETRY_EVENT,
+};
+use identity::list_identity_providers;
+use image_pipeline::{
+    check_file_size, prepare_image, ImageError, ImageLimits, ImageOptions, ImageOutcome,
+    ImagePreparedPayload, ImageReport, IMAGE_PREPARED_EVENT,
+};
+use pipeline::{Pipeline, PipelineAnswer, PipelineQuery, StageReport, PIPELINE_STAGE_EVENT};
+use provider::{list_models, list_providers, Completion, Provider, ProviderQuery};
+use query_error::QueryError;
+use query_registry::{
+    cancel_queries_for_window, cancel_query_by_id, register_query, QueryRegistryState,
+};
+use secrets::{
+    clear_secret, list_secrets, rotate_secret, set_secret, KeyringBackend, SecretStore,
+    SecretStoreState,
+};
 use serde::{Deserialize, Serialize}; // Add Serialize, Deserialize
+use session::{get_session, logout, refresh_profile, SessionManager, SessionState};
 use std::collections::HashMap;
-use tauri::{AppHandle, Emitter, Manager, Runtime, State}; // Ensure AppHandle is imported
+use std::time::Duration;
+use store::{
+    append_message, create_conversation, delete_conversation, get_conversation_messages,
+    list_conversations, rename_conversation, search_conversations, search_messages,
+    ConversationStore, STORE_FILE_NAME,
+};
+use stream::{
+    parse_stream_data, QueryStreamEvent, QueryStreamPayload, SseDecoder, StreamChunk, Utf8Decoder,
+    QUERY_STREAM_EVENT,
+};
+use tauri::{Emitter, Manager, Runtime, State, WebviewWindow, WindowEvent};
 use tauri_plugin_deep_link::DeepLinkExt;
-use tokio::fs::read; // Import tokio fs::read
+use tokio::fs::{metadata, read}; // Import tokio fs::read
 use url::Url;
 
 // --- Configuration ---
@@ -25,6 +82,11 @@ fn get_production_callback_base() -> &'static str {
     "revision://github/callback"
 }
 
+// Streams stay open while the answer is generated, so they get a longer total timeout
+// than the client default unless the caller sets one. Stalls are still caught by the
+// client's read timeout.
+const STREAM_TOTAL_TIMEOUT: Duration = Duration::from_secs(600);
+
 // Define the greet command
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -36,93 +98,261 @@ fn greet(name: &str) -> String {
 struct WorkerQueryRequest<'a> {
     text: &'a str,
     #[serde(rename = "base64ImageDataUrl")] // Match worker expected field name
-    base64_image_data_url: Option<String>, // Optional image data URL
+    base64_image_data_url: Option<String>, // Optional image data URL, single-image queries
+    #[serde(rename = "base64ImageDataUrls", skip_serializing_if = "Vec::is_empty")]
+    base64_image_data_urls: Vec<String>, // Used instead when the query has several images
+    #[serde(skip_serializing_if = "<[_]>::is_empty")] // Prior turns, oldest first
+    messages: &'a [ChatTurn],
+    #[serde(skip_serializing_if = "std::ops::Not::not")] // Only sent when asking for a stream
+    stream: bool,
+}
+
+impl<'a> WorkerQueryRequest<'a> {
+    fn new(text: &'a str, mut images: Vec<String>, messages: &'a [ChatTurn], stream: bool) -> Self {
+        // Keep single-image payloads in the original shape so older workers still work
+        let single = if images.len() == 1 {
+            images.pop()
+        } else {
+            None
+        };
+        WorkerQueryRequest {
+            text,
+            base64_image_data_url: single,
+            base64_image_data_urls: images,
+            messages,
+            stream,
+        }
+    }
 }
 
 #[derive(Deserialize)]
 struct WorkerQueryResponse {
     ai_text: String, // Expecting this field from the worker
-                     // Add other fields if the worker sends more data back
+    #[serde(default)] // What the vision model saw, if the worker reports it
+    vision_description: Option<String>,
 }
 
-// --- Error type for the new command ---
-// Using String error for simplicity, could define a more specific enum
-type CommandError = String;
-
-// --- New Tauri Command: send_query_to_worker ---
-#[tauri::command]
-async fn send_query_to_worker(
+// Everything the user supplied for one query
+struct QueryInput {
     text: String,
-    image_path: Option<String>, // Make image path optional
-    _app_handle: AppHandle,     // Keep if needed for state or events, otherwise remove
-) -> Result<String, CommandError> {
-    println!(
-        "[send_query_to_worker] Received query: '{}', Image path: {:?}",
-        text, image_path
-    );
+    image_paths: Vec<String>, // In the order the images are sent, empty paths removed
+    history: Vec<ChatTurn>,   // Already trimmed to the history budget
+    image_options: ImageOptions,
+    timeout: Option<Duration>, // Overrides the HTTP client's total timeout
+}
 
-    let mut base64_data_url: Option<String> = None;
+impl QueryInput {
+    fn new(
+        text: String,
+        image_path: Option<String>,
+        image_paths: Option<Vec<String>>,
+        history: Option<Vec<ChatTurn>>,
+        history_budget: Option<HistoryBudget>,
+        image_options: Option<ImageOptions>,
+        timeout_ms: Option<u64>,
+    ) -> Self {
+        let byte_limit = history_budget.unwrap_or_default().byte_limit();
+        QueryInput {
+            text,
+            image_paths: image_path
+                .into_iter()
+                .chain(image_paths.unwrap_or_default())
+                .filter(|path| !path.is_empty())
+                .collect(),
+            history: trim_history(history.unwrap_or_default(), byte_limit),
+            image_options: image_options.unwrap_or_default(),
+            timeout: timeout_ms.map(Duration::from_millis),
+        }
+    }
 
-    // 1. Read and Encode Image (if path is provided)
-    if let Some(path) = image_path {
-        if !path.is_empty() {
-            println!(
-                "[send_query_to_worker] Attempting to read image file: {}",
-                path
-            );
-            match read(&path).await {
-                Ok(image_bytes) => {
-                    println!(
-                        "[send_query_to_worker] Read {} bytes from image file.",
-                        image_bytes.len()
-                    );
-                    // Determine MIME type (simple approach based on extension)
-                    let mime_type = match std::path::Path::new(&path)
-                        .extension()
-                        .and_then(std::ffi::OsStr::to_str)
-                    {
-                        Some("png") => "image/png",
-                        Some("jpg") | Some("jpeg") => "image/jpeg",
-                        Some("webp") => "image/webp",
-                        Some("gif") => "image/gif",
-                        _ => "image/png", // Default or unknown
-                    };
-                    println!("[send_query_to_worker] Detected MIME type: {}", mime_type);
-
-                    let base64_encoded = STANDARD.encode(&image_bytes);
-                    println!(
-                        "[send_query_to_worker] Encoded image to base64 ({} chars)",
-                        base64_encoded.len()
-                    );
-                    base64_data_url = Some(format!("data:{};base64,{}", mime_type, base64_encoded));
-                }
-                Err(e) => {
-                    let err_msg = format!("Failed to read image file '{}': {}", path, e);
-                    eprintln!("[send_query_to_worker] Error: {}", err_msg);
-                    return Err(err_msg);
+    // First screenshot, saved with the user's turn in the conversation store
+    fn first_image_path(&self) -> Option<&str> {
+        self.image_paths.first().map(String::as_str)
+    }
+}
+
+// --- Error type for the query commands ---
+// Tagged JSON on the frontend side, see `query_error.rs`
+type CommandError = QueryError;
+
+// --- Shared helpers for the query commands ---
+
+// Loads every screenshot of the query and returns their `data:` URLs in order.
+// Each image gets a `query_image_prepared` event; one that can't be read or doesn't
+// fit in the payload limit is reported there and skipped. The query only fails when
+// there are too many images or none of them could be attached.
+async fn load_image_data_urls(
+    input: &QueryInput,
+    request_id: Option<&str>,
+    window: &WebviewWindow,
+    log_prefix: &str,
+) -> Result<Vec<String>, CommandError> {
+    if input.image_paths.is_empty() {
+        println!("[{}] No image path provided.", log_prefix);
+        return Ok(Vec::new());
+    }
+    let limits = ImageLimits::default();
+    limits.check_count(input.image_paths.len()).map_err(|e| {
+        eprintln!("[{}] Error: {}", log_prefix, e);
+        QueryError::from(e)
+    })?;
+
+    let mut data_urls = Vec::new();
+    let mut total_bytes = 0;
+    let mut failures = Vec::new();
+    for (index, path) in input.image_paths.iter().enumerate() {
+        let outcome = match load_image_data_url(path, &input.image_options, log_prefix).await {
+            Ok((data_url, _)) if total_bytes + data_url.len() > limits.max_total_bytes => {
+                ImageOutcome::Failed {
+                    error: ImageError::TotalSizeExceeded(limits.max_total_bytes),
                 }
             }
-        } else {
-            println!("[send_query_to_worker] Received empty image path, skipping image.");
+            Ok((data_url, report)) => {
+                total_bytes += data_url.len();
+                data_urls.push(data_url);
+                ImageOutcome::Prepared(report)
+            }
+            Err(error) => ImageOutcome::Failed { error },
+        };
+        if let ImageOutcome::Failed { error } = &outcome {
+            eprintln!(
+                "[{}] Error: image {} ({}): {}",
+                log_prefix,
+                index + 1,
+                path,
+                error
+            );
+            failures.push((index, error.clone()));
         }
-    } else {
-        println!("[send_query_to_worker] No image path provided.");
+        emit_image_outcome(window, request_id, index, path, outcome);
     }
 
-    // 2. Prepare Request for Worker
-    let worker_url = format!("{}/query", get_worker_api_url()); // Append /query path
-    let worker_key = get_worker_api_key();
+    if data_urls.is_empty() {
+        let summary: Vec<String> = failures
+            .iter()
+            .map(|(index, error)| format!("image {}: {}", index + 1, error))
+            .collect();
+        return Err(QueryError::ImageReadFailed {
+            message: format!(
+                "None of the images could be attached: {}",
+                summary.join("; ")
+            ),
+            errors: failures.into_iter().map(|(_, error)| error).collect(),
+        });
+    }
+    Ok(data_urls)
+}
 
-    if worker_key.is_empty() {
-        let err_msg = "Worker API Key is not configured.".to_string();
-        eprintln!("[send_query_to_worker] Error: {}", err_msg);
-        return Err(err_msg);
+// Reads one screenshot, shrinks it and turns it into a `data:` URL for the worker.
+// The size report is returned alongside so the caller can forward it to the UI.
+async fn load_image_data_url(
+    path: &str,
+    image_options: &ImageOptions,
+    log_prefix: &str,
+) -> Result<(String, ImageReport), ImageError> {
+    println!("[{}] Attempting to read image file: {}", log_prefix, path);
+    let image_bytes = read_image_file(path).await?;
+    println!(
+        "[{}] Read {} bytes from image file.",
+        log_prefix,
+        image_bytes.len()
+    );
+
+    // Validation, decoding and resizing are CPU bound; keep them off the async runtime.
+    // The MIME type comes from the file header, not the extension.
+    let options = *image_options;
+    let prepared = tokio::task::spawn_blocking(move || prepare_image(image_bytes, &options))
+        .await
+        .map_err(|e| ImageError::EncodeFailed(format!("image task failed: {}", e)))??;
+    let report = prepared.report;
+    println!(
+        "[{}] Prepared image: {}x{} ({} bytes) -> {}x{} {} ({} bytes)",
+        log_prefix,
+        report.original_width,
+        report.original_height,
+        report.original_bytes,
+        report.final_width,
+        report.final_height,
+        report.mime_type,
+        report.final_bytes
+    );
+
+    let base64_encoded = STANDARD.encode(&prepared.bytes);
+    println!(
+        "[{}] Encoded image to base64 ({} chars)",
+        log_prefix,
+        base64_encoded.len()
+    );
+    let data_url = format!("data:{};base64,{}", prepared.mime_type, base64_encoded);
+    Ok((data_url, report))
+}
+
+// Checks the size on disk before reading, so oversized files are never loaded
+async fn read_image_file(path: &str) -> Result<Vec<u8>, ImageError> {
+    let metadata = metadata(path)
+        .await
+        .map_err(|e| ImageError::ReadFailed(format!("'{}': {}", path, e)))?;
+    check_file_size(metadata.len())?;
+    read(path)
+        .await
+        .map_err(|e| ImageError::ReadFailed(format!("'{}': {}", path, e)))
+}
+
+// Tells the calling window how much a screenshot was shrunk, or why it was skipped
+fn emit_image_outcome(
+    window: &WebviewWindow,
+    request_id: Option<&str>,
+    index: usize,
+    path: &str,
+    outcome: ImageOutcome,
+) {
+    let payload = ImagePreparedPayload {
+        request_id: request_id.map(str::to_string),
+        index,
+        path: path.to_string(),
+        outcome,
+    };
+    if let Err(e) = window.emit_to(window.label(), IMAGE_PREPARED_EVENT, payload) {
+        eprintln!("[emit_image_outcome] Failed to emit image outcome: {}", e);
     }
-    if get_worker_api_url().is_empty() {
-        let err_msg = "Worker API URL is not configured.".to_string();
-        eprintln!("[send_query_to_worker] Error: {}", err_msg);
-        return Err(err_msg);
+}
+
+// Tells the calling window that a worker call is being retried
+fn emit_retry_progress(window: &WebviewWindow, request_id: Option<&str>, attempt: &RetryAttempt) {
+    let payload = RetryAttempt {
+        request_id: request_id.map(str::to_string),
+        ..attempt.clone()
+    };
+    if let Err(e) = window.emit_to(window.label(), HTTP_RETRY_EVENT, payload) {
+        eprintln!("[emit_retry_progress] Failed to emit retry progress: {}", e);
     }
+}
+
+// Tells the calling window that a pipeline stage started, finished or failed
+fn emit_stage_report(window: &WebviewWindow, request_id: Option<&str>, report: StageReport) {
+    let payload = StageReport {
+        request_id: request_id.map(str::to_string),
+        ..report
+    };
+    if let Err(e) = window.emit_to(window.label(), PIPELINE_STAGE_EVENT, payload) {
+        eprintln!("[emit_stage_report] Failed to emit stage report: {}", e);
+    }
+}
+
+// Resolves the worker /query URL and key, failing if either is not configured
+fn worker_query_endpoint(log_prefix: &str) -> Result<(String, String), CommandError> {
+    let app_config = config::current();
+
+    let Some(worker_key) = app_config.worker_api_key.clone() else {
+        let err_msg = config::describe_issues(WORKER_API_KEY);
+        eprintln!("[{}] Error: {}", log_prefix, err_msg);
+        return Err(QueryError::ConfigMissing { message: err_msg });
+    };
+    let Some(worker_url) = app_config.worker_endpoint("query") else {
+        let err_msg = config::describe_issues(WORKER_API_URL);
+        eprintln!("[{}] Error: {}", log_prefix, err_msg);
+        return Err(QueryError::ConfigMissing { message: err_msg });
+    };
 
     let key_len = worker_key.len();
     let masked_key = if key_len > 4 {
@@ -132,28 +362,289 @@ async fn send_query_to_worker(
     };
 
     println!(
-        "[send_query_to_worker] Sending request to Worker URL: {}",
-        worker_url
+        "[{}] Sending request to Worker URL: {}",
+        log_prefix, worker_url
     );
+    println!("[{}] Using Worker API Key: {}", log_prefix, masked_key);
+
+    Ok((worker_url, worker_key))
+}
+
+// Where a query goes: the worker, or a direct provider
+struct QueryTarget {
+    profile: QueryProfile,
+    model: Option<String>, // Overrides the provider's default model
+}
+
+// The profile a query runs with is the caller's choice, else the configured default
+fn resolve_target(
+    profile: Option<QueryProfile>,
+    model: Option<String>,
+    log_prefix: &str,
+) -> QueryTarget {
+    let profile = profile.unwrap_or_else(|| config::current().query_profile.unwrap_or_default());
+    println!(
+        "[{}] Using query profile: {:?}, model: {:?}",
+        log_prefix, profile, model
+    );
+    QueryTarget { profile, model }
+}
+
+// Builds the `pipeline` profile's stages from the current config
+fn load_pipeline(log_prefix: &str) -> Result<Pipeline, CommandError> {
+    let pipeline = Pipeline::from_config(&config::current());
+    if let Err(e) = &pipeline {
+        eprintln!("[{}] Error: {}", log_prefix, e);
+    }
+    pipeline
+}
+
+// e.g. `Mac OS 15.4.0`, filled into the pipeline's prompt templates
+fn os_description() -> String {
+    format!(
+        "{} {}",
+        tauri_plugin_os::type_(),
+        tauri_plugin_os::version()
+    )
+}
+
+// Resolves the direct provider of the target and the model to ask it
+fn direct_provider(
+    target: &QueryTarget,
+    log_prefix: &str,
+) -> Result<(Box<dyn Provider>, String), CommandError> {
+    let resolved = provider::provider(target.profile).and_then(|provider| {
+        let model = provider::select_model(provider.as_ref(), target.model.as_deref())?;
+        Ok((provider, model))
+    });
+    match &resolved {
+        Ok((provider, model)) => println!(
+            "[{}] Sending request to {} (model {})",
+            log_prefix,
+            provider.display_name(),
+            model
+        ),
+        Err(e) => eprintln!("[{}] Error: {}", log_prefix, e),
+    }
+    resolved
+}
+
+// Best-effort write to the conversation store; a failure here must not fail the query
+fn record_turn(
+    store: &ConversationStore,
+    conversation_id: Option<i64>,
+    role: ChatRole,
+    content: &str,
+    screenshot_path: Option<&str>,
+    vision_description: Option<&str>,
+    log_prefix: &str,
+) {
+    let Some(conversation_id) = conversation_id else {
+        return;
+    };
+    if let Err(e) = store.append_message(
+        conversation_id,
+        role,
+        content,
+        screenshot_path,
+        vision_description,
+    ) {
+        eprintln!(
+            "[{}] Failed to save {} turn to conversation {}: {}",
+            log_prefix,
+            role.as_str(),
+            conversation_id,
+            e
+        );
+    }
+}
+
+// --- New Tauri Command: send_query_to_worker ---
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // Tauri command arguments map 1:1 to invoke params
+async fn send_query_to_worker(
+    text: String,
+    image_path: Option<String>,            // Make image path optional
+    image_paths: Option<Vec<String>>,      // Optional: further screenshots, sent after image_path
+    request_id: Option<String>,            // Optional: lets the frontend abort via `cancel_query`
+    history: Option<Vec<ChatTurn>>, // Optional: prior turns of the conversation, oldest first
+    history_budget: Option<HistoryBudget>, // Optional: override of the history size limit
+    conversation_id: Option<i64>, // Optional: saves the question and the reply in this conversation
+    image_options: Option<ImageOptions>, // Optional: override of screenshot size/quality/format
+    timeout_ms: Option<u64>,      // Optional: total timeout for the query; pipeline stages share it
+    profile: Option<QueryProfile>, // Optional: `worker`, `pipeline` or a direct provider; defaults to QUERY_PROFILE
+    model: Option<String>,         // Optional: model of a direct provider, see `list_models`
+    window: WebviewWindow,
+    query_registry: State<'_, QueryRegistryState>,
+    store: State<'_, ConversationStore>,
+    http: State<'_, HttpClient>,
+) -> Result<String, CommandError> {
     println!(
-        "[send_query_to_worker] Using Worker API Key: {}",
-        masked_key
+        "[send_query_to_worker] Received query: '{}', Image path: {:?}, Extra images: {:?}, Request id: {:?}, History turns: {}",
+        text,
+        image_path,
+        image_paths,
+        request_id,
+        history.as_ref().map_or(0, Vec::len)
     );
 
-    let payload = WorkerQueryRequest {
-        text: &text,
-        base64_image_data_url: base64_data_url,
+    let input = QueryInput::new(
+        text,
+        image_path,
+        image_paths,
+        history,
+        history_budget,
+        image_options,
+        timeout_ms,
+    );
+    record_turn(
+        &store,
+        conversation_id,
+        ChatRole::User,
+        &input.text,
+        input.first_image_path(),
+        None,
+        "send_query_to_worker",
+    );
+
+    let target = resolve_target(profile, model, "send_query_to_worker");
+    let query = query_worker(
+        &input,
+        &target,
+        http.client(),
+        request_id.as_deref(),
+        &window,
+    );
+    let result = match &request_id {
+        None => query.await,
+        Some(request_id) => {
+            let mut registration =
+                register_query(query_registry.inner(), request_id, window.label());
+            tokio::select! {
+                result = query => result,
+                _ = registration.cancelled() => {
+                    println!("[send_query_to_worker] Request {} cancelled.", request_id);
+                    Err(QueryError::Cancelled)
+                }
+            }
+        }
     };
 
+    let worker_response = result?;
+    record_turn(
+        &store,
+        conversation_id,
+        ChatRole::Assistant,
+        &worker_response.ai_text,
+        None,
+        worker_response.vision_description.as_deref(),
+        "send_query_to_worker",
+    );
+    Ok(worker_response.ai_text)
+}
+
+// Runs the one-shot query against the worker, the pipeline, or the direct provider of
+// the target
+async fn query_worker(
+    input: &QueryInput,
+    target: &QueryTarget,
+    client: &reqwest::Client,
+    request_id: Option<&str>,
+    window: &WebviewWindow,
+) -> Result<WorkerQueryResponse, CommandError> {
+    // 1. Read, shrink and Encode Images (if paths are provided)
+    let image_data_urls =
+        load_image_data_urls(input, request_id, window, "send_query_to_worker").await?;
+
+    if target.profile == QueryProfile::Pipeline {
+        let pipeline = load_pipeline("send_query_to_worker")?;
+        let os = os_description();
+        let query = PipelineQuery {
+            question: &input.text,
+            os: &os,
+            image_data_urls: &image_data_urls,
+            history: &input.history,
+        };
+        let answer = pipeline
+            .complete(
+                client,
+                &query,
+                input.timeout,
+                |attempt| emit_retry_progress(window, request_id, attempt),
+                |report| emit_stage_report(window, request_id, report),
+            )
+            .await?;
+        return Ok(WorkerQueryResponse {
+            ai_text: answer.completion.text,
+            vision_description: answer.vision_description,
+        });
+    }
+    if target.profile.is_direct() {
+        let (provider, model) = direct_provider(target, "send_query_to_worker")?;
+        let query = ProviderQuery {
+            model: &model,
+            text: &input.text,
+            image_data_urls: &image_data_urls,
+            history: &input.history,
+            stream: false,
+        };
+        let completion = provider::complete(
+            provider.as_ref(),
+            client,
+            &query,
+            input.timeout,
+            |attempt| emit_retry_progress(window, request_id, attempt),
+        )
+        .await?;
+        return Ok(WorkerQueryResponse {
+            ai_text: completion.text,
+            vision_description: None, // Only the worker reports one
+        });
+    }
+
+    // 2. Prepare Request for Worker
+    let (worker_url, worker_key) = worker_query_endpoint("send_query_to_worker")?;
+
+    let payload = WorkerQueryRequest::new(&input.text, image_data_urls, &input.history, false);
+
     // 3. Send Request to Worker
-    let client = reqwest::Client::new();
-    match client
-        .post(&worker_url)
-        .header("Authorization", format!("Bearer {}", worker_key))
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await
+    post_worker_query(
+        client,
+        &worker_url,
+        &worker_key,
+        &payload,
+        input.timeout,
+        |attempt| emit_retry_progress(window, request_id, attempt),
+    )
+    .await
+}
+
+// Posts a one-shot query to the worker and reads the answer. Kept free of the window so
+// it can be exercised against a mock worker.
+async fn post_worker_query(
+    client: &reqwest::Client,
+    worker_url: &str,
+    worker_key: &str,
+    payload: &WorkerQueryRequest<'_>,
+    timeout: Option<Duration>,
+    on_retry: impl FnMut(&RetryAttempt),
+) -> Result<WorkerQueryResponse, CommandError> {
+    // Not idempotent: a retry may trigger a second AI call, see `http::retryable_status`
+    match send_with_retry(
+        "send_query_to_worker",
+        &RetryPolicy::default(),
+        false,
+        || {
+            let request = client
+                .post(worker_url)
+                .header("Authorization", format!("Bearer {}", worker_key))
+                .header("Content-Type", "application/json")
+                .json(payload);
+            with_timeout(request, timeout)
+        },
+        on_retry,
+    )
+    .await
     {
         Ok(response) => {
             let status = response.status();
@@ -167,30 +658,374 @@ async fn send_query_to_worker(
                         println!(
                             "[send_query_to_worker] Successfully received and parsed AI response."
                         );
-                        Ok(worker_response.ai_text)
+                        Ok(worker_response)
                     }
                     Err(e) => {
-                        let err_msg = format!("Failed to parse worker response: {}", e);
-                        eprintln!("[send_query_to_worker] Error: {}", err_msg);
-                        Err(err_msg)
+                        let err = QueryError::Parse {
+                            message: e.to_string(),
+                        };
+                        eprintln!("[send_query_to_worker] Error: {}", err);
+                        Err(err)
                     }
                 }
             } else {
-                let error_body = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "Failed to read error body".to_string());
-                let err_msg = format!("Worker returned error status {}: {}", status, error_body);
-                eprintln!("[send_query_to_worker] Error: {}", err_msg);
-                Err(err_msg)
+                let err = error_from_response(response).await;
+                eprintln!("[send_query_to_worker] Error: {}", err);
+                Err(err)
             }
         }
         Err(e) => {
-            let err_msg = format!("Failed to send request to worker: {}", e);
-            eprintln!("[send_query_to_worker] Error: {}", err_msg);
-            Err(err_msg)
+            let err = QueryError::from(e);
+            eprintln!("[send_query_to_worker] Error: {}", err);
+            Err(err)
+        }
+    }
+}
+
+// Turns a non-success worker response into the matching error, keeping the body text
+async fn error_from_response(response: reqwest::Response) -> QueryError {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let error_body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Failed to read error body".to_string());
+    QueryError::from_status(status, &headers, &error_body)
+}
+
+// --- New Tauri Command: send_query_to_worker_stream ---
+// Same request as `send_query_to_worker`, but the answer is forwarded to the calling
+// window as `query_stream` events (delta / done / error) tagged with `request_id`.
+// The full text is still returned once the stream ends. The request can be aborted
+// with `cancel_query`, which ends the stream with a `cancelled` event.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // Tauri command arguments map 1:1 to invoke params
+async fn send_query_to_worker_stream(
+    text: String,
+    image_path: Option<String>,
+    image_paths: Option<Vec<String>>,
+    request_id: String,
+    history: Option<Vec<ChatTurn>>,
+    history_budget: Option<HistoryBudget>,
+    conversation_id: Option<i64>,
+    image_options: Option<ImageOptions>,
+    timeout_ms: Option<u64>,
+    profile: Option<QueryProfile>,
+    model: Option<String>,
+    window: WebviewWindow,
+    query_registry: State<'_, QueryRegistryState>,
+    store: State<'_, ConversationStore>,
+    http: State<'_, HttpClient>,
+) -> Result<String, CommandError> {
+    println!(
+        "[send_query_to_worker_stream] Request {}: query '{}', Image path: {:?}, Extra images: {:?}, History turns: {}",
+        request_id,
+        text,
+        image_path,
+        image_paths,
+        history.as_ref().map_or(0, Vec::len)
+    );
+
+    let input = QueryInput::new(
+        text,
+        image_path,
+        image_paths,
+        history,
+        history_budget,
+        image_options,
+        timeout_ms,
+    );
+    record_turn(
+        &store,
+        conversation_id,
+        ChatRole::User,
+        &input.text,
+        input.first_image_path(),
+        None,
+        "send_query_to_worker_stream",
+    );
+    let target = resolve_target(profile, model, "send_query_to_worker_stream");
+    let mut registration = register_query(query_registry.inner(), &request_id, window.label());
+    let result = tokio::select! {
+        result = stream_query(&input, &target, http.client(), &request_id, &window) => result,
+        _ = registration.cancelled() => Err(QueryError::Cancelled),
+    };
+    let event = match &result {
+        Ok(answer) => {
+            record_turn(
+                &store,
+                conversation_id,
+                ChatRole::Assistant,
+                &answer.completion.text,
+                None,
+                answer.vision_description.as_deref(),
+                "send_query_to_worker_stream",
+            );
+            QueryStreamEvent::Done {
+                full_text: answer.completion.text.clone(),
+                usage: answer.completion.usage,
+            }
+        }
+        Err(QueryError::Cancelled) => {
+            println!(
+                "[send_query_to_worker_stream] Request {} cancelled.",
+                request_id
+            );
+            QueryStreamEvent::Cancelled
+        }
+        Err(err) => {
+            eprintln!("[send_query_to_worker_stream] Error: {}", err);
+            QueryStreamEvent::Error {
+                message: err.to_string(),
+                error: err.clone(),
+            }
+        }
+    };
+    emit_stream_event(&window, &request_id, event);
+    result.map(|answer| answer.completion.text)
+}
+
+fn emit_stream_event(window: &WebviewWindow, request_id: &str, event: QueryStreamEvent) {
+    let payload = QueryStreamPayload::new(request_id, event);
+    if let Err(e) = window.emit_to(window.label(), QUERY_STREAM_EVENT, payload) {
+        eprintln!(
+            "[send_query_to_worker_stream] Failed to emit stream event for {}: {}",
+            request_id, e
+        );
+    }
+}
+
+// A finished streamed query
+struct StreamedAnswer {
+    completion: Completion,             // Usage is only known for direct providers
+    vision_description: Option<String>, // What the vision model saw, if one ran
+}
+
+impl From<Completion> for StreamedAnswer {
+    fn from(completion: Completion) -> Self {
+        StreamedAnswer {
+            completion,
+            vision_description: None,
+        }
+    }
+}
+
+impl From<PipelineAnswer> for StreamedAnswer {
+    fn from(answer: PipelineAnswer) -> Self {
+        StreamedAnswer {
+            completion: answer.completion,
+            vision_description: answer.vision_description,
+        }
+    }
+}
+
+// Sends the query and relays the body as it arrives. Returns the accumulated text.
+async fn stream_query(
+    input: &QueryInput,
+    target: &QueryTarget,
+    client: &reqwest::Client,
+    request_id: &str,
+    window: &WebviewWindow,
+) -> Result<StreamedAnswer, CommandError> {
+    let image_data_urls = load_image_data_urls(
+        input,
+        Some(request_id),
+        window,
+        "send_query_to_worker_stream",
+    )
+    .await?;
+    let timeout = input.timeout.unwrap_or(STREAM_TOTAL_TIMEOUT);
+
+    if target.profile == QueryProfile::Pipeline {
+        let pipeline = load_pipeline("send_query_to_worker_stream")?;
+        let os = os_description();
+        let query = PipelineQuery {
+            question: &input.text,
+            os: &os,
+            image_data_urls: &image_data_urls,
+            history: &input.history,
+        };
+        return pipeline
+            .stream(
+                client,
+                &query,
+                timeout,
+                |attempt| emit_retry_progress(window, Some(request_id), attempt),
+                |report| emit_stage_report(window, Some(request_id), report),
+                |text| emit_stream_event(window, request_id, QueryStreamEvent::Delta { text }),
+            )
+            .await
+            .map(StreamedAnswer::from);
+    }
+    if target.profile.is_direct() {
+        let (provider, model) = direct_provider(target, "send_query_to_worker_stream")?;
+        let query = ProviderQuery {
+            model: &model,
+            text: &input.text,
+            image_data_urls: &image_data_urls,
+            history: &input.history,
+            stream: true,
+        };
+        return provider::stream(
+            provider.as_ref(),
+            client,
+            &query,
+            timeout,
+            |attempt| emit_retry_progress(window, Some(request_id), attempt),
+            |text| emit_stream_event(window, request_id, QueryStreamEvent::Delta { text }),
+        )
+        .await
+        .map(StreamedAnswer::from);
+    }
+
+    let (worker_url, worker_key) = worker_query_endpoint("send_query_to_worker_stream")?;
+
+    let payload = WorkerQueryRequest::new(&input.text, image_data_urls, &input.history, true);
+
+    // Only the initial request is retried; a stream that breaks halfway is an error
+    let response = send_with_retry(
+        "send_query_to_worker_stream",
+        &RetryPolicy::default(),
+        false,
+        || {
+            client
+                .post(&worker_url)
+                .header("Authorization", format!("Bearer {}", worker_key))
+                .header("Content-Type", "application/json")
+                .header("Accept", "text/event-stream")
+                .json(&payload)
+                .timeout(timeout)
+        },
+        |attempt| emit_retry_progress(window, Some(request_id), attempt),
+    )
+    .await
+    .map_err(QueryError::from)?;
+
+    let status = response.status();
+    println!(
+        "[send_query_to_worker_stream] Worker responded with status: {}",
+        status
+    );
+    if !status.is_success() {
+        return Err(error_from_response(response).await);
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    // A worker that doesn't stream yet answers with the usual JSON body
+    if content_type.starts_with("application/json") {
+        println!("[send_query_to_worker_stream] Worker did not stream; relaying JSON answer.");
+        let worker_response =
+            response
+                .json::<WorkerQueryResponse>()
+                .await
+                .map_err(|e| QueryError::Parse {
+                    message: e.to_string(),
+                })?;
+        emit_stream_event(
+            window,
+            request_id,
+            QueryStreamEvent::Delta {
+                text: worker_response.ai_text.clone(),
+            },
+        );
+        return Ok(StreamedAnswer {
+            completion: Completion {
+                text: worker_response.ai_text,
+                usage: None,
+            },
+            vision_description: worker_response.vision_description,
+        });
+    }
+
+    read_worker_stream(response, &content_type, |text| {
+        emit_stream_event(window, request_id, QueryStreamEvent::Delta { text })
+    })
+    .await
+}
+
+// Relays a streamed worker body (SSE, or plain chunked text) to `on_delta`. Returns the
+// accumulated text.
+async fn read_worker_stream(
+    mut response: reqwest::Response,
+    content_type: &str,
+    mut on_delta: impl FnMut(String),
+) -> Result<StreamedAnswer, CommandError> {
+    let is_sse = content_type.starts_with("text/event-stream");
+    let mut decoder = SseDecoder::new();
+    let mut text_decoder = Utf8Decoder::new();
+    let mut full_text = String::new();
+
+    loop {
+        let chunk = response.chunk().await.map_err(QueryError::from)?;
+        let finished = chunk.is_none();
+        let data_items: Vec<String> = match chunk {
+            Some(bytes) if is_sse => decoder.feed(&bytes),
+            // Plain chunked body: every chunk is a piece of the answer
+            Some(bytes) => vec![text_decoder.feed(&bytes)],
+            None if is_sse => decoder.finish().into_iter().collect(),
+            None => vec![text_decoder.finish()],
+        };
+
+        for data in data_items {
+            let parsed = if is_sse {
+                parse_stream_data(&data)
+            } else {
+                StreamChunk::Delta(data)
+            };
+            match parsed {
+                StreamChunk::Delta(delta) if delta.is_empty() => {}
+                StreamChunk::Delta(delta) => {
+                    full_text.push_str(&delta);
+                    on_delta(delta);
+                }
+                StreamChunk::Done => {
+                    return Ok(Completion {
+                        text: full_text,
+                        usage: None,
+                    }
+                    .into())
+                }
+                StreamChunk::Error(message) => return Err(QueryError::StreamFailed { message }),
+            }
+        }
+        if finished {
+            break;
         }
     }
+
+    println!(
+        "[send_query_to_worker_stream] Stream ended ({} chars).",
+        full_text.len()
+    );
+    Ok(Completion {
+        text: full_text,
+        usage: None,
+    }
+    .into())
+}
+
+// --- New Tauri Command: cancel_query ---
+// Aborts an in-flight query started with a `request_id`. Returns false if the id is
+// unknown or the query already finished.
+#[tauri::command]
+fn cancel_query(request_id: String, query_registry: State<'_, QueryRegistryState>) -> bool {
+    let cancelled = cancel_query_by_id(query_registry.inner(), &request_id);
+    println!(
+        "[cancel_query] Request {}: {}",
+        request_id,
+        if cancelled {
+            "cancelled"
+        } else {
+            "not running"
+        }
+    );
+    cancelled
 }
 
 // --- Main App Setup ---
@@ -203,6 +1038,8 @@ pub fn run() {
     dotenv().ok();
 
     let pending_auth_state = PendingAuthState::default();
+    let device_login_state = DeviceLoginState::default();
+    let query_registry_state = QueryRegistryState::default();
 
     let mut builder = tauri::Builder::default()
         // Register plugins...
@@ -214,11 +1051,55 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_os::init())
         .manage(pending_auth_state.clone())
+        .manage(device_login_state)
+        .manage(query_registry_state.clone())
+        // Abort queries whose window went away so their results don't arrive late
+        .on_window_event(|window, event| {
+            if let WindowEvent::Destroyed = event {
+                let registry = window.state::<QueryRegistryState>();
+                let cancelled = cancel_queries_for_window(registry.inner(), window.label());
+                if cancelled > 0 {
+                    println!(
+                        "Queries: Cancelled {} in-flight queries for closed window '{}'.",
+                        cancelled,
+                        window.label()
+                    );
+                }
+            }
+        })
         // --> ADD the new command to the handler <--
         .invoke_handler(tauri::generate_handler![
             greet,
             login_with_github,
-            send_query_to_worker // Added command
+            login_with_github_device,
+            cancel_device_login,
+            login_with_provider,
+            list_identity_providers,
+            send_query_to_worker, // Added command
+            send_query_to_worker_stream,
+            cancel_query,
+            list_providers,
+            list_models,
+            crop_screenshot_region,
+            crop_screenshot_to_window,
+            get_config_status,
+            reload_config,
+            validate_config,
+            list_secrets,
+            set_secret,
+            rotate_secret,
+            clear_secret,
+            get_session,
+            logout,
+            refresh_profile,
+            create_conversation,
+            append_message,
+            list_conversations,
+            search_conversations,
+            search_messages,
+            get_conversation_messages,
+            rename_conversation,
+            delete_conversation
         ]);
 
     #[cfg(debug_assertions)]
@@ -229,6 +1110,81 @@ pub fn run() {
 
     builder
         .setup(move |app| {
+            // Secrets live in the OS keyring, or an encrypted file in the app data dir
+            // when no keyring is reachable
+            let secret_store: SecretStoreState = match app.path().app_data_dir() {
+                Ok(dir) => SecretStore::open(&dir),
+                Err(e) => {
+                    eprintln!(
+                        "Secrets: Failed to resolve app data dir, keyring only: {}",
+                        e
+                    );
+                    SecretStore::new(Box::new(KeyringBackend))
+                }
+            }
+            .into();
+            app.manage(secret_store.clone());
+
+            // Runtime configuration: env vars > secret store > config.env in the app config
+            // dir > embedded fallback. Problems are reported via get_config_status instead
+            // of aborting.
+            let config_file = app
+                .path()
+                .app_config_dir()
+                .map(|dir| dir.join(CONFIG_FILE_NAME))
+                .map_err(|e| eprintln!("Config: Failed to resolve app config dir: {}", e))
+                .ok();
+            let config_status = config::init(config_file, Some(secret_store.clone()));
+            println!(
+                "Config: Loaded (ready: {}, file: {:?}).",
+                config_status.ready, config_status.config_file
+            );
+            // Login pages on a GitHub Enterprise Server or OIDC issuer need an opener scope
+            auth::allow_login_urls(app.handle());
+
+            // Restore the signed-in session, if any, so users stay logged in across launches
+            let session: SessionState = SessionManager::new(Some(secret_store)).into();
+            match session.restore() {
+                Some(_) => println!("Session: Restored stored session."),
+                None => println!("Session: No stored session."),
+            }
+            app.manage(session.clone());
+
+            // Conversation store lives in the app data dir; fall back to memory so the
+            // app still works (without persistence) if the file can't be opened.
+            let store = app
+                .path()
+                .app_data_dir()
+                .map_err(|e| e.to_string())
+                .and_then(|dir| {
+                    ConversationStore::open(&dir.join(STORE_FILE_NAME)).map_err(|e| e.to_string())
+                })
+                .or_else(|e| {
+                    eprintln!(
+                        "Store: Failed to open conversation store, using memory: {}",
+                        e
+                    );
+                    ConversationStore::open_in_memory()
+                })?;
+            app.manage(store);
+            println!("Store: Conversation store managed.");
+
+            // One pooled HTTP client with timeouts, shared by the query and auth code
+            app.manage(HttpClient::new(&HttpClientConfig::default())?);
+            println!("HTTP: Shared client managed.");
+
+            // Check the restored token in the background; a revoked one ends the session
+            // and the windows are told through session_changed
+            if session.info().is_some() {
+                let handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    let http = handle.state::<HttpClient>();
+                    if let Err(e) = session::refresh(&handle, &session, http.client()).await {
+                        eprintln!("Session: Startup profile refresh failed: {}", e);
+                    }
+                });
+            }
+
             // Deep Link Handler Setup remains the same...
             println!(
                 "Deep Link: Registering on_open_url handler (will activate if scheme configured)."
@@ -250,22 +1206,21 @@ pub fn run() {
                         if let (Some(code), Some(state)) = (params.get("code"), params.get("state"))
                         {
                             println!("Deep Link: Extracted State: {}, Code: [hidden]", state);
-                            let sender = {
+                            let pending = {
                                 let mut map_guard = pending_state
                                     .lock()
                                     .expect("Failed to lock pending auth state for deep link");
                                 map_guard.remove(state)
                             };
-                            match sender {
-                                Some(tx) => {
+                            match pending {
+                                Some(pending) => {
+                                    // Hands over the code together with the stored PKCE verifier
                                     println!("Deep Link: State matched. Sending code via channel.");
-                                    let send_result = tx.send(Ok(code.clone()));
-                                    if send_result.is_err() {
+                                    let error_event = pending.error_event();
+                                    if !pending.complete(code.clone()) {
                                         eprintln!("Deep Link: Receiver dropped. State: {}", state);
-                                        let _ = handle.emit(
-                                            "github_auth_error",
-                                            Some(&AuthError::CallbackTimeout),
-                                        );
+                                        let _ = handle
+                                            .emit(&error_event, Some(&AuthError::CallbackTimeout));
                                     } else {
                                         println!(
                                             "Deep Link: Code sent successfully for state: {}",
@@ -278,18 +1233,19 @@ pub fn run() {
                                         "Deep Link: Invalid or expired state received: {}",
                                         state
                                     );
-                                    let _ = handle
-                                        .emit("github_auth_error", Some(&AuthError::InvalidState));
+                                    // The state doesn't say which provider it was meant for
+                                    for error_event in pending_error_events(&pending_state) {
+                                        let _ = handle
+                                            .emit(&error_event, Some(&AuthError::InvalidState));
+                                    }
                                 }
                             }
                         } else {
                             eprintln!("Deep Link: Callback URL missing 'code' or 'state'");
-                            let _ = handle.emit(
-                                "github_auth_error",
-                                Some(&AuthError::DeepLinkError(
-                                    "Missing code or state".to_string(),
-                                )),
-                            );
+                            let err = AuthError::DeepLinkError("Missing code or state".to_string());
+                            for error_event in pending_error_events(&pending_state) {
+                                let _ = handle.emit(&error_event, Some(&err));
+                            }
                         }
                     } else {
                         println!("Deep Link: Ignoring URL: {}", url_str);
@@ -305,3 +1261,120 @@ pub fn run() {
 fn main() {
     run();
 }
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock_server::{MockResponse, MockServer, MOCK_ANSWER};
+    use serde_json::json;
+
+    async fn post_query(
+        worker: &MockServer,
+        timeout: Option<Duration>,
+    ) -> (Result<WorkerQueryResponse, QueryError>, u32) {
+        let url = format!("{}/query", worker.url());
+        let images = vec!["data:image/png;base64,AAAA".to_string()];
+        let payload = WorkerQueryRequest::new("what is this?", images, &[], false);
+        let mut retries = 0;
+        let result = post_worker_query(
+            &reqwest::Client::new(),
+            &url,
+            "mock-worker-key",
+            &payload,
+            timeout,
+            |_| retries += 1,
+        )
+        .await;
+        (result, retries)
+    }
+
+    #[tokio::test]
+    async fn test_query_against_mock_worker() {
+        let worker = MockServer::worker().await;
+        let (result, retries) = post_query(&worker, None).await;
+        assert_eq!(result.unwrap().ai_text, MOCK_ANSWER);
+        assert_eq!(retries, 0);
+
+        let request = &worker.requests("/query")[0];
+        assert_eq!(
+            request.header("authorization"),
+            Some("Bearer mock-worker-key")
+        );
+        assert_eq!(
+            request.json(),
+            json!({
+                "text": "what is this?",
+                "base64ImageDataUrl": "data:image/png;base64,AAAA",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_maps_worker_failures() {
+        let worker = MockServer::worker().await;
+        worker
+            .enqueue("/query", MockResponse::text(401, "text/plain", "bad key"))
+            .enqueue("/query", MockResponse::text(400, "text/plain", "no text"))
+            // Not retried: the AI provider may already have been called
+            .enqueue("/query", MockResponse::text(500, "text/plain", "boom"))
+            .enqueue("/query", MockResponse::malformed(200));
+        let (result, _) = post_query(&worker, None).await;
+        assert!(matches!(
+            result,
+            Err(QueryError::AuthRejected { status: 401, .. })
+        ));
+        let (result, _) = post_query(&worker, None).await;
+        assert!(matches!(
+            result,
+            Err(QueryError::Rejected { status: 400, .. })
+        ));
+        let (result, retries) = post_query(&worker, None).await;
+        assert!(matches!(
+            result,
+            Err(QueryError::Upstream { status: 500, .. })
+        ));
+        assert_eq!(retries, 0);
+        let (result, _) = post_query(&worker, None).await;
+        assert!(matches!(result, Err(QueryError::Parse { .. })));
+        assert_eq!(worker.requests("/query").len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_query_retries_and_times_out() {
+        let worker = MockServer::worker().await;
+        worker.enqueue(
+            "/query",
+            MockResponse::text(503, "text/plain", "busy").with_header("retry-after", "0"),
+        );
+        let (result, retries) = post_query(&worker, None).await;
+        assert_eq!(result.unwrap().ai_text, MOCK_ANSWER);
+        assert_eq!(retries, 1);
+
+        worker.route(
+            "/query",
+            MockResponse::ok(json!({ "ai_text": "late" })).with_delay(Duration::from_secs(2)),
+        );
+        let (result, _) = post_query(&worker, Some(Duration::from_millis(200))).await;
+        assert!(matches!(result, Err(QueryError::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_plain_stream_keeps_split_characters() {
+        let answer = "答案：你好".as_bytes();
+        let split = answer.len() - 4; // Inside "你"
+        let url = mock_server::serve_chunks(
+            "text/plain; charset=utf-8",
+            vec![answer[..split].to_vec(), answer[split..].to_vec()],
+        )
+        .await;
+        let response = reqwest::Client::new().post(&url).send().await.unwrap();
+
+        let mut deltas = Vec::new();
+        let streamed = read_worker_stream(response, "text/plain", |text| deltas.push(text))
+            .await
+            .unwrap();
+        assert_eq!(streamed.completion.text, "答案：你好");
+        assert_eq!(deltas, ["答案：", "你好"]);
+    }
+}
@@ -0,0 +1,419 @@
+// src-tauri/src/mock_server.rs
+
+// --- Dependencies ---
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// --- Mock servers for end-to-end tests ---
+// A local axum server with scriptable responses that stands in for the worker, GitHub
+// or an AI provider (OpenAI-compatible, Anthropic, Ollama), so the login and query
+// flows can run offline. Each path has a default response; responses queued with
+// `enqueue` are served first, in order, which is how a test scripts "fail twice, then
+// succeed". Every request is recorded for assertions.
+
+pub const MOCK_ACCESS_TOKEN: &str = "gho_mock_token";
+pub const MOCK_USER_ID: u64 = 4242;
+pub const MOCK_LOGIN: &str = "mock-user";
+pub const MOCK_ANSWER: &str = "mock answer";
+pub const MOCK_MODEL: &str = "mock-vision";
+
+// --- Scripted responses ---
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: StatusCode,
+    content_type: &'static str,
+    body: String,
+    headers: Vec<(&'static str, String)>,
+    delay: Duration, // Simulated latency before the response is sent
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self::text(status, "application/json", &body.to_string())
+    }
+
+    pub fn ok(body: Value) -> Self {
+        Self::json(200, body)
+    }
+
+    // Claims to be JSON but is cut off halfway
+    pub fn malformed(status: u16) -> Self {
+        Self::text(status, "application/json", r#"{"ai_text": "trunc"#)
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: &str) -> Self {
+        MockResponse {
+            status: StatusCode::from_u16(status).expect("valid status code"),
+            content_type,
+            body: body.to_string(),
+            headers: Vec::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+impl IntoResponse for MockResponse {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.body).into_response();
+        let headers = response.headers_mut();
+        headers.insert("content-type", self.content_type.parse().unwrap());
+        for (name, value) in self.headers {
+            headers.insert(name, value.parse().unwrap());
+        }
+        response
+    }
+}
+
+// --- Recorded requests ---
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).expect("request body should be JSON")
+    }
+
+    pub fn form(&self) -> HashMap<String, String> {
+        url::form_urlencoded::parse(self.body.as_bytes())
+            .into_owned()
+            .collect()
+    }
+}
+
+// --- The server ---
+#[derive(Default)]
+struct Routes {
+    defaults: HashMap<String, MockResponse>,
+    queued: HashMap<String, VecDeque<MockResponse>>,
+    requests: Vec<RecordedRequest>,
+}
+
+type SharedRoutes = Arc<StdMutex<Routes>>;
+
+pub struct MockServer {
+    url: String,
+    routes: SharedRoutes,
+}
+
+impl MockServer {
+    // Binds an ephemeral port on 127.0.0.1; the server lives as long as the test runtime
+    pub async fn start() -> Self {
+        let routes = SharedRoutes::default();
+        let app = Router::new().fallback(handle).with_state(routes.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        MockServer { url, routes }
+    }
+
+    // A stand-in for the Cloudflare worker: `/query`, `/sync-user` and `/auth/github/token`
+    pub async fn worker() -> Self {
+        let server = Self::start().await;
+        server
+            .route(
+                "/query",
+                MockResponse::ok(json!({ "ai_text": MOCK_ANSWER })),
+            )
+            .route("/sync-user", MockResponse::ok(json!({ "success": true })))
+            .route("/auth/github/token", MockResponse::ok(token_body()));
+        server
+    }
+
+    // A stand-in for github.com and api.github.com, served from the same base URL
+    pub async fn github() -> Self {
+        let server = Self::start().await;
+        server
+            .route("/login/oauth/access_token", MockResponse::ok(token_body()))
+            .route(
+                "/login/device/code",
+                MockResponse::ok(json!({
+                    "device_code": "mock-device-code",
+                    "user_code": "ABCD-1234",
+                    "verification_uri": format!("{}/login/device", server.url),
+                    "expires_in": 60,
+                    "interval": 0,
+                })),
+            )
+            .route(
+                "/user",
+                MockResponse::ok(json!({
+                    "login": MOCK_LOGIN,
+                    "id": MOCK_USER_ID,
+                    "name": "Mock User",
+                    "avatar_url": format!("{}/avatar.png", server.url),
+                    "email": null,
+                })),
+            );
+        server
+    }
+
+    // A stand-in for an OpenAI-compatible API; its base URL is `{url}/v1`
+    pub async fn openai() -> Self {
+        let server = Self::start().await;
+        server
+            .route(
+                "/v1/chat/completions",
+                MockResponse::ok(json!({
+                    "id": "chatcmpl-mock",
+                    "object": "chat.completion",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": MOCK_ANSWER },
+                        "finish_reason": "stop",
+                    }],
+                    "usage": { "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 },
+                })),
+            )
+            .route(
+                "/v1/models",
+                MockResponse::ok(json!({
+                    "object": "list",
+                    "data": [{ "id": MOCK_MODEL, "object": "model", "owned_by": "mock" }],
+                })),
+            );
+        server
+    }
+
+    // A stand-in for the Anthropic Messages API
+    pub async fn anthropic() -> Self {
+        let server = Self::start().await;
+        server
+            .route(
+                "/v1/messages",
+                MockResponse::ok(json!({
+                    "id": "msg_mock",
+                    "type": "message",
+                    "role": "assistant",
+                    "model": MOCK_MODEL,
+                    "content": [{ "type": "text", "text": MOCK_ANSWER }],
+                    "stop_reason": "end_turn",
+                    "usage": { "input_tokens": 12, "output_tokens": 3 },
+                })),
+            )
+            .route(
+                "/v1/models",
+                MockResponse::ok(json!({
+                    "data": [{ "type": "model", "id": MOCK_MODEL, "display_name": "Mock Vision" }],
+                    "has_more": false,
+                })),
+            );
+        server
+    }
+
+    // A stand-in for a local Ollama server
+    pub async fn ollama() -> Self {
+        let server = Self::start().await;
+        server
+            .route(
+                "/api/chat",
+                MockResponse::ok(json!({
+                    "model": MOCK_MODEL,
+                    "message": { "role": "assistant", "content": MOCK_ANSWER },
+                    "done": true,
+                    "prompt_eval_count": 12,
+                    "eval_count": 3,
+                })),
+            )
+            .route(
+                "/api/tags",
+                MockResponse::ok(json!({ "models": [{ "name": MOCK_MODEL, "size": 1 }] })),
+            );
+        server
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Replaces the default response for `path`
+    pub fn route(&self, path: &str, response: MockResponse) -> &Self {
+        self.lock().defaults.insert(path.to_string(), response);
+        self
+    }
+
+    // Serves `response` once, after any responses queued earlier and before the default
+    pub fn enqueue(&self, path: &str, response: MockResponse) -> &Self {
+        self.lock()
+            .queued
+            .entry(path.to_string())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    pub fn requests(&self, path: &str) -> Vec<RecordedRequest> {
+        self.lock()
+            .requests
+            .iter()
+            .filter(|request| request.path == path)
+            .cloned()
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Routes> {
+        self.routes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn token_body() -> Value {
+    json!({ "access_token": MOCK_ACCESS_TOKEN, "token_type": "bearer", "scope": "read:user" })
+}
+
+async fn handle(
+    State(routes): State<SharedRoutes>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().to_string();
+    let response = {
+        let mut routes = routes.lock().unwrap_or_else(PoisonError::into_inner);
+        routes.requests.push(RecordedRequest {
+            method,
+            path: path.clone(),
+            headers,
+            body: String::from_utf8_lossy(&body).to_string(),
+        });
+        routes
+            .queued
+            .get_mut(&path)
+            .and_then(VecDeque::pop_front)
+            .or_else(|| routes.defaults.get(&path).cloned())
+    };
+    match response {
+        Some(response) => {
+            tokio::time::sleep(response.delay).await;
+            response.into_response()
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("no mock response for {}", path),
+        )
+            .into_response(),
+    }
+}
+
+// --- Chunked body stub ---
+// axum hands a scripted body to the connection in one piece, so a test that needs the
+// client to see specific chunk boundaries uses this instead: it answers a single
+// request by writing each chunk as an HTTP/1.1 chunk, pausing in between.
+pub async fn serve_chunks(content_type: &'static str, chunks: Vec<Vec<u8>>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind the chunked mock server");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let Ok((mut socket, _)) = listener.accept().await else {
+            return;
+        };
+        if read_request(&mut socket).await.is_err() {
+            return;
+        }
+        let head = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n",
+            content_type
+        );
+        let mut frames = vec![head.into_bytes()];
+        for chunk in chunks {
+            let mut frame = format!("{:x}\r\n", chunk.len()).into_bytes();
+            frame.extend_from_slice(&chunk);
+            frame.extend_from_slice(b"\r\n");
+            frames.push(frame);
+        }
+        frames.push(b"0\r\n\r\n".to_vec());
+        for frame in frames {
+            if socket.write_all(&frame).await.is_err() {
+                return;
+            }
+            let _ = socket.flush().await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+    url
+}
+
+// Reads the request head and its body, so closing the socket afterwards doesn't
+// reset the connection under the client
+async fn read_request(socket: &mut tokio::net::TcpStream) -> std::io::Result<()> {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&received[..end]).to_ascii_lowercase();
+            let body_len = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|len| len.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if received.len() >= end + 4 + body_len {
+                return Ok(());
+            }
+        }
+        let read = socket.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        received.extend_from_slice(&buf[..read]);
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queued_responses_come_before_the_default() {
+        let server = MockServer::worker().await;
+        server.enqueue("/query", MockResponse::text(503, "text/plain", "busy"));
+        let client = reqwest::Client::new();
+        let url = format!("{}/query", server.url());
+
+        let first = client.post(&url).body("one").send().await.unwrap();
+        assert_eq!(first.status(), 503);
+        let second = client.post(&url).body("two").send().await.unwrap();
+        assert_eq!(
+            second.json::<Value>().await.unwrap()["ai_text"],
+            MOCK_ANSWER
+        );
+
+        let missing = client.get(format!("{}/nope", server.url())).send().await;
+        assert_eq!(missing.unwrap().status(), 404);
+
+        assert_eq!(server.requests("/query")[0].method, Method::POST);
+        let bodies: Vec<String> = server
+            .requests("/query")
+            .into_iter()
+            .map(|r| r.body)
+            .collect();
+        assert_eq!(bodies, ["one", "two"]);
+    }
+}
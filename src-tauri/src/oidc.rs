@@ -0,0 +1,583 @@
+// src-tauri/src/oidc.rs
+
+// --- Dependencies ---
+use crate::auth::{AuthCode, AuthError};
+use crate::config::AppConfig;
+use crate::identity::{AuthorizeParams, IdentityProvider, TokenSet, UserProfile};
+use async_trait::async_trait;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::header::{ACCEPT, AUTHORIZATION};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+use url::Url;
+
+// --- Generic OpenID Connect provider ---
+// Works with any issuer that publishes a discovery document (GitLab, Keycloak, ...).
+// The app is a public client: the code is redeemed with PKCE and no client secret, and
+// the ID token is checked against the issuer's published keys before it is trusted.
+
+pub const OIDC_PROVIDER_ID: &str = "oidc";
+const DISCOVERY_PATH: &str = ".well-known/openid-configuration";
+const ID_TOKEN_LEEWAY_SECS: u64 = 60; // Tolerated clock skew for `exp` and `nbf`
+
+// Only asymmetric algorithms: an HS256 token would be "signed" with our public client ID
+const ALLOWED_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+// --- Data structures ---
+#[derive(Deserialize, Debug, Clone)]
+struct DiscoveryDocument {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OidcTokenResponse {
+    #[serde(default)]
+    access_token: String,
+    id_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+// The claims we check beyond what `jsonwebtoken` validates (signature, iss, aud, exp, nbf)
+#[derive(Deserialize, Debug)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    nonce: Option<String>,
+    aud: serde_json::Value, // A string or an array; `jsonwebtoken` has already checked it
+    azp: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct UserinfoClaims {
+    sub: String,
+    preferred_username: Option<String>,
+    nickname: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+    picture: Option<String>,
+}
+
+impl From<UserinfoClaims> for UserProfile {
+    fn from(claims: UserinfoClaims) -> Self {
+        let login = claims
+            .preferred_username
+            .or(claims.nickname)
+            .or_else(|| claims.email.clone())
+            .unwrap_or_else(|| claims.sub.clone());
+        UserProfile {
+            provider: OIDC_PROVIDER_ID.to_string(),
+            id: claims.sub,
+            login,
+            name: claims.name,
+            email: claims.email,
+            avatar_url: claims.picture,
+        }
+    }
+}
+
+// --- The provider ---
+pub struct OidcProvider {
+    issuer: String,
+    client_id: String,
+    scopes: String,
+    display_name: String,
+    discovery: OnceCell<DiscoveryDocument>, // Fetched once per provider instance
+}
+
+impl OidcProvider {
+    pub fn new(issuer: &str, client_id: &str, scopes: &str, display_name: &str) -> Self {
+        OidcProvider {
+            issuer: issuer.to_string(),
+            client_id: client_id.to_string(),
+            scopes: scopes.to_string(),
+            display_name: display_name.to_string(),
+            discovery: OnceCell::new(),
+        }
+    }
+
+    // `None` unless both the issuer and the client ID are configured
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        Some(OidcProvider::new(
+            config.oidc_issuer.as_deref()?,
+            config.oidc_client_id.as_deref()?,
+            config
+                .oidc_scopes
+                .as_deref()
+                .unwrap_or("openid profile email"),
+            config
+                .oidc_display_name
+                .as_deref()
+                .unwrap_or("OpenID Connect"),
+        ))
+    }
+
+    async fn discovery(&self, client: &reqwest::Client) -> Result<&DiscoveryDocument, AuthError> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!("{}/{}", self.issuer.trim_end_matches('/'), DISCOVERY_PATH);
+                println!("OIDC: Fetching discovery document from {}", url);
+                let document: DiscoveryDocument = get_json(client, &url, None).await?;
+                // The document must describe the issuer we were configured with
+                if document.issuer.trim_end_matches('/') != self.issuer.trim_end_matches('/') {
+                    return Err(AuthError::ProviderError(format!(
+                        "discovery document is for issuer '{}', expected '{}'",
+                        document.issuer, self.issuer
+                    )));
+                }
+                Ok(document)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for OidcProvider {
+    fn id(&self) -> &str {
+        OIDC_PROVIDER_ID
+    }
+
+    fn display_name(&self) -> String {
+        self.display_name.clone()
+    }
+
+    async fn authorize_url(
+        &self,
+        client: &reqwest::Client,
+        params: &AuthorizeParams<'_>,
+    ) -> Result<String, AuthError> {
+        let discovery = self.discovery(client).await?;
+        let mut url = Url::parse(&discovery.authorization_endpoint).map_err(|e| {
+            AuthError::ProviderError(format!("invalid authorization_endpoint: {}", e))
+        })?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", params.redirect_uri)
+            .append_pair("scope", &self.scopes)
+            .append_pair("state", params.state)
+            .append_pair("nonce", params.nonce)
+            .append_pair("code_challenge", params.code_challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    async fn exchange_code(
+        &self,
+        client: &reqwest::Client,
+        code: &AuthCode,
+        redirect_uri: &str,
+        nonce: &str,
+    ) -> Result<TokenSet, AuthError> {
+        let discovery = self.discovery(client).await?;
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("code", code.code.as_str()),
+            ("code_verifier", code.code_verifier.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("client_id", self.client_id.as_str()),
+        ];
+        // A code can only be redeemed once, so no retry here
+        let response = client
+            .post(&discovery.token_endpoint)
+            .header(ACCEPT, "application/json")
+            .form(&params)
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        let token: OidcTokenResponse = serde_json::from_str(&body).map_err(|e| {
+            AuthError::ProviderError(format!(
+                "token endpoint returned {}: {} ({})",
+                status, body, e
+            ))
+        })?;
+        if let Some(error) = token.error {
+            return Err(AuthError::ProviderError(format!(
+                "{} {}",
+                error,
+                token.error_description.unwrap_or_default()
+            )));
+        }
+        if !status.is_success() || token.access_token.is_empty() {
+            return Err(AuthError::ProviderError(format!(
+                "token endpoint returned {} without an access token",
+                status
+            )));
+        }
+        let id_token = token.id_token.ok_or_else(|| {
+            AuthError::InvalidIdToken("the token response has no id_token".to_string())
+        })?;
+
+        let jwks: JwkSet = get_json(client, &discovery.jwks_uri, None).await?;
+        let claims =
+            validate_id_token(&id_token, &jwks, &discovery.issuer, &self.client_id, nonce)?;
+        Ok(TokenSet {
+            access_token: token.access_token,
+            subject: Some(claims.sub),
+        })
+    }
+
+    async fn fetch_profile(
+        &self,
+        client: &reqwest::Client,
+        access_token: &str,
+    ) -> Result<UserProfile, AuthError> {
+        let discovery = self.discovery(client).await?;
+        let userinfo_endpoint = discovery.userinfo_endpoint.as_deref().ok_or_else(|| {
+            AuthError::ProviderError("the issuer has no userinfo_endpoint".to_string())
+        })?;
+        let claims: UserinfoClaims =
+            get_json(client, userinfo_endpoint, Some(access_token)).await?;
+        Ok(claims.into())
+    }
+}
+
+// --- ID token validation ---
+// Signature against the issuer's keys, then iss, aud, exp/nbf, azp and our nonce
+pub fn validate_id_token(
+    id_token: &str,
+    jwks: &JwkSet,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+) -> Result<IdTokenClaims, AuthError> {
+    let invalid = |message: String| AuthError::InvalidIdToken(message);
+    let header = decode_header(id_token).map_err(|e| invalid(e.to_string()))?;
+    if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+        return Err(invalid(format!(
+            "algorithm {:?} is not allowed",
+            header.alg
+        )));
+    }
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| invalid(format!("no published key matches kid {:?}", header.kid)))?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| invalid(e.to_string()))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    validation.leeway = ID_TOKEN_LEEWAY_SECS;
+    validation.validate_nbf = true;
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|e| invalid(e.to_string()))?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(invalid(
+            "nonce does not match the login request".to_string(),
+        ));
+    }
+    let multiple_audiences = matches!(&claims.aud, serde_json::Value::Array(aud) if aud.len() > 1);
+    if (multiple_audiences || claims.azp.is_some()) && claims.azp.as_deref() != Some(client_id) {
+        return Err(invalid("azp does not match the client ID".to_string()));
+    }
+    Ok(claims)
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: Option<&str>,
+) -> Result<T, AuthError> {
+    let mut request = client.get(url).header(ACCEPT, "application/json");
+    if let Some(token) = bearer {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED && bearer.is_some() {
+        return Err(AuthError::TokenRejected);
+    }
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AuthError::ProviderError(format!(
+            "GET {} failed (status {}): {}",
+            url, status, body
+        )));
+    }
+    response
+        .json::<T>()
+        .await
+        .map_err(|e| AuthError::ParseError(format!("{}: {}", url, e)))
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State as AxumState, routing::get, routing::post, Json, Router};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const CLIENT_ID: &str = "desktop-app";
+    const KID: &str = "test-key";
+
+    // An ES256 key, published as a JWKS like an issuer would
+    struct TestKey {
+        pkcs8: Vec<u8>,
+        jwks: Value,
+    }
+
+    impl TestKey {
+        fn generate() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .unwrap()
+                .as_ref()
+                .to_vec();
+            let pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng).unwrap();
+            // Uncompressed point: 0x04 || x || y
+            let point = pair.public_key().as_ref();
+            let jwks = json!({"keys": [{
+                "kty": "EC", "crv": "P-256", "kid": KID, "alg": "ES256", "use": "sig",
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+            }]});
+            TestKey { pkcs8, jwks }
+        }
+
+        fn sign(&self, claims: &Value) -> String {
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some(KID.to_string());
+            encode(&header, claims, &EncodingKey::from_ec_der(&self.pkcs8)).unwrap()
+        }
+
+        fn jwk_set(&self) -> JwkSet {
+            serde_json::from_value(self.jwks.clone()).unwrap()
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn claims(issuer: &str, nonce: &str) -> Value {
+        json!({
+            "iss": issuer, "aud": CLIENT_ID, "sub": "user-1", "nonce": nonce,
+            "iat": now(), "exp": now() + 300,
+        })
+    }
+
+    // --- Mock issuer ---
+    struct MockIssuer {
+        base: String,
+        jwks: Value,
+        id_token: StdMutex<String>, // What the token endpoint hands out next
+    }
+
+    async fn start_mock_issuer(key: &TestKey) -> Arc<MockIssuer> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = Arc::new(MockIssuer {
+            base: format!("http://{}", listener.local_addr().unwrap()),
+            jwks: key.jwks.clone(),
+            id_token: StdMutex::new(String::new()),
+        });
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(|AxumState(issuer): AxumState<Arc<MockIssuer>>| async move {
+                    Json(json!({
+                        "issuer": issuer.base,
+                        "authorization_endpoint": format!("{}/authorize", issuer.base),
+                        "token_endpoint": format!("{}/token", issuer.base),
+                        "userinfo_endpoint": format!("{}/userinfo", issuer.base),
+                        "jwks_uri": format!("{}/jwks", issuer.base),
+                    }))
+                }),
+            )
+            .route(
+                "/jwks",
+                get(|AxumState(issuer): AxumState<Arc<MockIssuer>>| async move {
+                    Json(issuer.jwks.clone())
+                }),
+            )
+            .route(
+                "/token",
+                post(|AxumState(issuer): AxumState<Arc<MockIssuer>>| async move {
+                    Json(json!({
+                        "access_token": "oidc-access-token",
+                        "token_type": "Bearer",
+                        "id_token": issuer.id_token.lock().unwrap().clone(),
+                    }))
+                }),
+            )
+            .route(
+                "/userinfo",
+                get(|headers: axum::http::HeaderMap| async move {
+                    let authorized = headers
+                        .get("authorization")
+                        .is_some_and(|value| value == "Bearer oidc-access-token");
+                    if authorized {
+                        Ok(Json(json!({
+                            "sub": "user-1", "preferred_username": "octo",
+                            "name": "Octo Cat", "email": "octo@example.com",
+                        })))
+                    } else {
+                        Err(axum::http::StatusCode::UNAUTHORIZED)
+                    }
+                }),
+            )
+            .with_state(issuer.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        issuer
+    }
+
+    #[tokio::test]
+    async fn test_login_against_mock_issuer() {
+        let key = TestKey::generate();
+        let issuer = start_mock_issuer(&key).await;
+        let client = reqwest::Client::new();
+        let provider = OidcProvider::new(&issuer.base, CLIENT_ID, "openid profile", "Mock");
+
+        let url = provider
+            .authorize_url(
+                &client,
+                &AuthorizeParams {
+                    redirect_uri: "revision://github/callback",
+                    state: "state-1",
+                    code_challenge: "challenge",
+                    nonce: "nonce-1",
+                },
+            )
+            .await
+            .unwrap();
+        let url = Url::parse(&url).unwrap();
+        assert_eq!(url.path(), "/authorize");
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&("nonce".to_string(), "nonce-1".to_string())));
+        assert!(query.contains(&("code_challenge_method".to_string(), "S256".to_string())));
+
+        *issuer.id_token.lock().unwrap() = key.sign(&claims(&issuer.base, "nonce-1"));
+        let code = AuthCode {
+            code: "code".to_string(),
+            code_verifier: "verifier".to_string(),
+        };
+        let tokens = provider
+            .exchange_code(&client, &code, "revision://github/callback", "nonce-1")
+            .await
+            .unwrap();
+        assert_eq!(tokens.subject.as_deref(), Some("user-1"));
+
+        let profile = provider
+            .fetch_profile(&client, &tokens.access_token)
+            .await
+            .unwrap();
+        assert_eq!(profile.login, "octo");
+        assert_eq!(profile.id, "user-1");
+        assert!(matches!(
+            provider.fetch_profile(&client, "revoked").await,
+            Err(AuthError::TokenRejected)
+        ));
+
+        // A replayed token from another login request is refused
+        let err = provider
+            .exchange_code(&client, &code, "revision://github/callback", "nonce-2")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AuthError::InvalidIdToken(_)));
+    }
+
+    #[tokio::test]
+    async fn test_discovery_must_match_issuer() {
+        let key = TestKey::generate();
+        let issuer = start_mock_issuer(&key).await;
+        let other = issuer.base.replace("127.0.0.1", "localhost");
+        let provider = OidcProvider::new(&other, CLIENT_ID, "openid", "Mock");
+        let params = AuthorizeParams {
+            redirect_uri: "revision://github/callback",
+            state: "s",
+            code_challenge: "c",
+            nonce: "n",
+        };
+        assert!(matches!(
+            provider
+                .authorize_url(&reqwest::Client::new(), &params)
+                .await,
+            Err(AuthError::ProviderError(_))
+        ));
+    }
+
+    #[test]
+    fn test_id_token_validation() {
+        let key = TestKey::generate();
+        let jwks = key.jwk_set();
+        let issuer = "https://issuer.example";
+        let check = |claims: Value| {
+            validate_id_token(&key.sign(&claims), &jwks, issuer, CLIENT_ID, "nonce-1")
+        };
+
+        assert_eq!(check(claims(issuer, "nonce-1")).unwrap().sub, "user-1");
+
+        let mut wrong_audience = claims(issuer, "nonce-1");
+        wrong_audience["aud"] = json!("someone-else");
+        let mut wrong_issuer = claims(issuer, "nonce-1");
+        wrong_issuer["iss"] = json!("https://evil.example");
+        let mut expired = claims(issuer, "nonce-1");
+        expired["exp"] = json!(now() - ID_TOKEN_LEEWAY_SECS - 10);
+        let mut shared_audience = claims(issuer, "nonce-1");
+        shared_audience["aud"] = json!([CLIENT_ID, "other-app"]);
+        for (name, claims) in [
+            ("nonce", claims(issuer, "nonce-2")),
+            ("audience", wrong_audience),
+            ("issuer", wrong_issuer),
+            ("expired", expired),
+            ("azp", shared_audience),
+        ] {
+            assert!(
+                matches!(check(claims), Err(AuthError::InvalidIdToken(_))),
+                "{}",
+                name
+            );
+        }
+
+        // Tampered payload: the signature no longer matches
+        let token = key.sign(&claims(issuer, "nonce-1"));
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let forged = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&json!({
+                "iss": issuer, "aud": CLIENT_ID, "sub": "admin", "nonce": "nonce-1", "exp": now() + 300,
+            }))
+            .unwrap(),
+        );
+        parts[1] = &forged;
+        assert!(validate_id_token(&parts.join("."), &jwks, issuer, CLIENT_ID, "nonce-1").is_err());
+
+        // Symmetric algorithms are refused outright
+        let hs256 = encode(
+            &Header::new(Algorithm::HS256),
+            &claims(issuer, "nonce-1"),
+            &EncodingKey::from_secret(CLIENT_ID.as_bytes()),
+        )
+        .unwrap();
+        assert!(validate_id_token(&hs256, &jwks, issuer, CLIENT_ID, "nonce-1").is_err());
+    }
+}
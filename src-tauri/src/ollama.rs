@@ -0,0 +1,233 @@
+// src-tauri/src/ollama.rs
+
+// --- Dependencies ---
+use crate::config::AppConfig;
+use crate::conversation::ChatRole;
+use crate::provider::{
+    parse_error, split_data_url, Completion, ModelInfo, Provider, ProviderEvent, ProviderQuery,
+    StreamFormat, Usage,
+};
+use crate::query_error::QueryError;
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// --- Ollama ---
+// A local Ollama server's native `/api/chat`. Images are attached to the message as
+// bare base64 strings (no `data:` prefix), and the stream is one JSON object per line.
+// Ollama streams by default, so `stream` is always sent explicitly.
+
+#[derive(Debug, Clone)]
+pub struct OllamaProvider {
+    pub base_url: String, // e.g. `http://127.0.0.1:11434`
+    pub model: Option<String>,
+}
+
+impl OllamaProvider {
+    // `None` unless the base URL is configured
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        Some(OllamaProvider {
+            base_url: config.ollama_base_url.as_ref()?.to_string(),
+            model: config.ollama_model.clone(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+}
+
+// A whole response, or one line of a stream; the last line has `done` and the counts
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: Option<ResponseMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: String,
+}
+
+impl ChatResponse {
+    fn usage(&self) -> Option<Usage> {
+        (self.prompt_eval_count.is_some() || self.eval_count.is_some()).then_some(Usage {
+            input_tokens: self.prompt_eval_count,
+            output_tokens: self.eval_count,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct TagList {
+    models: Vec<TagEntry>,
+}
+
+#[derive(Deserialize)]
+struct TagEntry {
+    name: String, // e.g. `llava:latest`
+}
+
+impl Provider for OllamaProvider {
+    fn display_name(&self) -> String {
+        "Ollama".to_string()
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::NdJson
+    }
+
+    fn query_request(&self, client: &reqwest::Client) -> RequestBuilder {
+        client.post(self.url("api/chat"))
+    }
+
+    fn request_body(&self, query: &ProviderQuery) -> Value {
+        let mut messages: Vec<Value> = query
+            .history
+            .iter()
+            .map(|turn| json!({ "role": turn.role.as_str(), "content": turn.text }))
+            .collect();
+        let images: Vec<&str> = query
+            .image_data_urls
+            .iter()
+            .filter_map(|url| split_data_url(url).map(|(_, data)| data))
+            .collect();
+        let mut message = json!({ "role": ChatRole::User.as_str(), "content": query.text });
+        if !images.is_empty() {
+            message["images"] = json!(images);
+        }
+        messages.push(message);
+        json!({ "model": query.model, "messages": messages, "stream": query.stream })
+    }
+
+    fn parse_completion(&self, body: Value) -> Result<Completion, QueryError> {
+        let response: ChatResponse =
+            serde_json::from_value(body).map_err(|e| parse_error(e.to_string()))?;
+        let usage = response.usage();
+        let text = response
+            .message
+            .map(|message| message.content)
+            .ok_or_else(|| parse_error("response has no message"))?;
+        Ok(Completion { text, usage })
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Vec<ProviderEvent> {
+        let response = match serde_json::from_str::<ChatResponse>(data.trim()) {
+            Ok(response) => response,
+            Err(e) => {
+                return vec![ProviderEvent::Error(format!(
+                    "unexpected stream data: {}",
+                    e
+                ))]
+            }
+        };
+        if let Some(error) = response.error {
+            return vec![ProviderEvent::Error(error)];
+        }
+        let mut events = Vec::new();
+        if let Some(usage) = response.usage() {
+            events.push(ProviderEvent::Usage(usage));
+        }
+        if let Some(message) = response.message {
+            events.push(ProviderEvent::Delta(message.content));
+        }
+        if response.done {
+            events.push(ProviderEvent::Done);
+        }
+        events
+    }
+
+    fn models_request(&self, client: &reqwest::Client) -> RequestBuilder {
+        client.get(self.url("api/tags"))
+    }
+
+    fn parse_models(&self, body: Value) -> Result<Vec<ModelInfo>, QueryError> {
+        let tags: TagList = serde_json::from_value(body).map_err(|e| parse_error(e.to_string()))?;
+        Ok(tags
+            .models
+            .into_iter()
+            .map(|model| ModelInfo {
+                id: model.name,
+                name: None,
+            })
+            .collect())
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> OllamaProvider {
+        OllamaProvider {
+            base_url: "http://127.0.0.1:11434/".to_string(),
+            model: None,
+        }
+    }
+
+    #[test]
+    fn test_request_sends_bare_base64_images() {
+        let images = ["data:image/png;base64,iVBORw0K".to_string()];
+        let body = provider().request_body(&ProviderQuery {
+            model: "llava",
+            text: "What is this?",
+            image_data_urls: &images,
+            history: &[],
+            stream: false,
+        });
+        assert_eq!(
+            body,
+            json!({
+                "model": "llava",
+                "messages": [{ "role": "user", "content": "What is this?", "images": ["iVBORw0K"] }],
+                "stream": false,
+            })
+        );
+        let request = provider()
+            .query_request(&reqwest::Client::new())
+            .build()
+            .unwrap();
+        assert_eq!(request.url().as_str(), "http://127.0.0.1:11434/api/chat");
+    }
+
+    #[test]
+    fn test_parse_stream_event_variants() {
+        let provider = provider();
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#
+            ),
+            [ProviderEvent::Delta("Hel".to_string())]
+        );
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":20,"eval_count":2}"#
+            ),
+            [
+                ProviderEvent::Usage(Usage {
+                    input_tokens: Some(20),
+                    output_tokens: Some(2)
+                }),
+                ProviderEvent::Delta(String::new()),
+                ProviderEvent::Done
+            ]
+        );
+        assert_eq!(
+            provider.parse_stream_event(r#"{"error":"model 'nope' not found"}"#),
+            [ProviderEvent::Error("model 'nope' not found".to_string())]
+        );
+        assert!(matches!(
+            provider.parse_stream_event("not json")[..],
+            [ProviderEvent::Error(_)]
+        ));
+    }
+}
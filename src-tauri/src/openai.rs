@@ -0,0 +1,352 @@
+// src-tauri/src/openai.rs
+
+// --- Dependencies ---
+use crate::config::AppConfig;
+use crate::conversation::ChatRole;
+use crate::provider::{
+    parse_error, Completion, ModelInfo, Provider, ProviderEvent, ProviderQuery, Usage,
+};
+use crate::query_error::QueryError;
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// --- OpenAI-compatible provider ---
+// Talks to `POST {base}/chat/completions` on any OpenAI-compatible server (OpenAI,
+// LM Studio, vLLM, Ollama's `/v1`, ...). Screenshots go inline as `image_url` parts
+// holding their `data:` URL; prior turns are sent as plain text.
+
+#[derive(Debug, Clone)]
+pub struct OpenAiProvider {
+    pub base_url: String,        // e.g. `https://api.openai.com/v1`
+    pub api_key: Option<String>, // Sent as a bearer token when set
+    pub model: Option<String>,
+    pub stream_usage: bool, // Sends `stream_options`, which some servers reject
+}
+
+impl OpenAiProvider {
+    // `None` unless the base URL is configured
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        Some(OpenAiProvider {
+            base_url: config.openai_base_url.as_ref()?.to_string(),
+            api_key: config.openai_api_key.clone(),
+            model: config.openai_model.clone(),
+            stream_usage: config.openai_stream_usage,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+}
+
+// Usage as OpenAI reports it, on the completion and on the last stream chunk
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+}
+
+impl From<OpenAiUsage> for Usage {
+    fn from(usage: OpenAiUsage) -> Self {
+        Usage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatCompletion {
+    choices: Vec<CompletionChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Deserialize)]
+struct CompletionMessage {
+    content: Option<String>,
+}
+
+// One `data:` event of a streamed completion
+#[derive(Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<OpenAiUsage>,
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: Option<ChunkDelta>,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+impl Provider for OpenAiProvider {
+    fn display_name(&self) -> String {
+        "OpenAI-compatible".to_string()
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    fn query_request(&self, client: &reqwest::Client) -> RequestBuilder {
+        self.authorize(client.post(self.url("chat/completions")))
+    }
+
+    fn request_body(&self, query: &ProviderQuery) -> Value {
+        let mut messages: Vec<Value> = query
+            .history
+            .iter()
+            .map(|turn| json!({ "role": turn.role.as_str(), "content": turn.text }))
+            .collect();
+        let content = if query.image_data_urls.is_empty() {
+            json!(query.text)
+        } else {
+            let text = json!({ "type": "text", "text": query.text });
+            let images = query
+                .image_data_urls
+                .iter()
+                .map(|url| json!({ "type": "image_url", "image_url": { "url": url } }));
+            Value::Array(std::iter::once(text).chain(images).collect())
+        };
+        messages.push(json!({ "role": ChatRole::User.as_str(), "content": content }));
+
+        let mut body = json!({ "model": query.model, "messages": messages });
+        if query.stream {
+            body["stream"] = json!(true);
+        }
+        // Asks for a last chunk with the token counts. Opt-in: servers that don't know
+        // the option may reject the whole request with a 400.
+        if query.stream && self.stream_usage {
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+        body
+    }
+
+    fn parse_completion(&self, body: Value) -> Result<Completion, QueryError> {
+        let completion: ChatCompletion =
+            serde_json::from_value(body).map_err(|e| parse_error(e.to_string()))?;
+        let text = completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| parse_error("completion has no message content"))?;
+        Ok(Completion {
+            text,
+            usage: completion.usage.map(Usage::from),
+        })
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Vec<ProviderEvent> {
+        let trimmed = data.trim();
+        if trimmed == "[DONE]" {
+            return vec![ProviderEvent::Done];
+        }
+        let chunk = match serde_json::from_str::<ChatCompletionChunk>(trimmed) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return vec![ProviderEvent::Error(format!(
+                    "unexpected stream data: {}",
+                    e
+                ))]
+            }
+        };
+        if let Some(error) = chunk.error {
+            return vec![ProviderEvent::Error(error.message)];
+        }
+        // The first chunk only carries the role and the last one only the finish
+        // reason; both come out as empty deltas
+        let delta: String = chunk
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.delta.and_then(|delta| delta.content))
+            .collect();
+        let mut events = vec![ProviderEvent::Delta(delta)];
+        if let Some(usage) = chunk.usage {
+            events.push(ProviderEvent::Usage(usage.into()));
+        }
+        events
+    }
+
+    fn models_request(&self, client: &reqwest::Client) -> RequestBuilder {
+        self.authorize(client.get(self.url("models")))
+    }
+
+    fn parse_models(&self, body: Value) -> Result<Vec<ModelInfo>, QueryError> {
+        let list: ModelList =
+            serde_json::from_value(body).map_err(|e| parse_error(e.to_string()))?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|model| ModelInfo {
+                id: model.id,
+                name: None,
+            })
+            .collect())
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ChatTurn;
+    use crate::mock_server::MOCK_MODEL;
+
+    fn query<'a>(text: &'a str, images: &'a [String], stream: bool) -> ProviderQuery<'a> {
+        ProviderQuery {
+            model: MOCK_MODEL,
+            text,
+            image_data_urls: images,
+            history: &[],
+            stream,
+        }
+    }
+
+    #[test]
+    fn test_request_sends_images_as_data_urls() {
+        let mut provider = OpenAiProvider {
+            base_url: "https://api.example/v1".to_string(),
+            api_key: None,
+            model: None,
+            stream_usage: true,
+        };
+        let history = [ChatTurn {
+            role: ChatRole::Assistant,
+            text: "Earlier answer".to_string(),
+            image_ref: Some("shot-1.png".to_string()),
+        }];
+        let images = ["data:image/png;base64,AAAA".to_string()];
+        let body = provider.request_body(&ProviderQuery {
+            model: "gpt-4o-mini",
+            text: "What is this?",
+            image_data_urls: &images,
+            history: &history,
+            stream: true,
+        });
+        assert_eq!(
+            body,
+            json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    { "role": "assistant", "content": "Earlier answer" },
+                    { "role": "user", "content": [
+                        { "type": "text", "text": "What is this?" },
+                        { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } },
+                    ]},
+                ],
+                "stream": true,
+                "stream_options": { "include_usage": true },
+            })
+        );
+
+        let body = provider.request_body(&query("Hi", &[], false));
+        assert_eq!(
+            body,
+            json!({ "model": MOCK_MODEL, "messages": [{ "role": "user", "content": "Hi" }] })
+        );
+
+        // Token counts in streams are opt-in
+        provider.stream_usage = false;
+        let body = provider.request_body(&query("Hi", &[], true));
+        assert_eq!(body["stream"], json!(true));
+        assert!(body.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_parse_stream_event_variants() {
+        let provider = OpenAiProvider {
+            base_url: String::new(),
+            api_key: None,
+            model: None,
+            stream_usage: false,
+        };
+        assert_eq!(
+            provider.parse_stream_event(" [DONE] "),
+            [ProviderEvent::Done]
+        );
+        assert_eq!(
+            provider.parse_stream_event(r#"{"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#),
+            [ProviderEvent::Delta("Hel".to_string())]
+        );
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":2}}"#
+            ),
+            [
+                ProviderEvent::Delta(String::new()),
+                ProviderEvent::Usage(Usage {
+                    input_tokens: Some(9),
+                    output_tokens: Some(2)
+                })
+            ]
+        );
+        assert_eq!(
+            provider.parse_stream_event(
+                r#"{"error":{"message":"model overloaded","type":"server_error"}}"#
+            ),
+            [ProviderEvent::Error("model overloaded".to_string())]
+        );
+        assert!(matches!(
+            provider.parse_stream_event("not json")[..],
+            [ProviderEvent::Error(_)]
+        ));
+    }
+
+    #[test]
+    fn test_api_key_is_sent_as_bearer_token() {
+        let client = reqwest::Client::new();
+        let mut provider = OpenAiProvider {
+            base_url: "https://api.example/v1".to_string(),
+            api_key: Some("sk-test".to_string()),
+            model: None,
+            stream_usage: false,
+        };
+        let query = provider.query_request(&client).build().unwrap();
+        assert_eq!(
+            query.url().as_str(),
+            "https://api.example/v1/chat/completions"
+        );
+        assert_eq!(query.headers()["authorization"], "Bearer sk-test");
+        let models = provider.models_request(&client).build().unwrap();
+        assert_eq!(models.headers()["authorization"], "Bearer sk-test");
+
+        provider.api_key = None;
+        let query = provider.query_request(&client).build().unwrap();
+        assert!(query.headers().get("authorization").is_none());
+    }
+}
@@ -0,0 +1,679 @@
+// src-tauri/src/pipeline.rs
+
+// --- Dependencies ---
+use crate::config::{self, AppConfig, PIPELINE_REASONING_PROFILE, PIPELINE_VISION_PROFILE};
+use crate::conversation::ChatTurn;
+use crate::http::RetryAttempt;
+use crate::provider::{
+    self, provider_from_config, select_model, Completion, Provider, ProviderQuery, Usage,
+};
+use crate::query_error::QueryError;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// --- Vision-then-reasoning pipeline ---
+// The worker's two-step flow for screenshot queries, run by the app on direct
+// providers: the vision stage describes the screenshots as JSON, then the reasoning
+// stage answers the question from that description without seeing the images. Each
+// stage has its own provider, model, prompt template and output check, and reports its
+// progress as `query_pipeline_stage` events, so the user can inspect what the vision
+// model saw. A query without screenshots goes straight to the reasoning stage.
+
+pub const PIPELINE_STAGE_EVENT: &str = "query_pipeline_stage";
+
+// Built-in templates. Placeholders: `{{os}}`, `{{question}}` and, for the reasoning
+// stage, `{{description}}`.
+const VISION_TEMPLATE: &str = include_str!("../../Prompt/V1.md");
+const REASONING_TEMPLATE: &str = include_str!("../../Prompt/R1.md");
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum StageKind {
+    Vision,
+    Reasoning,
+}
+
+impl StageKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StageKind::Vision => "vision",
+            StageKind::Reasoning => "reasoning",
+        }
+    }
+}
+
+// What a stage's output must look like before it is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputCheck {
+    NonEmpty,
+    Json, // One JSON document; a surrounding ```json fence is dropped
+}
+
+impl OutputCheck {
+    // The output to pass on, or why it can't be used
+    pub fn apply(self, output: &str) -> Result<String, String> {
+        let output = output.trim();
+        if output.is_empty() {
+            return Err("the output is empty".to_string());
+        }
+        match self {
+            OutputCheck::NonEmpty => Ok(output.to_string()),
+            OutputCheck::Json => {
+                let json = strip_code_fence(output);
+                serde_json::from_str::<Value>(json)
+                    .map_err(|e| format!("the output is not valid JSON: {}", e))?;
+                Ok(json.to_string())
+            }
+        }
+    }
+}
+
+// Models often wrap JSON in a Markdown fence despite being told not to
+fn strip_code_fence(text: &str) -> &str {
+    let Some(inner) = text.strip_prefix("```") else {
+        return text;
+    };
+    let inner = inner.strip_prefix("json").unwrap_or(inner);
+    inner.strip_suffix("```").unwrap_or(inner).trim()
+}
+
+// Replaces `{{name}}` with its value in one pass, so substituted text is never
+// expanded again. Unknown placeholders are left as they are.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = after[..end].trim();
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (end, *value))
+        });
+        match value {
+            Some((end, value)) => {
+                rendered.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                rendered.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+pub struct Stage {
+    pub kind: StageKind,
+    pub provider: Box<dyn Provider>,
+    pub model: String,
+    pub template: String,
+    pub check: OutputCheck,
+}
+
+pub struct Pipeline {
+    pub vision: Stage,
+    pub reasoning: Stage,
+}
+
+// One query, as the pipeline sees it
+pub struct PipelineQuery<'a> {
+    pub question: &'a str,
+    pub os: &'a str, // e.g. `Mac OS 15.4.0`, for the templates
+    pub image_data_urls: &'a [String],
+    pub history: &'a [ChatTurn], // Only given to the reasoning stage
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineAnswer {
+    pub completion: Completion,             // The reasoning stage's answer
+    pub vision_description: Option<String>, // `None` when there were no screenshots
+}
+
+// Progress of one stage, emitted as `query_pipeline_stage`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StageReport {
+    pub request_id: Option<String>, // Filled in by the command, like `RetryAttempt`
+    pub stage: StageKind,
+    pub provider: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub status: StageStatus,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum StageStatus {
+    Started,
+    Completed {
+        output: String, // After the output check
+        #[serde(skip_serializing_if = "Option::is_none")]
+        usage: Option<Usage>,
+    },
+    Failed {
+        error: QueryError,
+    },
+}
+
+impl Pipeline {
+    // Both stages as configured, failing on the first one that can't run
+    pub fn from_config(config: &AppConfig) -> Result<Pipeline, QueryError> {
+        Ok(Pipeline {
+            vision: Stage::from_config(StageKind::Vision, config)?,
+            reasoning: Stage::from_config(StageKind::Reasoning, config)?,
+        })
+    }
+
+    // Runs both stages and returns the reasoning stage's answer. `timeout` covers both
+    // stages: the reasoning stage gets whatever the vision stage left of it.
+    pub async fn complete(
+        &self,
+        client: &reqwest::Client,
+        query: &PipelineQuery<'_>,
+        timeout: Option<Duration>,
+        mut on_retry: impl FnMut(&RetryAttempt),
+        mut on_stage: impl FnMut(StageReport),
+    ) -> Result<PipelineAnswer, QueryError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let description = self
+            .describe(client, query, timeout, &mut on_retry, &mut on_stage)
+            .await?;
+        let timeout = deadline.map(remaining).transpose()?;
+        let stage = &self.reasoning;
+        let prompt = self.reasoning_prompt(query, description.as_deref());
+        on_stage(stage.report(StageStatus::Started));
+        let result = provider::complete(
+            stage.provider.as_ref(),
+            client,
+            &stage.query(&prompt, &[], query.history, false),
+            timeout,
+            on_retry,
+        )
+        .await
+        .and_then(|completion| stage.check_output(completion));
+        on_stage(stage.finished(&result));
+        Ok(PipelineAnswer {
+            completion: result?,
+            vision_description: description,
+        })
+    }
+
+    // Like `complete`, but streams the reasoning stage's answer to `on_delta`. The
+    // vision stage is always one-shot, since its output is only usable once complete.
+    pub async fn stream(
+        &self,
+        client: &reqwest::Client,
+        query: &PipelineQuery<'_>,
+        timeout: Duration,
+        mut on_retry: impl FnMut(&RetryAttempt),
+        mut on_stage: impl FnMut(StageReport),
+        on_delta: impl FnMut(String),
+    ) -> Result<PipelineAnswer, QueryError> {
+        let deadline = Instant::now() + timeout;
+        let description = self
+            .describe(client, query, Some(timeout), &mut on_retry, &mut on_stage)
+            .await?;
+        let timeout = remaining(deadline)?;
+        let stage = &self.reasoning;
+        let prompt = self.reasoning_prompt(query, description.as_deref());
+        on_stage(stage.report(StageStatus::Started));
+        let result = provider::stream(
+            stage.provider.as_ref(),
+            client,
+            &stage.query(&prompt, &[], query.history, true),
+            timeout,
+            on_retry,
+            on_delta,
+        )
+        .await
+        .and_then(|completion| stage.check_output(completion));
+        on_stage(stage.finished(&result));
+        Ok(PipelineAnswer {
+            completion: result?,
+            vision_description: description,
+        })
+    }
+
+    // Runs the vision stage; `None` when the query has no screenshots
+    async fn describe(
+        &self,
+        client: &reqwest::Client,
+        query: &PipelineQuery<'_>,
+        timeout: Option<Duration>,
+        on_retry: &mut impl FnMut(&RetryAttempt),
+        on_stage: &mut impl FnMut(StageReport),
+    ) -> Result<Option<String>, QueryError> {
+        if query.image_data_urls.is_empty() {
+            println!("[pipeline] No screenshots; skipping the vision stage.");
+            return Ok(None);
+        }
+        let stage = &self.vision;
+        let prompt = render(
+            &stage.template,
+            &[("os", query.os), ("question", query.question)],
+        );
+        on_stage(stage.report(StageStatus::Started));
+        let result = provider::complete(
+            stage.provider.as_ref(),
+            client,
+            &stage.query(&prompt, query.image_data_urls, &[], false),
+            timeout,
+            on_retry,
+        )
+        .await
+        .and_then(|completion| stage.check_output(completion));
+        on_stage(stage.finished(&result));
+        Ok(Some(result?.text))
+    }
+
+    // Without a description there is nothing to reason about; the question goes as is
+    fn reasoning_prompt(&self, query: &PipelineQuery, description: Option<&str>) -> String {
+        match description {
+            Some(description) => render(
+                &self.reasoning.template,
+                &[
+                    ("os", query.os),
+                    ("question", query.question),
+                    ("description", description),
+                ],
+            ),
+            None => query.question.to_string(),
+        }
+    }
+}
+
+// What the vision stage left of the overall timeout, for the reasoning stage
+fn remaining(deadline: Instant) -> Result<Duration, QueryError> {
+    match deadline.saturating_duration_since(Instant::now()) {
+        left if left.is_zero() => Err(QueryError::Timeout {
+            message: "the vision stage used up the whole timeout".to_string(),
+        }),
+        left => Ok(left),
+    }
+}
+
+impl Stage {
+    fn from_config(kind: StageKind, config: &AppConfig) -> Result<Stage, QueryError> {
+        let (profile_key, profile, model, prompt, builtin, check) = match kind {
+            StageKind::Vision => (
+                PIPELINE_VISION_PROFILE,
+                config.pipeline_vision_profile,
+                config.pipeline_vision_model.as_deref(),
+                config.pipeline_vision_prompt.as_deref(),
+                VISION_TEMPLATE,
+                OutputCheck::Json,
+            ),
+            StageKind::Reasoning => (
+                PIPELINE_REASONING_PROFILE,
+                config.pipeline_reasoning_profile,
+                config.pipeline_reasoning_model.as_deref(),
+                config.pipeline_reasoning_prompt.as_deref(),
+                REASONING_TEMPLATE,
+                OutputCheck::NonEmpty,
+            ),
+        };
+        let profile = profile.ok_or_else(|| QueryError::ConfigMissing {
+            message: config::describe_issues(profile_key),
+        })?;
+        let provider =
+            provider_from_config(profile, config).ok_or_else(|| QueryError::ConfigMissing {
+                message: format!(
+                    "{} uses the {:?} provider, which is not configured",
+                    profile_key, profile
+                ),
+            })?;
+        let model = select_model(provider.as_ref(), model)?;
+        let template = match prompt {
+            Some(path) => load_template(path)?,
+            None => builtin.to_string(),
+        };
+        Ok(Stage {
+            kind,
+            provider,
+            model,
+            template,
+            check,
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        text: &'a str,
+        image_data_urls: &'a [String],
+        history: &'a [ChatTurn],
+        stream: bool,
+    ) -> ProviderQuery<'a> {
+        println!(
+            "[pipeline] Running the {} stage on {} (model {})",
+            self.kind.as_str(),
+            self.provider.display_name(),
+            self.model
+        );
+        ProviderQuery {
+            model: &self.model,
+            text,
+            image_data_urls,
+            history,
+            stream,
+        }
+    }
+
+    fn check_output(&self, completion: Completion) -> Result<Completion, QueryError> {
+        match self.check.apply(&completion.text) {
+            Ok(text) => Ok(Completion {
+                text,
+                usage: completion.usage,
+            }),
+            Err(message) => {
+                eprintln!(
+                    "[pipeline] Rejected {} output ({}): {}",
+                    self.kind.as_str(),
+                    message,
+                    completion.text
+                );
+                Err(QueryError::InvalidStageOutput {
+                    stage: self.kind.as_str().to_string(),
+                    message,
+                })
+            }
+        }
+    }
+
+    fn report(&self, status: StageStatus) -> StageReport {
+        StageReport {
+            request_id: None,
+            stage: self.kind,
+            provider: self.provider.display_name(),
+            model: self.model.clone(),
+            status,
+        }
+    }
+
+    fn finished(&self, result: &Result<Completion, QueryError>) -> StageReport {
+        self.report(match result {
+            Ok(completion) => StageStatus::Completed {
+                output: completion.text.clone(),
+                usage: completion.usage,
+            },
+            Err(error) => StageStatus::Failed {
+                error: error.clone(),
+            },
+        })
+    }
+}
+
+fn load_template(path: &Path) -> Result<String, QueryError> {
+    std::fs::read_to_string(path).map_err(|e| QueryError::ConfigMissing {
+        message: format!("Failed to read prompt template '{}': {}", path.display(), e),
+    })
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueryProfile;
+    use crate::mock_server::{MockResponse, MockServer, MOCK_ANSWER, MOCK_MODEL};
+    use crate::ollama::OllamaProvider;
+    use crate::openai::OpenAiProvider;
+    use serde_json::json;
+
+    const DESCRIPTION: &str = r#"{"main_window":"Settings","relevant_elements":[]}"#;
+
+    fn pipeline(vision: &MockServer, reasoning: &MockServer) -> Pipeline {
+        Pipeline {
+            vision: Stage {
+                kind: StageKind::Vision,
+                provider: Box::new(OpenAiProvider {
+                    base_url: format!("{}/v1", vision.url()),
+                    api_key: None,
+                    model: None,
+                    stream_usage: false,
+                }),
+                model: MOCK_MODEL.to_string(),
+                template: "Describe for '{{question}}' on {{os}}".to_string(),
+                check: OutputCheck::Json,
+            },
+            reasoning: Stage {
+                kind: StageKind::Reasoning,
+                provider: Box::new(OllamaProvider {
+                    base_url: reasoning.url().to_string(),
+                    model: None,
+                }),
+                model: "reasoner".to_string(),
+                template: "Q: {{question}}\n{{description}}".to_string(),
+                check: OutputCheck::NonEmpty,
+            },
+        }
+    }
+
+    fn vision_answer(content: &str) -> MockResponse {
+        MockResponse::ok(json!({
+            "choices": [{ "message": { "role": "assistant", "content": content } }],
+        }))
+    }
+
+    #[test]
+    fn test_render_fills_known_placeholders_once() {
+        assert_eq!(
+            render(
+                "{{ question }} on {{os}}: {{description}} {{unknown}} {{",
+                &[("question", "{{os}}?"), ("os", "macOS")]
+            ),
+            "{{os}}? on macOS: {{description}} {{unknown}} {{"
+        );
+        // The built-in templates use the placeholders the stages fill in
+        assert!(VISION_TEMPLATE.contains("{{question}}") && VISION_TEMPLATE.contains("{{os}}"));
+        assert!(REASONING_TEMPLATE.contains("{{description}}"));
+    }
+
+    #[test]
+    fn test_output_checks() {
+        assert_eq!(
+            OutputCheck::Json.apply("```json\n{\"a\": 1}\n```\n"),
+            Ok("{\"a\": 1}".to_string())
+        );
+        assert_eq!(OutputCheck::Json.apply("[1, 2]"), Ok("[1, 2]".to_string()));
+        assert!(OutputCheck::Json
+            .apply("The window shows settings.")
+            .unwrap_err()
+            .contains("not valid JSON"));
+        assert_eq!(
+            OutputCheck::NonEmpty.apply("  \n"),
+            Err("the output is empty".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stages_share_the_timeout() {
+        let left = remaining(Instant::now() + Duration::from_secs(10)).unwrap();
+        assert!(left > Duration::from_secs(9) && left <= Duration::from_secs(10));
+        assert!(matches!(
+            remaining(Instant::now()),
+            Err(QueryError::Timeout { .. })
+        ));
+    }
+
+    #[test]
+    fn test_stages_from_config() {
+        let config = AppConfig {
+            ollama_base_url: Some(url::Url::parse("http://127.0.0.1:11434").unwrap()),
+            ollama_model: Some("llava".to_string()),
+            pipeline_vision_profile: Some(QueryProfile::Ollama),
+            pipeline_reasoning_profile: Some(QueryProfile::OpenAi),
+            ..Default::default()
+        };
+        let err = Pipeline::from_config(&config).err().unwrap();
+        assert!(
+            matches!(&err, QueryError::ConfigMissing { message } if message.contains(PIPELINE_REASONING_PROFILE)),
+            "{:?}",
+            err
+        );
+
+        let vision = Stage::from_config(StageKind::Vision, &config).unwrap();
+        assert_eq!(vision.model, "llava");
+        assert_eq!(vision.template, VISION_TEMPLATE);
+        assert_eq!(vision.check, OutputCheck::Json);
+
+        let config = AppConfig {
+            pipeline_vision_model: Some("llava:13b".to_string()),
+            pipeline_vision_prompt: Some("/does/not/exist.md".into()),
+            ..config
+        };
+        assert!(matches!(
+            Stage::from_config(StageKind::Vision, &config),
+            Err(QueryError::ConfigMissing { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_vision_description_feeds_reasoning() {
+        let vision = MockServer::openai().await;
+        let reasoning = MockServer::ollama().await;
+        vision.enqueue(
+            "/v1/chat/completions",
+            vision_answer(&format!("```json\n{}\n```", DESCRIPTION)),
+        );
+        let images = ["data:image/png;base64,iVBORw0K".to_string()];
+        let history = [ChatTurn {
+            role: crate::conversation::ChatRole::User,
+            text: "Earlier".to_string(),
+            image_ref: None,
+        }];
+        let query = PipelineQuery {
+            question: "Why is it greyed out?",
+            os: "macOS 15.4",
+            image_data_urls: &images,
+            history: &history,
+        };
+
+        let mut reports = Vec::new();
+        let answer = pipeline(&vision, &reasoning)
+            .complete(
+                &reqwest::Client::new(),
+                &query,
+                None,
+                |_| {},
+                |report| reports.push(report),
+            )
+            .await
+            .unwrap();
+        assert_eq!(answer.completion.text, MOCK_ANSWER);
+        assert_eq!(answer.vision_description.as_deref(), Some(DESCRIPTION));
+
+        // The vision stage sees the screenshot but not the history
+        let sent = vision.requests("/v1/chat/completions")[0].json();
+        assert_eq!(sent["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            sent["messages"][0]["content"][0]["text"],
+            "Describe for 'Why is it greyed out?' on macOS 15.4"
+        );
+        assert_eq!(
+            sent["messages"][0]["content"][1]["image_url"]["url"],
+            images[0]
+        );
+        // The reasoning stage sees the description and the history, not the screenshot
+        let sent = reasoning.requests("/api/chat")[0].json();
+        assert_eq!(sent["model"], "reasoner");
+        assert_eq!(sent["messages"][0]["content"], "Earlier");
+        assert_eq!(
+            sent["messages"][1],
+            json!({ "role": "user", "content": format!("Q: Why is it greyed out?\n{}", DESCRIPTION) })
+        );
+
+        let statuses: Vec<(StageKind, &StageStatus)> = reports
+            .iter()
+            .map(|report| (report.stage, &report.status))
+            .collect();
+        assert_eq!(statuses.len(), 4);
+        assert_eq!(statuses[0], (StageKind::Vision, &StageStatus::Started));
+        assert!(matches!(
+            statuses[1],
+            (StageKind::Vision, StageStatus::Completed { output, .. }) if output == DESCRIPTION
+        ));
+        assert_eq!(statuses[2], (StageKind::Reasoning, &StageStatus::Started));
+        assert_eq!(
+            serde_json::to_value(&reports[3]).unwrap(),
+            json!({
+                "requestId": null,
+                "stage": "reasoning",
+                "provider": "Ollama",
+                "model": "reasoner",
+                "status": "completed",
+                "output": MOCK_ANSWER,
+                "usage": { "inputTokens": 12, "outputTokens": 3 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_description_stops_the_pipeline() {
+        let vision = MockServer::openai().await;
+        let reasoning = MockServer::ollama().await;
+        vision.enqueue("/v1/chat/completions", vision_answer("A settings window."));
+        let images = ["data:image/png;base64,iVBORw0K".to_string()];
+        let query = PipelineQuery {
+            question: "Why?",
+            os: "macOS 15.4",
+            image_data_urls: &images,
+            history: &[],
+        };
+
+        let mut reports = Vec::new();
+        let err = pipeline(&vision, &reasoning)
+            .stream(
+                &reqwest::Client::new(),
+                &query,
+                Duration::from_secs(5),
+                |_| {},
+                |report| reports.push(report),
+                |_| {},
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            QueryError::InvalidStageOutput { stage, .. } if stage == "vision"
+        ));
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1].status, StageStatus::Failed { error: err });
+        assert!(reasoning.requests("/api/chat").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_text_only_query_skips_vision() {
+        let vision = MockServer::openai().await;
+        let reasoning = MockServer::ollama().await;
+        let query = PipelineQuery {
+            question: "What is Rust?",
+            os: "macOS 15.4",
+            image_data_urls: &[],
+            history: &[],
+        };
+        let mut stages = Vec::new();
+        let answer = pipeline(&vision, &reasoning)
+            .complete(
+                &reqwest::Client::new(),
+                &query,
+                None,
+                |_| {},
+                |report| stages.push(report.stage),
+            )
+            .await
+            .unwrap();
+        assert_eq!(answer.vision_description, None);
+        assert_eq!(stages, [StageKind::Reasoning, StageKind::Reasoning]);
+        assert!(vision.requests("/v1/chat/completions").is_empty());
+        assert_eq!(
+            reasoning.requests("/api/chat")[0].json()["messages"][0]["content"],
+            "What is Rust?"
+        );
+    }
+}
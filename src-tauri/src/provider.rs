@@ -0,0 +1,790 @@
+// src-tauri/src/provider.rs
+
+// --- Dependencies ---
+use crate::anthropic::AnthropicProvider;
+use crate::config::{self, AppConfig, QueryProfile, WORKER_API_URL};
+use crate::conversation::ChatTurn;
+use crate::http::{send_with_retry, with_timeout, HttpClient, RetryAttempt, RetryPolicy};
+use crate::ollama::OllamaProvider;
+use crate::openai::OpenAiProvider;
+use crate::query_error::QueryError;
+use crate::stream::{LineDecoder, SseDecoder};
+use reqwest::header::{HeaderMap, ACCEPT, CONTENT_TYPE};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+use tauri::State;
+
+// --- Model providers ---
+// A provider is an AI API the app calls directly instead of going through the worker.
+// Implementations only describe their wire format: how a query becomes a request body
+// (including how screenshots are attached), and how completions, stream events, errors
+// and model lists are read back. Sending, retrying and streaming are shared below.
+// Providers are built from the current config each time, like identity providers.
+
+// One query, in provider-neutral form
+pub struct ProviderQuery<'a> {
+    pub model: &'a str,
+    pub text: &'a str,
+    pub image_data_urls: &'a [String], // `data:` URLs, in the order they were attached
+    pub history: &'a [ChatTurn],       // Prior turns, oldest first, text only
+    pub stream: bool,
+}
+
+// Token counts as reported by the provider; either may be missing
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+}
+
+impl Usage {
+    // Providers report the two counts in different events; later values win
+    fn merge(&mut self, other: Usage) {
+        self.input_tokens = other.input_tokens.or(self.input_tokens);
+        self.output_tokens = other.output_tokens.or(self.output_tokens);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Option<Usage>,
+}
+
+// What one event of a streamed response means
+#[derive(Debug, PartialEq)]
+pub enum ProviderEvent {
+    Delta(String),
+    Usage(Usage),
+    Done,
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamFormat {
+    Sse,    // Server-sent events, one JSON object per `data:` field
+    NdJson, // One JSON object per line
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,           // What goes into `model` of a query
+    pub name: Option<String>, // Display name, if the provider has one
+}
+
+pub trait Provider: Send + Sync {
+    fn display_name(&self) -> String;
+
+    // Used when the query doesn't pick a model
+    fn default_model(&self) -> Option<&str>;
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Sse
+    }
+
+    // The POST that runs a query: URL and auth headers, without the body
+    fn query_request(&self, client: &reqwest::Client) -> RequestBuilder;
+
+    // Message formatting and image encoding
+    fn request_body(&self, query: &ProviderQuery) -> Value;
+
+    fn parse_completion(&self, body: Value) -> Result<Completion, QueryError>;
+
+    // Interprets one SSE `data:` field or NDJSON line
+    fn parse_stream_event(&self, data: &str) -> Vec<ProviderEvent>;
+
+    fn map_error(&self, status: StatusCode, headers: &HeaderMap, body: &str) -> QueryError {
+        QueryError::from_status(status, headers, &error_message(body))
+    }
+
+    // The GET that lists the available models
+    fn models_request(&self, client: &reqwest::Client) -> RequestBuilder;
+
+    fn parse_models(&self, body: Value) -> Result<Vec<ModelInfo>, QueryError>;
+}
+
+// --- Shared helpers for implementations ---
+
+// Splits `data:image/png;base64,AAAA` into the MIME type and the base64 payload
+pub fn split_data_url(data_url: &str) -> Option<(&str, &str)> {
+    let (header, data) = data_url.strip_prefix("data:")?.split_once(',')?;
+    let mime_type = header.strip_suffix(";base64")?;
+    Some((mime_type, data))
+}
+
+// The readable part of an error body: `{"error":{"message":...}}` (OpenAI, Anthropic),
+// `{"error":"..."}` (Ollama), or the body itself
+pub fn error_message(body: &str) -> String {
+    let Ok(value) = serde_json::from_str::<Value>(body) else {
+        return body.to_string();
+    };
+    value["error"]["message"]
+        .as_str()
+        .or_else(|| value["error"].as_str())
+        .map_or_else(|| body.to_string(), str::to_string)
+}
+
+pub fn parse_error(message: impl Into<String>) -> QueryError {
+    QueryError::Parse {
+        message: message.into(),
+    }
+}
+
+// --- Registry ---
+// The configured provider for a direct profile, or why it can't be used
+pub fn provider(profile: QueryProfile) -> Result<Box<dyn Provider>, QueryError> {
+    let config = config::current();
+    provider_from_config(profile, &config).ok_or_else(|| {
+        let missing = profile
+            .required_settings(&config)
+            .into_iter()
+            .find(|(_, is_set)| !is_set);
+        QueryError::ConfigMissing {
+            message: match missing {
+                Some((key, _)) if profile.is_direct() => config::describe_issues(key),
+                _ => format!(
+                    "The {:?} profile is not a direct provider; see {}",
+                    profile, WORKER_API_URL
+                ),
+            },
+        }
+    })
+}
+
+pub fn provider_from_config(
+    profile: QueryProfile,
+    config: &AppConfig,
+) -> Option<Box<dyn Provider>> {
+    match profile {
+        QueryProfile::Worker | QueryProfile::Pipeline => None,
+        QueryProfile::OpenAi => {
+            OpenAiProvider::from_config(config).map(|p| Box::new(p) as Box<dyn Provider>)
+        }
+        QueryProfile::Anthropic => {
+            AnthropicProvider::from_config(config).map(|p| Box::new(p) as Box<dyn Provider>)
+        }
+        QueryProfile::Ollama => {
+            OllamaProvider::from_config(config).map(|p| Box::new(p) as Box<dyn Provider>)
+        }
+    }
+}
+
+// The query's model, else the provider's default
+pub fn select_model(provider: &dyn Provider, model: Option<&str>) -> Result<String, QueryError> {
+    model
+        .filter(|model| !model.trim().is_empty())
+        .or(provider.default_model())
+        .map(str::to_string)
+        .ok_or_else(|| QueryError::ConfigMissing {
+            message: format!(
+                "No model selected for {}; pick one or configure a default",
+                provider.display_name()
+            ),
+        })
+}
+
+// --- Requests ---
+
+// Sends a one-shot query and returns the answer
+pub async fn complete(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    query: &ProviderQuery<'_>,
+    timeout: Option<Duration>,
+    on_retry: impl FnMut(&RetryAttempt),
+) -> Result<Completion, QueryError> {
+    let response = send(provider, client, query, timeout, on_retry).await?;
+    read_completion(provider, response).await
+}
+
+// Sends a streamed query, passing each piece of the answer to `on_delta`.
+// Returns the accumulated text.
+pub async fn stream(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    query: &ProviderQuery<'_>,
+    timeout: Duration,
+    on_retry: impl FnMut(&RetryAttempt),
+    mut on_delta: impl FnMut(String),
+) -> Result<Completion, QueryError> {
+    let mut response = send(provider, client, query, Some(timeout), on_retry).await?;
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    // Some servers ignore `stream` and answer with the whole completion
+    if provider.stream_format() == StreamFormat::Sse && content_type.starts_with("application/json")
+    {
+        println!(
+            "[{}] Server did not stream; relaying JSON answer.",
+            provider.display_name()
+        );
+        let completion = read_completion(provider, response).await?;
+        on_delta(completion.text.clone());
+        return Ok(completion);
+    }
+
+    let mut decoder = BodyDecoder::new(provider.stream_format());
+    let mut text = String::new();
+    let mut usage: Option<Usage> = None;
+    loop {
+        let chunk = response.chunk().await.map_err(QueryError::from)?;
+        let finished = chunk.is_none();
+        let data_items = match chunk {
+            Some(bytes) => decoder.feed(&bytes),
+            None => decoder.finish(),
+        };
+        for data in data_items {
+            for event in provider.parse_stream_event(&data) {
+                match event {
+                    ProviderEvent::Delta(delta) if delta.is_empty() => {}
+                    ProviderEvent::Delta(delta) => {
+                        text.push_str(&delta);
+                        on_delta(delta);
+                    }
+                    ProviderEvent::Usage(reported) => {
+                        usage.get_or_insert_with(Usage::default).merge(reported)
+                    }
+                    ProviderEvent::Done => return Ok(Completion { text, usage }),
+                    ProviderEvent::Error(message) => {
+                        return Err(QueryError::StreamFailed { message })
+                    }
+                }
+            }
+        }
+        if finished {
+            break;
+        }
+    }
+    println!(
+        "[{}] Stream ended without an end marker ({} chars).",
+        provider.display_name(),
+        text.len()
+    );
+    Ok(Completion { text, usage })
+}
+
+// Lists the models the provider offers
+pub async fn fetch_models(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+) -> Result<Vec<ModelInfo>, QueryError> {
+    // Listing is idempotent and may be retried freely
+    let response = send_with_retry(
+        "list_models",
+        &RetryPolicy::default(),
+        true,
+        || provider.models_request(client),
+        |_| {},
+    )
+    .await?;
+    let body = read_json(provider, response).await?;
+    provider.parse_models(body)
+}
+
+// Posts the query, retrying what is safe to retry, and maps a non-success status
+async fn send(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    query: &ProviderQuery<'_>,
+    timeout: Option<Duration>,
+    on_retry: impl FnMut(&RetryAttempt),
+) -> Result<reqwest::Response, QueryError> {
+    let body = provider.request_body(query);
+    println!(
+        "[{}] Sending {} request for model {} ({} image(s), {} prior turn(s))",
+        provider.display_name(),
+        if query.stream {
+            "streaming"
+        } else {
+            "one-shot"
+        },
+        query.model,
+        query.image_data_urls.len(),
+        query.history.len()
+    );
+    // Not idempotent: a retry may be billed twice, see `http::retryable_status`
+    let response = send_with_retry(
+        "provider_query",
+        &RetryPolicy::default(),
+        false,
+        || {
+            let mut request = provider.query_request(client).json(&body);
+            if query.stream && provider.stream_format() == StreamFormat::Sse {
+                request = request.header(ACCEPT, "text/event-stream");
+            }
+            with_timeout(request, timeout)
+        },
+        on_retry,
+    )
+    .await?;
+
+    let status = response.status();
+    println!(
+        "[{}] Server responded with status: {}",
+        provider.display_name(),
+        status
+    );
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(error_from_response(provider, response).await)
+    }
+}
+
+async fn error_from_response(provider: &dyn Provider, response: reqwest::Response) -> QueryError {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Failed to read error body".to_string());
+    provider.map_error(status, &headers, &body)
+}
+
+async fn read_json(
+    provider: &dyn Provider,
+    response: reqwest::Response,
+) -> Result<Value, QueryError> {
+    if !response.status().is_success() {
+        return Err(error_from_response(provider, response).await);
+    }
+    response
+        .json::<Value>()
+        .await
+        .map_err(|e| parse_error(e.to_string()))
+}
+
+async fn read_completion(
+    provider: &dyn Provider,
+    response: reqwest::Response,
+) -> Result<Completion, QueryError> {
+    let completion = provider.parse_completion(read_json(provider, response).await?)?;
+    if let Some(usage) = completion.usage {
+        println!(
+            "[{}] Usage: {:?} input / {:?} output tokens",
+            provider.display_name(),
+            usage.input_tokens,
+            usage.output_tokens
+        );
+    }
+    Ok(completion)
+}
+
+enum BodyDecoder {
+    Sse(SseDecoder),
+    Lines(LineDecoder),
+}
+
+impl BodyDecoder {
+    fn new(format: StreamFormat) -> Self {
+        match format {
+            StreamFormat::Sse => BodyDecoder::Sse(SseDecoder::new()),
+            StreamFormat::NdJson => BodyDecoder::Lines(LineDecoder::new()),
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        match self {
+            BodyDecoder::Sse(decoder) => decoder.feed(chunk),
+            BodyDecoder::Lines(decoder) => decoder.feed(chunk),
+        }
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        match self {
+            BodyDecoder::Sse(decoder) => decoder.finish().into_iter().collect(),
+            BodyDecoder::Lines(decoder) => decoder.finish().into_iter().collect(),
+        }
+    }
+}
+
+// --- Tauri commands ---
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderInfo {
+    pub id: QueryProfile,
+    pub name: String,
+    pub default_model: Option<String>,
+    pub is_default: bool, // Used by queries that don't name a profile
+}
+
+// Query profiles that can be used right now: the worker when configured, every
+// configured direct provider, and the pipeline once both of its stages are
+#[tauri::command]
+pub fn list_providers() -> Vec<ProviderInfo> {
+    let config = config::current();
+    let default_profile = config.query_profile.unwrap_or_default();
+    QueryProfile::ALL
+        .into_iter()
+        .filter_map(|profile| {
+            let (name, default_model) = match profile {
+                QueryProfile::Worker => {
+                    config.worker_api_url.as_ref()?;
+                    ("Worker".to_string(), None)
+                }
+                QueryProfile::Pipeline => {
+                    let stages = [
+                        config.pipeline_vision_profile,
+                        config.pipeline_reasoning_profile,
+                    ];
+                    for stage in stages {
+                        provider_from_config(stage?, &config)?;
+                    }
+                    ("Vision + reasoning pipeline".to_string(), None)
+                }
+                _ => {
+                    let provider = provider_from_config(profile, &config)?;
+                    let model = provider.default_model().map(str::to_string);
+                    (provider.display_name(), model)
+                }
+            };
+            Some(ProviderInfo {
+                id: profile,
+                name,
+                default_model,
+                is_default: profile == default_profile,
+            })
+        })
+        .collect()
+}
+
+// Models offered by a direct provider, for the model picker. The worker picks its
+// own model and the pipeline's stages are configured, so they have none to offer.
+#[tauri::command]
+pub async fn list_models(
+    provider: QueryProfile,
+    http: State<'_, HttpClient>,
+) -> Result<Vec<ModelInfo>, QueryError> {
+    if !provider.is_direct() {
+        return Ok(Vec::new());
+    }
+    let provider = self::provider(provider)?;
+    let models = fetch_models(provider.as_ref(), http.client()).await;
+    match &models {
+        Ok(models) => println!(
+            "[list_models] {} offers {} model(s).",
+            provider.display_name(),
+            models.len()
+        ),
+        Err(e) => eprintln!("[list_models] {}: {}", provider.display_name(), e),
+    }
+    models
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer, MOCK_ANSWER, MOCK_MODEL};
+    use serde_json::json;
+
+    // A provider against its stub, with a streamed "mock answer" in its wire format and
+    // an error response in its error format. `sse` takes (event name, data) pairs; an
+    // empty name sends a bare `data:` line.
+    struct Backend {
+        provider: Box<dyn Provider>,
+        server: MockServer,
+        query_path: &'static str,
+        stream: MockResponse,
+        error: MockResponse,
+        expected_error: QueryError,
+    }
+
+    fn sse(events: &[(&str, &str)]) -> MockResponse {
+        let body: String = events
+            .iter()
+            .map(|(name, data)| {
+                if name.is_empty() {
+                    format!("data: {}\n\n", data)
+                } else {
+                    format!("event: {}\ndata: {}\n\n", name, data)
+                }
+            })
+            .collect();
+        MockResponse::text(200, "text/event-stream", &body)
+    }
+
+    async fn backends() -> Vec<Backend> {
+        let openai = MockServer::openai().await;
+        let anthropic = MockServer::anthropic().await;
+        let ollama = MockServer::ollama().await;
+        let ndjson: String = [
+            json!({ "message": { "role": "assistant", "content": "mock " }, "done": false }),
+            json!({ "message": { "role": "assistant", "content": "answer" }, "done": false }),
+            json!({ "message": { "role": "assistant", "content": "" }, "done": true, "prompt_eval_count": 20, "eval_count": 2 }),
+        ]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect();
+        vec![
+            Backend {
+                provider: Box::new(OpenAiProvider {
+                    base_url: format!("{}/v1", openai.url()),
+                    api_key: Some("sk-test".to_string()),
+                    model: None,
+                    stream_usage: true,
+                }),
+                server: openai,
+                query_path: "/v1/chat/completions",
+                stream: sse(&[
+                    (
+                        "",
+                        r#"{"choices":[{"index":0,"delta":{"role":"assistant"}}]}"#,
+                    ),
+                    (
+                        "",
+                        r#"{"choices":[{"index":0,"delta":{"content":"mock "}}]}"#,
+                    ),
+                    (
+                        "",
+                        r#"{"choices":[{"index":0,"delta":{"content":"answer"},"finish_reason":"stop"}]}"#,
+                    ),
+                    (
+                        "",
+                        r#"{"choices":[],"usage":{"prompt_tokens":7,"completion_tokens":2}}"#,
+                    ),
+                    ("", "[DONE]"),
+                ]),
+                error: MockResponse::json(
+                    401,
+                    json!({ "error": { "message": "Incorrect API key provided", "type": "invalid_request_error" } }),
+                ),
+                expected_error: QueryError::AuthRejected {
+                    status: 401,
+                    message: "Incorrect API key provided".to_string(),
+                },
+            },
+            Backend {
+                provider: Box::new(AnthropicProvider {
+                    base_url: anthropic.url().to_string(),
+                    api_key: "sk-ant-test".to_string(),
+                    model: None,
+                }),
+                server: anthropic,
+                query_path: "/v1/messages",
+                stream: sse(&[
+                    (
+                        "message_start",
+                        r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":25,"output_tokens":1}}}"#,
+                    ),
+                    (
+                        "content_block_start",
+                        r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                    ),
+                    ("ping", r#"{"type":"ping"}"#),
+                    (
+                        "content_block_delta",
+                        r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"mock "}}"#,
+                    ),
+                    (
+                        "content_block_delta",
+                        r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"answer"}}"#,
+                    ),
+                    (
+                        "content_block_stop",
+                        r#"{"type":"content_block_stop","index":0}"#,
+                    ),
+                    (
+                        "message_delta",
+                        r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+                    ),
+                    ("message_stop", r#"{"type":"message_stop"}"#),
+                ]),
+                error: MockResponse::json(
+                    529,
+                    json!({ "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded" } }),
+                ),
+                expected_error: QueryError::Upstream {
+                    status: 529,
+                    message: "Overloaded".to_string(),
+                },
+            },
+            Backend {
+                provider: Box::new(OllamaProvider {
+                    base_url: ollama.url().to_string(),
+                    model: None,
+                }),
+                server: ollama,
+                query_path: "/api/chat",
+                stream: MockResponse::text(200, "application/x-ndjson", &ndjson),
+                error: MockResponse::json(404, json!({ "error": "model 'nope' not found" })),
+                expected_error: QueryError::Rejected {
+                    status: 404,
+                    message: "model 'nope' not found".to_string(),
+                },
+            },
+        ]
+    }
+
+    fn query(stream: bool) -> ProviderQuery<'static> {
+        ProviderQuery {
+            model: MOCK_MODEL,
+            text: "Hi",
+            image_data_urls: &[],
+            history: &[],
+            stream,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_backend_completes_streams_and_lists_models() {
+        let client = reqwest::Client::new();
+        let timeout = Duration::from_secs(5);
+        for backend in backends().await {
+            let provider = backend.provider.as_ref();
+            let name = provider.display_name();
+
+            let completion = complete(provider, &client, &query(false), None, |_| {})
+                .await
+                .unwrap();
+            assert_eq!(completion.text, MOCK_ANSWER, "{}", name);
+            assert_eq!(completion.usage.unwrap().output_tokens, Some(3), "{}", name);
+            let sent = &backend.server.requests(backend.query_path)[0];
+            assert_eq!(
+                sent.json(),
+                provider.request_body(&query(false)),
+                "{}",
+                name
+            );
+
+            backend.server.enqueue(backend.query_path, backend.stream);
+            let mut deltas = Vec::new();
+            let completion = stream(
+                provider,
+                &client,
+                &query(true),
+                timeout,
+                |_| {},
+                |delta| deltas.push(delta),
+            )
+            .await
+            .unwrap();
+            assert_eq!(completion.text, MOCK_ANSWER, "{}", name);
+            assert_eq!(deltas, ["mock ", "answer"], "{}", name);
+            assert_eq!(completion.usage.unwrap().output_tokens, Some(2), "{}", name);
+
+            backend.server.enqueue(backend.query_path, backend.error);
+            let err = complete(provider, &client, &query(false), None, |_| {})
+                .await
+                .unwrap_err();
+            assert_eq!(err, backend.expected_error, "{}", name);
+
+            let models = fetch_models(provider, &client).await.unwrap();
+            assert_eq!(models[0].id, MOCK_MODEL, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_fallbacks_and_failures() {
+        let backend = backends().await.remove(0);
+        let provider = backend.provider.as_ref();
+        let client = reqwest::Client::new();
+        let timeout = Duration::from_secs(5);
+
+        // A server that ignores `stream` still yields the answer, as one delta
+        let mut deltas = Vec::new();
+        let completion = stream(
+            provider,
+            &client,
+            &query(true),
+            timeout,
+            |_| {},
+            |delta| deltas.push(delta),
+        )
+        .await
+        .unwrap();
+        assert_eq!(deltas, [completion.text]);
+        let sent = &backend.server.requests(backend.query_path)[0];
+        assert_eq!(sent.header("accept"), Some("text/event-stream"));
+
+        backend.server.enqueue(
+            backend.query_path,
+            sse(&[
+                (
+                    "",
+                    r#"{"choices":[{"index":0,"delta":{"content":"partial"}}]}"#,
+                ),
+                ("", r#"{"error":{"message":"context length exceeded"}}"#),
+            ]),
+        );
+        let err = stream(provider, &client, &query(true), timeout, |_| {}, |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            QueryError::StreamFailed {
+                message: "context length exceeded".to_string()
+            }
+        );
+
+        backend.server.enqueue(
+            backend.query_path,
+            MockResponse::ok(json!({ "choices": [] })),
+        );
+        let err = complete(provider, &client, &query(false), None, |_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(err, QueryError::Parse { .. }));
+    }
+
+    #[test]
+    fn test_split_data_url() {
+        assert_eq!(
+            split_data_url("data:image/jpeg;base64,/9j/4AAQ"),
+            Some(("image/jpeg", "/9j/4AAQ"))
+        );
+        assert_eq!(split_data_url("data:image/png,raw"), None);
+        assert_eq!(split_data_url("https://example.com/a.png"), None);
+    }
+
+    #[test]
+    fn test_error_message_shapes() {
+        assert_eq!(
+            error_message(r#"{"error":{"type":"invalid_request_error","message":"bad model"}}"#),
+            "bad model"
+        );
+        assert_eq!(
+            error_message(r#"{"error":"model not found"}"#),
+            "model not found"
+        );
+        assert_eq!(error_message("Bad Gateway"), "Bad Gateway");
+        assert_eq!(error_message(r#"{"detail":"x"}"#), r#"{"detail":"x"}"#);
+    }
+
+    #[test]
+    fn test_registry_follows_config() {
+        let config = AppConfig {
+            openai_base_url: Some(url::Url::parse("http://127.0.0.1:1234/v1").unwrap()),
+            anthropic_api_key: Some("sk-ant".to_string()),
+            anthropic_model: Some("claude-test".to_string()),
+            ..Default::default()
+        };
+        assert!(provider_from_config(QueryProfile::Worker, &config).is_none());
+        assert!(provider_from_config(QueryProfile::Ollama, &config).is_none());
+
+        let openai = provider_from_config(QueryProfile::OpenAi, &config).unwrap();
+        assert_eq!(openai.display_name(), "OpenAI-compatible");
+        assert!(matches!(
+            select_model(openai.as_ref(), None),
+            Err(QueryError::ConfigMissing { .. })
+        ));
+        assert_eq!(
+            select_model(openai.as_ref(), Some("picked")).unwrap(),
+            "picked"
+        );
+
+        let anthropic = provider_from_config(QueryProfile::Anthropic, &config).unwrap();
+        assert_eq!(
+            select_model(anthropic.as_ref(), Some(" ")).unwrap(),
+            "claude-test"
+        );
+    }
+}
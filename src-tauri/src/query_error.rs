@@ -0,0 +1,192 @@
+// src-tauri/src/query_error.rs
+
+// --- Dependencies ---
+use crate::image_pipeline::ImageError;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// Longest error body kept in the message; servers sometimes answer with a whole HTML page
+const MAX_ERROR_BODY_CHARS: usize = 500;
+
+// --- Error handling ---
+// Returned by the query commands. Serialized with a `kind` tag, e.g.
+// `{"kind":"rateLimited","retryAfterSecs":30,"message":"..."}`, so the UI can react to
+// each case without parsing messages.
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum QueryError {
+    #[error("Configuration missing: {message}")]
+    ConfigMissing { message: String },
+    #[error("{message}")]
+    ImageReadFailed {
+        message: String,
+        errors: Vec<ImageError>, // One entry per image that could not be attached
+    },
+    #[error("Failed to reach the server: {message}")]
+    Network { message: String },
+    #[error("The request timed out: {message}")]
+    Timeout { message: String },
+    #[error("The server rejected the credentials ({status}): {message}")]
+    AuthRejected { status: u16, message: String },
+    #[error("Rate limited by the server: {message}")]
+    RateLimited {
+        retry_after_secs: Option<u64>,
+        message: String,
+    },
+    #[error("The server failed ({status}): {message}")]
+    Upstream { status: u16, message: String },
+    #[error("The server rejected the request ({status}): {message}")]
+    Rejected { status: u16, message: String },
+    #[error("The server reported an error: {message}")]
+    StreamFailed { message: String },
+    #[error("Failed to parse the server response: {message}")]
+    Parse { message: String },
+    #[error("The {stage} stage returned unusable output: {message}")]
+    InvalidStageOutput { stage: String, message: String }, // See `pipeline`
+    #[error("cancelled")]
+    Cancelled,
+}
+
+impl QueryError {
+    // Maps a non-success response from the worker or a provider to the matching variant
+    pub fn from_status(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let message = truncate_body(body);
+        let code = status.as_u16();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => QueryError::AuthRejected {
+                status: code,
+                message,
+            },
+            StatusCode::TOO_MANY_REQUESTS => QueryError::RateLimited {
+                retry_after_secs: retry_after(headers).map(|d| d.as_secs()),
+                message,
+            },
+            s if s.is_server_error() => QueryError::Upstream {
+                status: code,
+                message,
+            },
+            _ => QueryError::Rejected {
+                status: code,
+                message,
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for QueryError {
+    fn from(err: reqwest::Error) -> Self {
+        let message = err.to_string();
+        if err.is_timeout() {
+            QueryError::Timeout { message }
+        } else if err.is_decode() {
+            QueryError::Parse { message }
+        } else {
+            QueryError::Network { message }
+        }
+    }
+}
+
+impl From<ImageError> for QueryError {
+    fn from(err: ImageError) -> Self {
+        QueryError::ImageReadFailed {
+            message: err.to_string(),
+            errors: vec![err],
+        }
+    }
+}
+
+// Reads `Retry-After` as either delta-seconds or an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(
+        at.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+fn truncate_body(body: &str) -> String {
+    let body = body.trim();
+    match body.char_indices().nth(MAX_ERROR_BODY_CHARS) {
+        Some((cut, _)) => format!("{}...", &body[..cut]),
+        None => body.to_string(),
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_from_status_variants() {
+        let headers = HeaderMap::new();
+        assert!(matches!(
+            QueryError::from_status(StatusCode::FORBIDDEN, &headers, "no"),
+            QueryError::AuthRejected { status: 403, .. }
+        ));
+        assert!(matches!(
+            QueryError::from_status(StatusCode::BAD_GATEWAY, &headers, ""),
+            QueryError::Upstream { status: 502, .. }
+        ));
+        assert!(matches!(
+            QueryError::from_status(StatusCode::BAD_REQUEST, &headers, ""),
+            QueryError::Rejected { status: 400, .. }
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("30"));
+        assert_eq!(
+            QueryError::from_status(StatusCode::TOO_MANY_REQUESTS, &headers, "slow down"),
+            QueryError::RateLimited {
+                retry_after_secs: Some(30),
+                message: "slow down".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_retry_after_http_date() {
+        let mut headers = HeaderMap::new();
+        let later = SystemTime::now() + Duration::from_secs(120);
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_str(&httpdate::fmt_http_date(later)).unwrap(),
+        );
+        let wait = retry_after(&headers).unwrap();
+        assert!(wait <= Duration::from_secs(120) && wait >= Duration::from_secs(118));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_serializes_as_tagged_json() {
+        let err = QueryError::RateLimited {
+            retry_after_secs: Some(5),
+            message: "busy".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({"kind": "rateLimited", "retryAfterSecs": 5, "message": "busy"})
+        );
+        assert_eq!(
+            serde_json::to_value(QueryError::Cancelled).unwrap(),
+            serde_json::json!({"kind": "cancelled"})
+        );
+    }
+}
@@ -0,0 +1,164 @@
+// src-tauri/src/query_registry.rs
+
+// --- Dependencies ---
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex}; // Same locking approach as PendingAuthState
+use tokio::sync::oneshot;
+
+// --- State Management ---
+// One entry per in-flight query, keyed by the request id supplied by the frontend.
+// Sending on `cancel_tx` makes the owning command drop its HTTP future.
+pub struct InFlightQuery {
+    window_label: String,
+    generation: u64,
+    cancel_tx: oneshot::Sender<()>,
+}
+
+pub type QueryRegistryState = Arc<StdMutex<HashMap<String, InFlightQuery>>>;
+
+// Distinguishes re-used request ids so an old guard never removes a newer entry
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+// --- Registration guard ---
+// Held by the command for as long as the query runs; the entry is removed on drop,
+// whichever way the command exits.
+pub struct QueryRegistration {
+    registry: QueryRegistryState,
+    request_id: String,
+    generation: u64,
+    cancel_rx: Option<oneshot::Receiver<()>>,
+}
+
+impl QueryRegistration {
+    // Resolves when `cancel_query` (or a window close) cancels this request
+    pub async fn cancelled(&mut self) {
+        match self.cancel_rx.as_mut() {
+            Some(rx) => {
+                // A dropped sender means the entry was replaced or removed: treat as cancelled
+                let _ = rx.await;
+                self.cancel_rx = None;
+            }
+            None => std::future::pending::<()>().await,
+        }
+    }
+}
+
+impl Drop for QueryRegistration {
+    fn drop(&mut self) {
+        let mut map = self.registry.lock().expect("Failed to lock query registry");
+        if map
+            .get(&self.request_id)
+            .is_some_and(|entry| entry.generation == self.generation)
+        {
+            map.remove(&self.request_id);
+        }
+    }
+}
+
+// Adds a query to the registry. Re-using an id that is still running cancels the old query.
+pub fn register_query(
+    registry: &QueryRegistryState,
+    request_id: &str,
+    window_label: &str,
+) -> QueryRegistration {
+    let (cancel_tx, cancel_rx) = oneshot::channel::<()>();
+    let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    let previous = registry
+        .lock()
+        .expect("Failed to lock query registry")
+        .insert(
+            request_id.to_string(),
+            InFlightQuery {
+                window_label: window_label.to_string(),
+                generation,
+                cancel_tx,
+            },
+        );
+    if let Some(previous) = previous {
+        println!(
+            "[query_registry] Request id {} reused; cancelling the previous query.",
+            request_id
+        );
+        let _ = previous.cancel_tx.send(());
+    }
+    QueryRegistration {
+        registry: registry.clone(),
+        request_id: request_id.to_string(),
+        generation,
+        cancel_rx: Some(cancel_rx),
+    }
+}
+
+// Cancels one query. Returns false if the id is unknown or already finished.
+pub fn cancel_query_by_id(registry: &QueryRegistryState, request_id: &str) -> bool {
+    let entry = registry
+        .lock()
+        .expect("Failed to lock query registry")
+        .remove(request_id);
+    match entry {
+        Some(entry) => entry.cancel_tx.send(()).is_ok(),
+        None => false,
+    }
+}
+
+// Cancels every query started from the given window. Returns how many were cancelled.
+pub fn cancel_queries_for_window(registry: &QueryRegistryState, window_label: &str) -> usize {
+    let mut map = registry.lock().expect("Failed to lock query registry");
+    let ids: Vec<String> = map
+        .iter()
+        .filter(|(_, entry)| entry.window_label == window_label)
+        .map(|(id, _)| id.clone())
+        .collect();
+    ids.into_iter()
+        .filter_map(|id| map.remove(&id))
+        .map(|entry| entry.cancel_tx.send(()).is_ok())
+        .filter(|sent| *sent)
+        .count()
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_by_id_wakes_registration() {
+        let registry = QueryRegistryState::default();
+        let mut registration = register_query(&registry, "req-1", "main");
+        assert!(cancel_query_by_id(&registry, "req-1"));
+        registration.cancelled().await;
+        assert!(!cancel_query_by_id(&registry, "req-1"));
+    }
+
+    #[test]
+    fn test_drop_removes_only_own_entry() {
+        let registry = QueryRegistryState::default();
+        let first = register_query(&registry, "req-1", "main");
+        let second = register_query(&registry, "req-1", "main");
+        drop(first); // Superseded guard must not remove the newer entry
+        assert!(registry.lock().unwrap().contains_key("req-1"));
+        drop(second);
+        assert!(registry.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reused_id_cancels_previous_query() {
+        let registry = QueryRegistryState::default();
+        let mut first = register_query(&registry, "req-1", "main");
+        let _second = register_query(&registry, "req-1", "main");
+        first.cancelled().await;
+    }
+
+    #[test]
+    fn test_cancel_queries_for_window() {
+        let registry = QueryRegistryState::default();
+        let _a = register_query(&registry, "a", "queryWindow");
+        let _b = register_query(&registry, "b", "queryWindow");
+        let _c = register_query(&registry, "c", "main");
+        assert_eq!(cancel_queries_for_window(&registry, "queryWindow"), 2);
+        let map = registry.lock().unwrap();
+        assert_eq!(map.len(), 1);
+        assert!(map.contains_key("c"));
+    }
+}
@@ -0,0 +1,576 @@
+// src-tauri/src/secrets.rs
+
+// --- Dependencies ---
+use crate::config::{
+    self, ANTHROPIC_API_KEY, GITHUB_CLIENT_SECRET, OPENAI_API_KEY, WORKER_API_KEY,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, PoisonError};
+use tauri::State;
+use thiserror::Error;
+
+// Service name under which entries appear in the OS keyring
+pub const KEYRING_SERVICE: &str = "com.revision.app";
+// Fallback files in the app data dir, used when no keyring is reachable (headless Linux)
+pub const SECRETS_FILE_NAME: &str = "secrets.enc";
+pub const SECRETS_KEY_FILE_NAME: &str = "secrets.key";
+
+const KEY_LEN: usize = 32;
+
+// --- Error handling ---
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+pub enum SecretError {
+    #[error("Secret value must not be empty")]
+    Empty,
+    #[error("No stored secret to rotate: {0}")]
+    NotFound(String),
+    #[error("Secret storage failed: {0}")]
+    Backend(String),
+    #[error("Secret file is corrupt or was encrypted with another key: {0}")]
+    Corrupt(String),
+}
+
+// --- Keys ---
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SecretKey {
+    WorkerApiKey,
+    GithubClientSecret,
+    // Access token and profile of the signed-in user. Only `session` reads or writes it,
+    // so the secret commands cannot overwrite or clear it
+    #[serde(skip)]
+    GithubSession,
+    OpenAiApiKey,
+    AnthropicApiKey,
+    // Access token stored by older versions before `GithubSession`; only ever deleted
+    #[serde(skip)]
+    LegacyGithubAccessToken,
+}
+
+impl SecretKey {
+    // The secrets listed and edited through the secret commands
+    pub const ALL: [SecretKey; 4] = [
+        SecretKey::WorkerApiKey,
+        SecretKey::GithubClientSecret,
+        SecretKey::OpenAiApiKey,
+        SecretKey::AnthropicApiKey,
+    ];
+
+    // Account name in the keyring and key in the encrypted file
+    pub fn account(self) -> &'static str {
+        match self {
+            SecretKey::WorkerApiKey => "worker_api_key",
+            SecretKey::GithubClientSecret => "github_client_secret",
+            SecretKey::GithubSession => "github_session",
+            SecretKey::OpenAiApiKey => "openai_api_key",
+            SecretKey::AnthropicApiKey => "anthropic_api_key",
+            SecretKey::LegacyGithubAccessToken => "github_access_token",
+        }
+    }
+
+    // The configuration key this secret provides, if any
+    pub fn from_config_key(key: &str) -> Option<SecretKey> {
+        match key {
+            WORKER_API_KEY => Some(SecretKey::WorkerApiKey),
+            GITHUB_CLIENT_SECRET => Some(SecretKey::GithubClientSecret),
+            OPENAI_API_KEY => Some(SecretKey::OpenAiApiKey),
+            ANTHROPIC_API_KEY => Some(SecretKey::AnthropicApiKey),
+            _ => None,
+        }
+    }
+}
+
+// --- Backends ---
+pub trait SecretBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn get(&self, key: SecretKey) -> Result<Option<String>, SecretError>;
+    fn set(&self, key: SecretKey, value: &str) -> Result<(), SecretError>;
+    fn delete(&self, key: SecretKey) -> Result<(), SecretError>;
+}
+
+// macOS Keychain, Windows Credential Manager or the Secret Service on Linux
+pub struct KeyringBackend;
+
+impl KeyringBackend {
+    fn entry(key: SecretKey) -> Result<keyring::Entry, SecretError> {
+        keyring::Entry::new(KEYRING_SERVICE, key.account())
+            .map_err(|e| SecretError::Backend(e.to_string()))
+    }
+
+    // A lookup that merely finds nothing proves the keyring is reachable
+    pub fn is_available() -> bool {
+        match Self::entry(SecretKey::WorkerApiKey).map(|entry| entry.get_password()) {
+            Ok(Ok(_)) | Ok(Err(keyring::Error::NoEntry)) => true,
+            Ok(Err(e)) => {
+                eprintln!("Secrets: Keyring unavailable: {}", e);
+                false
+            }
+            Err(e) => {
+                eprintln!("Secrets: Keyring unavailable: {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl SecretBackend for KeyringBackend {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn get(&self, key: SecretKey) -> Result<Option<String>, SecretError> {
+        match Self::entry(key)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(SecretError::Backend(e.to_string())),
+        }
+    }
+
+    fn set(&self, key: SecretKey, value: &str) -> Result<(), SecretError> {
+        Self::entry(key)?
+            .set_password(value)
+            .map_err(|e| SecretError::Backend(e.to_string()))
+    }
+
+    fn delete(&self, key: SecretKey) -> Result<(), SecretError> {
+        match Self::entry(key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(SecretError::Backend(e.to_string())),
+        }
+    }
+}
+
+// All secrets in one ChaCha20-Poly1305 encrypted JSON map. The random key lives in a
+// separate owner-only file next to it: this keeps secrets out of backups and casual
+// reads of the data dir, not away from other code running as the same user.
+pub struct EncryptedFileBackend {
+    path: PathBuf,
+    key_path: PathBuf,
+    lock: StdMutex<()>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFileBackend {
+    pub fn new(dir: &Path) -> Self {
+        EncryptedFileBackend {
+            path: dir.join(SECRETS_FILE_NAME),
+            key_path: dir.join(SECRETS_KEY_FILE_NAME),
+            lock: StdMutex::new(()),
+        }
+    }
+
+    fn key(&self, create: bool) -> Result<Option<LessSafeKey>, SecretError> {
+        let bytes = match fs::read(&self.key_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound && create => self.create_key()?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(SecretError::Backend(e.to_string())),
+        };
+        let unbound = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+            .map_err(|_| SecretError::Corrupt("invalid key file".to_string()))?;
+        Ok(Some(LessSafeKey::new(unbound)))
+    }
+
+    fn create_key(&self) -> Result<Vec<u8>, SecretError> {
+        if self.path.exists() {
+            // A new key could never decrypt the existing file
+            return Err(SecretError::Corrupt(format!(
+                "'{}' is missing",
+                self.key_path.display()
+            )));
+        }
+        let mut bytes = vec![0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| SecretError::Backend("no system randomness".to_string()))?;
+        if let Some(dir) = self.key_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| SecretError::Backend(e.to_string()))?;
+        }
+        write_private(&self.key_path, &bytes, true)?;
+        Ok(bytes)
+    }
+
+    fn load(&self) -> Result<HashMap<String, String>, SecretError> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(SecretError::Backend(e.to_string())),
+        };
+        let file: EncryptedFile =
+            serde_json::from_str(&content).map_err(|e| SecretError::Corrupt(e.to_string()))?;
+        let key = self.key(false)?.ok_or_else(|| {
+            SecretError::Corrupt(format!("'{}' is missing", self.key_path.display()))
+        })?;
+        let nonce = STANDARD
+            .decode(&file.nonce)
+            .ok()
+            .and_then(|bytes| Nonce::try_assume_unique_for_key(&bytes).ok())
+            .ok_or_else(|| SecretError::Corrupt("invalid nonce".to_string()))?;
+        let mut in_out = STANDARD
+            .decode(&file.ciphertext)
+            .map_err(|e| SecretError::Corrupt(e.to_string()))?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(SECRETS_FILE_NAME), &mut in_out)
+            .map_err(|_| SecretError::Corrupt("authentication failed".to_string()))?;
+        serde_json::from_slice(plaintext).map_err(|e| SecretError::Corrupt(e.to_string()))
+    }
+
+    fn save(&self, secrets: &HashMap<String, String>) -> Result<(), SecretError> {
+        let key = self.key(true)?.expect("key is created on demand");
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| SecretError::Backend("no system randomness".to_string()))?;
+        let mut in_out =
+            serde_json::to_vec(secrets).map_err(|e| SecretError::Backend(e.to_string()))?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(SECRETS_FILE_NAME),
+            &mut in_out,
+        )
+        .map_err(|_| SecretError::Backend("encryption failed".to_string()))?;
+
+        let file = EncryptedFile {
+            version: 1,
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(in_out),
+        };
+        let json = serde_json::to_vec(&file).map_err(|e| SecretError::Backend(e.to_string()))?;
+        // Write a temp file and rename it, so a crash never leaves half a file behind
+        let tmp_path = self.path.with_extension("tmp");
+        write_private(&tmp_path, &json, false)?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| SecretError::Backend(e.to_string()))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut HashMap<String, String>)) -> Result<(), SecretError> {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut secrets = self.load()?;
+        change(&mut secrets);
+        self.save(&secrets)
+    }
+}
+
+impl SecretBackend for EncryptedFileBackend {
+    fn name(&self) -> &'static str {
+        "encryptedFile"
+    }
+
+    fn get(&self, key: SecretKey) -> Result<Option<String>, SecretError> {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(self.load()?.remove(key.account()))
+    }
+
+    fn set(&self, key: SecretKey, value: &str) -> Result<(), SecretError> {
+        self.update(|secrets| {
+            secrets.insert(key.account().to_string(), value.to_string());
+        })
+    }
+
+    // Leaves the file alone when there is nothing to delete, so no key file gets created
+    fn delete(&self, key: SecretKey) -> Result<(), SecretError> {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut secrets = self.load()?;
+        match secrets.remove(key.account()) {
+            Some(_) => self.save(&secrets),
+            None => Ok(()),
+        }
+    }
+}
+
+// Creates or truncates `path` readable by the owner only
+fn write_private(path: &Path, bytes: &[u8], create_new: bool) -> Result<(), SecretError> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if create_new {
+        options.create_new(true);
+    } else {
+        options.create(true).truncate(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| SecretError::Backend(e.to_string()))?;
+    file.write_all(bytes)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| SecretError::Backend(e.to_string()))
+}
+
+// --- Store ---
+pub struct SecretStore {
+    backend: Box<dyn SecretBackend>,
+}
+
+pub type SecretStoreState = Arc<SecretStore>;
+
+impl SecretStore {
+    pub fn new(backend: Box<dyn SecretBackend>) -> Self {
+        SecretStore { backend }
+    }
+
+    // Prefers the OS keyring and falls back to the encrypted file in `data_dir`
+    pub fn open(data_dir: &Path) -> Self {
+        let backend: Box<dyn SecretBackend> = if KeyringBackend::is_available() {
+            Box::new(KeyringBackend)
+        } else {
+            Box::new(EncryptedFileBackend::new(data_dir))
+        };
+        println!("Secrets: Using {} backend.", backend.name());
+        SecretStore::new(backend)
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    pub fn get(&self, key: SecretKey) -> Result<Option<String>, SecretError> {
+        self.backend.get(key)
+    }
+
+    pub fn set(&self, key: SecretKey, value: &str) -> Result<(), SecretError> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(SecretError::Empty);
+        }
+        self.backend.set(key, value)
+    }
+
+    // Like `set`, but only replaces a secret that is already stored
+    pub fn rotate(&self, key: SecretKey, value: &str) -> Result<(), SecretError> {
+        if self.backend.get(key)?.is_none() {
+            return Err(SecretError::NotFound(key.account().to_string()));
+        }
+        self.set(key, value)
+    }
+
+    pub fn delete(&self, key: SecretKey) -> Result<(), SecretError> {
+        self.backend.delete(key)
+    }
+
+    pub fn status(&self) -> Result<SecretStoreStatus, SecretError> {
+        let secrets = SecretKey::ALL
+            .iter()
+            .map(|key| {
+                Ok(SecretStatus {
+                    key: *key,
+                    stored: self.backend.get(*key)?.is_some(),
+                })
+            })
+            .collect::<Result<_, SecretError>>()?;
+        Ok(SecretStoreStatus {
+            backend: self.backend_name(),
+            secrets,
+        })
+    }
+}
+
+// Which secrets are stored; values are never sent to the UI
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretStatus {
+    pub key: SecretKey,
+    pub stored: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretStoreStatus {
+    pub backend: &'static str,
+    pub secrets: Vec<SecretStatus>,
+}
+
+// --- Tauri Commands ---
+
+// Reports the backend in use and which secrets are stored
+#[tauri::command]
+pub fn list_secrets(store: State<'_, SecretStoreState>) -> Result<SecretStoreStatus, SecretError> {
+    store.status()
+}
+
+// Stores a secret (replacing any previous value) and reloads the configuration
+#[tauri::command]
+pub fn set_secret(
+    key: SecretKey,
+    value: String,
+    store: State<'_, SecretStoreState>,
+) -> Result<SecretStoreStatus, SecretError> {
+    store.set(key, &value)?;
+    println!("[set_secret] Stored {}", key.account());
+    config::reload();
+    store.status()
+}
+
+// Replaces a stored secret, failing if there is none yet
+#[tauri::command]
+pub fn rotate_secret(
+    key: SecretKey,
+    value: String,
+    store: State<'_, SecretStoreState>,
+) -> Result<SecretStoreStatus, SecretError> {
+    store.rotate(key, &value)?;
+    println!("[rotate_secret] Rotated {}", key.account());
+    config::reload();
+    store.status()
+}
+
+#[tauri::command]
+pub fn clear_secret(
+    key: SecretKey,
+    store: State<'_, SecretStoreState>,
+) -> Result<SecretStoreStatus, SecretError> {
+    store.delete(key)?;
+    println!("[clear_secret] Cleared {}", key.account());
+    config::reload();
+    store.status()
+}
+
+// --- Tests ---
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    pub(crate) struct MemoryBackend(StdMutex<HashMap<SecretKey, String>>);
+
+    impl SecretBackend for MemoryBackend {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+        fn get(&self, key: SecretKey) -> Result<Option<String>, SecretError> {
+            Ok(self.0.lock().unwrap().get(&key).cloned())
+        }
+        fn set(&self, key: SecretKey, value: &str) -> Result<(), SecretError> {
+            self.0.lock().unwrap().insert(key, value.to_string());
+            Ok(())
+        }
+        fn delete(&self, key: SecretKey) -> Result<(), SecretError> {
+            self.0.lock().unwrap().remove(&key);
+            Ok(())
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("secrets-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_encrypted_file_round_trip() {
+        let dir = temp_dir("round-trip");
+        let backend = EncryptedFileBackend::new(&dir);
+        assert_eq!(backend.get(SecretKey::WorkerApiKey).unwrap(), None);
+        backend.delete(SecretKey::GithubSession).unwrap();
+        assert!(!dir.join(SECRETS_KEY_FILE_NAME).exists());
+
+        backend.set(SecretKey::WorkerApiKey, "worker-key").unwrap();
+        backend.set(SecretKey::GithubSession, "gho_token").unwrap();
+        backend.delete(SecretKey::GithubSession).unwrap();
+
+        // A fresh instance reads what the first one wrote
+        let reopened = EncryptedFileBackend::new(&dir);
+        assert_eq!(
+            reopened.get(SecretKey::WorkerApiKey).unwrap().as_deref(),
+            Some("worker-key")
+        );
+        assert_eq!(reopened.get(SecretKey::GithubSession).unwrap(), None);
+
+        let on_disk = fs::read_to_string(dir.join(SECRETS_FILE_NAME)).unwrap();
+        assert!(!on_disk.contains("worker-key"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.join(SECRETS_KEY_FILE_NAME))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_encrypted_file_detects_tampering_and_lost_key() {
+        let dir = temp_dir("tamper");
+        let backend = EncryptedFileBackend::new(&dir);
+        backend.set(SecretKey::WorkerApiKey, "worker-key").unwrap();
+
+        let path = dir.join(SECRETS_FILE_NAME);
+        let mut file: EncryptedFile =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let mut ciphertext = STANDARD.decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = STANDARD.encode(ciphertext);
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(
+            backend.get(SecretKey::WorkerApiKey),
+            Err(SecretError::Corrupt(_))
+        ));
+
+        // Without the key the file can neither be read nor silently overwritten
+        fs::remove_file(dir.join(SECRETS_KEY_FILE_NAME)).unwrap();
+        assert!(matches!(
+            backend.set(SecretKey::WorkerApiKey, "new"),
+            Err(SecretError::Corrupt(_))
+        ));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_store_set_rotate_clear() {
+        let store = SecretStore::new(Box::new(MemoryBackend::default()));
+        assert_eq!(
+            store.rotate(SecretKey::WorkerApiKey, "new"),
+            Err(SecretError::NotFound("worker_api_key".to_string()))
+        );
+        assert_eq!(
+            store.set(SecretKey::WorkerApiKey, "  "),
+            Err(SecretError::Empty)
+        );
+
+        store.set(SecretKey::WorkerApiKey, " first ").unwrap();
+        store.rotate(SecretKey::WorkerApiKey, "second").unwrap();
+        assert_eq!(
+            store.get(SecretKey::WorkerApiKey).unwrap().as_deref(),
+            Some("second")
+        );
+
+        store.delete(SecretKey::WorkerApiKey).unwrap();
+        let status = store.status().unwrap();
+        assert_eq!(status.backend, "memory");
+        assert!(status.secrets.iter().all(|secret| !secret.stored));
+    }
+
+    #[test]
+    fn test_session_is_not_a_listed_secret() {
+        let store = SecretStore::new(Box::new(MemoryBackend::default()));
+        store.set(SecretKey::GithubSession, "{}").unwrap();
+        let status = store.status().unwrap();
+        assert!(status
+            .secrets
+            .iter()
+            .all(|secret| secret.key != SecretKey::GithubSession));
+        assert!(serde_json::from_str::<SecretKey>(r#""githubSession""#).is_err());
+        assert_eq!(
+            serde_json::from_str::<SecretKey>(r#""workerApiKey""#).unwrap(),
+            SecretKey::WorkerApiKey
+        );
+    }
+}
@@ -0,0 +1,329 @@
+// src-tauri/src/session.rs
+
+// --- Dependencies ---
+use crate::auth::AuthError;
+use crate::http::HttpClient;
+use crate::identity::{self, UserProfile};
+use crate::secrets::{SecretKey, SecretStore, SecretStoreState};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex as StdMutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Runtime, State};
+use thiserror::Error;
+
+// --- Session ---
+// The signed-in account, from whichever identity provider was used. The access token and
+// profile are stored together as one entry in the secret store so they can't drift apart,
+// and restored at startup. The profile records its provider, which is used to refresh it.
+// The token never leaves the backend; the UI only sees `SessionInfo`.
+
+// Payload: `SessionInfo`, or `null` after logout or when the token was rejected.
+// Emitted app-wide, so every window sees it.
+pub const SESSION_CHANGED_EVENT: &str = "session_changed";
+
+// --- Error handling ---
+#[derive(Serialize, Debug, Clone, Error, PartialEq)]
+pub enum SessionError {
+    #[error("Not signed in")]
+    NotSignedIn,
+    #[error("The session has expired or was revoked; please sign in again")]
+    Expired,
+    #[error("Failed to refresh the profile: {0}")]
+    Profile(String),
+    #[error("Failed to persist the session: {0}")]
+    Storage(String),
+}
+
+// --- Data structures ---
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StoredSession {
+    access_token: String,
+    profile: UserProfile,
+    signed_in_at: i64,         // Unix millis
+    profile_refreshed_at: i64, // Unix millis
+}
+
+// What the UI gets to see
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub profile: UserProfile,
+    pub signed_in_at: i64,
+    pub profile_refreshed_at: i64,
+}
+
+impl From<&StoredSession> for SessionInfo {
+    fn from(session: &StoredSession) -> Self {
+        SessionInfo {
+            profile: session.profile.clone(),
+            signed_in_at: session.signed_in_at,
+            profile_refreshed_at: session.profile_refreshed_at,
+        }
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// Older versions kept only the access token, under another name. Users sign in again,
+// so the entry is just removed instead of being left behind in the keyring.
+fn delete_legacy_token(store: &SecretStore) {
+    if let Err(e) = store.delete(SecretKey::LegacyGithubAccessToken) {
+        eprintln!("Session: Failed to delete legacy access token: {}", e);
+    }
+}
+
+// --- The session manager ---
+// Without a secret store the session only lasts until the app quits.
+pub struct SessionManager {
+    store: Option<SecretStoreState>,
+    current: StdMutex<Option<StoredSession>>,
+}
+
+pub type SessionState = Arc<SessionManager>;
+
+impl SessionManager {
+    pub fn new(store: Option<SecretStoreState>) -> Self {
+        SessionManager {
+            store,
+            current: StdMutex::new(None),
+        }
+    }
+
+    // Loads the persisted session, if any. An unreadable entry is discarded so the user
+    // is asked to sign in again instead of being stuck.
+    pub fn restore(&self) -> Option<SessionInfo> {
+        let store = self.store.as_ref()?;
+        delete_legacy_token(store);
+        let restored = match store.get(SecretKey::GithubSession) {
+            Ok(Some(raw)) => match serde_json::from_str::<StoredSession>(&raw) {
+                Ok(session) => Some(session),
+                Err(e) => {
+                    eprintln!("Session: Discarding unreadable stored session: {}", e);
+                    let _ = store.delete(SecretKey::GithubSession);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!("Session: Failed to read stored session: {}", e);
+                None
+            }
+        };
+        let info = restored.as_ref().map(SessionInfo::from);
+        *self.lock() = restored;
+        info
+    }
+
+    pub fn info(&self) -> Option<SessionInfo> {
+        self.lock().as_ref().map(SessionInfo::from)
+    }
+
+    pub fn access_token(&self) -> Option<String> {
+        self.lock()
+            .as_ref()
+            .map(|session| session.access_token.clone())
+    }
+
+    // Starts a new session after a successful login. Persisting is best effort: if it
+    // fails the session still works until the app quits.
+    pub fn sign_in(&self, access_token: String, profile: UserProfile) -> SessionInfo {
+        let now = now_millis();
+        let session = StoredSession {
+            access_token,
+            profile,
+            signed_in_at: now,
+            profile_refreshed_at: now,
+        };
+        self.save(session)
+    }
+
+    pub fn update_profile(&self, profile: UserProfile) -> Result<SessionInfo, SessionError> {
+        let mut session = self.lock().clone().ok_or(SessionError::NotSignedIn)?;
+        session.profile = profile;
+        session.profile_refreshed_at = now_millis();
+        Ok(self.save(session))
+    }
+
+    // Forgets the session in memory and in the secret store
+    pub fn sign_out(&self) -> Result<(), SessionError> {
+        *self.lock() = None;
+        match &self.store {
+            Some(store) => {
+                delete_legacy_token(store);
+                store
+                    .delete(SecretKey::GithubSession)
+                    .map_err(|e| SessionError::Storage(e.to_string()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn save(&self, session: StoredSession) -> SessionInfo {
+        if let Some(store) = &self.store {
+            let persisted = serde_json::to_string(&session)
+                .map_err(|e| e.to_string())
+                .and_then(|raw| {
+                    store
+                        .set(SecretKey::GithubSession, &raw)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = persisted {
+                eprintln!("Session: {}", SessionError::Storage(e));
+            }
+        }
+        let info = SessionInfo::from(&session);
+        *self.lock() = Some(session);
+        info
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<StoredSession>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub fn emit_session_changed<R: Runtime>(app: &AppHandle<R>, info: Option<&SessionInfo>) {
+    if let Err(e) = app.emit(SESSION_CHANGED_EVENT, info) {
+        eprintln!("Session: Failed to emit {}: {}", SESSION_CHANGED_EVENT, e);
+    }
+}
+
+// Re-fetches the profile with the stored token. A rejected token ends the session.
+pub async fn refresh<R: Runtime>(
+    app: &AppHandle<R>,
+    session: &SessionManager,
+    client: &reqwest::Client,
+) -> Result<SessionInfo, SessionError> {
+    let access_token = session.access_token().ok_or(SessionError::NotSignedIn)?;
+    let provider_id = session
+        .info()
+        .ok_or(SessionError::NotSignedIn)?
+        .profile
+        .provider;
+    let provider =
+        identity::provider(&provider_id).map_err(|e| SessionError::Profile(e.to_string()))?;
+    match provider.fetch_profile(client, &access_token).await {
+        Ok(profile) => {
+            let info = session.update_profile(profile)?;
+            emit_session_changed(app, Some(&info));
+            Ok(info)
+        }
+        Err(AuthError::TokenRejected) => {
+            println!("Session: Stored token was rejected, signing out.");
+            if let Err(e) = session.sign_out() {
+                eprintln!("Session: {}", e);
+            }
+            emit_session_changed(app, None);
+            Err(SessionError::Expired)
+        }
+        Err(e) => Err(SessionError::Profile(e.to_string())),
+    }
+}
+
+// --- Tauri commands ---
+#[tauri::command]
+pub fn get_session(session: State<'_, SessionState>) -> Option<SessionInfo> {
+    session.info()
+}
+
+#[tauri::command]
+pub fn logout<R: Runtime>(
+    app: AppHandle<R>,
+    session: State<'_, SessionState>,
+) -> Result<(), SessionError> {
+    println!("[logout] Signing out.");
+    let result = session.sign_out();
+    // The in-memory session is gone even if the stored copy could not be deleted
+    emit_session_changed(&app, None);
+    result
+}
+
+#[tauri::command]
+pub async fn refresh_profile<R: Runtime>(
+    app: AppHandle<R>,
+    session: State<'_, SessionState>,
+    http: State<'_, HttpClient>,
+) -> Result<SessionInfo, SessionError> {
+    println!("[refresh_profile] Refreshing profile.");
+    refresh(&app, session.inner(), http.client()).await
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::tests::MemoryBackend;
+    use crate::secrets::SecretStore;
+
+    fn profile(login: &str) -> UserProfile {
+        UserProfile {
+            provider: "github".to_string(),
+            id: "42".to_string(),
+            login: login.to_string(),
+            name: None,
+            email: None,
+            avatar_url: Some("https://avatars.example/42".to_string()),
+        }
+    }
+
+    fn memory_store() -> SecretStoreState {
+        Arc::new(SecretStore::new(Box::new(MemoryBackend::default())))
+    }
+
+    #[test]
+    fn test_session_survives_restart() {
+        let store = memory_store();
+        let session = SessionManager::new(Some(store.clone()));
+        assert!(session.restore().is_none());
+        session.sign_in("gho_token".to_string(), profile("octocat"));
+
+        let restarted = SessionManager::new(Some(store.clone()));
+        let info = restarted.restore().expect("session should be restored");
+        assert_eq!(info.profile, profile("octocat"));
+        assert_eq!(restarted.access_token().as_deref(), Some("gho_token"));
+
+        restarted.sign_out().unwrap();
+        assert!(restarted.info().is_none());
+        assert!(SessionManager::new(Some(store)).restore().is_none());
+    }
+
+    #[test]
+    fn test_unreadable_session_is_discarded() {
+        let store = memory_store();
+        store.set(SecretKey::GithubSession, "not json").unwrap();
+        assert!(SessionManager::new(Some(store.clone())).restore().is_none());
+        assert_eq!(store.get(SecretKey::GithubSession).unwrap(), None);
+    }
+
+    #[test]
+    fn test_legacy_access_token_is_deleted() {
+        let store = memory_store();
+        store
+            .set(SecretKey::LegacyGithubAccessToken, "gho_old")
+            .unwrap();
+        assert!(SessionManager::new(Some(store.clone())).restore().is_none());
+        assert_eq!(store.get(SecretKey::LegacyGithubAccessToken).unwrap(), None);
+    }
+
+    #[test]
+    fn test_update_profile_requires_session() {
+        let session = SessionManager::new(None);
+        assert_eq!(
+            session.update_profile(profile("octocat")).unwrap_err(),
+            SessionError::NotSignedIn
+        );
+        session.sign_in("gho_token".to_string(), profile("octocat"));
+        let info = session.update_profile(profile("hubot")).unwrap();
+        assert_eq!(info.profile.login, "hubot");
+        // The info never carries the token
+        assert!(serde_json::to_value(&info)
+            .unwrap()
+            .get("accessToken")
+            .is_none());
+    }
+}
@@ -0,0 +1,833 @@
+// src-tauri/src/store.rs
+
+// --- Dependencies ---
+use crate::conversation::ChatRole;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex as StdMutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::State;
+use thiserror::Error;
+
+// File name of the database inside the app data dir
+pub const STORE_FILE_NAME: &str = "conversations.db";
+const DEFAULT_CONVERSATION_TITLE: &str = "New conversation";
+const AUTO_TITLE_MAX_CHARS: usize = 40;
+
+// --- Schema migrations ---
+// Applied in order; `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    // 1: conversations and their messages
+    "CREATE TABLE conversations (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        title       TEXT    NOT NULL,
+        created_at  INTEGER NOT NULL,
+        updated_at  INTEGER NOT NULL
+    );
+    CREATE TABLE messages (
+        id               INTEGER PRIMARY KEY AUTOINCREMENT,
+        conversation_id  INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        role             TEXT    NOT NULL,
+        content          TEXT    NOT NULL,
+        screenshot_path  TEXT,
+        created_at       INTEGER NOT NULL
+    );
+    CREATE INDEX idx_messages_conversation ON messages(conversation_id, id);",
+    // 2: vision-model description per message and a full-text index over messages.
+    // The trigram tokenizer gives substring matches, which also works for CJK text.
+    "ALTER TABLE messages ADD COLUMN vision_description TEXT;
+    CREATE VIRTUAL TABLE messages_fts USING fts5(
+        content, vision_description,
+        content = 'messages', content_rowid = 'id', tokenize = 'trigram'
+    );
+    CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+        INSERT INTO messages_fts(rowid, content, vision_description)
+        VALUES (new.id, new.content, new.vision_description);
+    END;
+    CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+        INSERT INTO messages_fts(messages_fts, rowid, content, vision_description)
+        VALUES ('delete', old.id, old.content, old.vision_description);
+    END;
+    CREATE TRIGGER messages_fts_update AFTER UPDATE ON messages BEGIN
+        INSERT INTO messages_fts(messages_fts, rowid, content, vision_description)
+        VALUES ('delete', old.id, old.content, old.vision_description);
+        INSERT INTO messages_fts(rowid, content, vision_description)
+        VALUES (new.id, new.content, new.vision_description);
+    END;
+    INSERT INTO messages_fts(messages_fts) VALUES ('rebuild');",
+];
+
+// --- Full-text search settings ---
+// The trigram tokenizer can't match terms shorter than this; they are applied as
+// plain substring filters instead.
+const FTS_MIN_TERM_CHARS: usize = 3;
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+const SNIPPET_TOKENS: i32 = 16;
+// Private-use characters used to mark highlights inside FTS snippets
+const HIGHLIGHT_OPEN: char = '\u{E000}';
+const HIGHLIGHT_CLOSE: char = '\u{E001}';
+
+// --- Error handling ---
+#[derive(Serialize, Debug, Clone, Error)]
+pub enum StoreError {
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Conversation {0} not found")]
+    ConversationNotFound(i64),
+    #[error("Failed to open conversation store: {0}")]
+    OpenFailed(String),
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Database(err.to_string())
+    }
+}
+
+// --- Data structures returned to the frontend ---
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub id: i64,
+    pub title: String,
+    pub created_at: i64, // Unix millis
+    pub updated_at: i64, // Unix millis
+    pub message_count: i64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMessage {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub role: ChatRole,
+    pub content: String,
+    pub screenshot_path: Option<String>,
+    pub vision_description: Option<String>, // What the vision model saw in the screenshot
+    pub created_at: i64,                    // Unix millis
+}
+
+// Which stored field a search hit matched in
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchField {
+    Content,
+    VisionDescription,
+}
+
+// Highlighted range inside `SearchHit::snippet`, in UTF-16 code units so the
+// frontend can slice the JS string directly.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub conversation_id: i64,
+    pub conversation_title: String,
+    pub message_id: i64,
+    pub role: ChatRole,
+    pub field: SearchField,
+    pub snippet: String,
+    pub highlights: Vec<HighlightRange>,
+    pub created_at: i64, // Unix millis
+    pub score: f64,      // bm25; lower is better, 0 for substring-only matches
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// --- The store ---
+// A single connection guarded by a mutex; all operations are short.
+pub struct ConversationStore {
+    conn: StdMutex<Connection>,
+}
+
+impl ConversationStore {
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| StoreError::OpenFailed(format!("{}: {}", parent.display(), e)))?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| StoreError::OpenFailed(format!("{}: {}", path.display(), e)))?;
+        Self::from_connection(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        let conn =
+            Connection::open_in_memory().map_err(|e| StoreError::OpenFailed(e.to_string()))?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self, StoreError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if applied < MIGRATIONS.len() {
+            let tx = conn.transaction()?;
+            for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+                println!("[store] Applying migration {}", index + 1);
+                tx.execute_batch(migration)?;
+            }
+            tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+            tx.commit()?;
+        }
+        Ok(ConversationStore {
+            conn: StdMutex::new(conn),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().expect("Failed to lock conversation store")
+    }
+
+    pub fn create_conversation(&self, title: Option<&str>) -> Result<Conversation, StoreError> {
+        let conn = self.lock();
+        let now = now_millis();
+        let title = title_or_default(title);
+        conn.execute(
+            "INSERT INTO conversations (title, created_at, updated_at) VALUES (?1, ?2, ?2)",
+            params![title, now],
+        )?;
+        let id = conn.last_insert_rowid();
+        Self::get_conversation_locked(&conn, id)
+    }
+
+    pub fn append_message(
+        &self,
+        conversation_id: i64,
+        role: ChatRole,
+        content: &str,
+        screenshot_path: Option<&str>,
+        vision_description: Option<&str>,
+    ) -> Result<StoredMessage, StoreError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let now = now_millis();
+        let (title, message_count): (String, i64) = tx
+            .query_row(
+                "SELECT title, (SELECT COUNT(*) FROM messages WHERE conversation_id = ?1)
+                 FROM conversations WHERE id = ?1",
+                params![conversation_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or(StoreError::ConversationNotFound(conversation_id))?;
+
+        tx.execute(
+            "INSERT INTO messages
+                (conversation_id, role, content, screenshot_path, vision_description, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                conversation_id,
+                role.as_str(),
+                content,
+                screenshot_path,
+                vision_description,
+                now
+            ],
+        )?;
+        let message_id = tx.last_insert_rowid();
+
+        // Untitled conversations take their name from the first question
+        let new_title = if message_count == 0
+            && role == ChatRole::User
+            && title == DEFAULT_CONVERSATION_TITLE
+            && !content.trim().is_empty()
+        {
+            content.trim().chars().take(AUTO_TITLE_MAX_CHARS).collect()
+        } else {
+            title
+        };
+        tx.execute(
+            "UPDATE conversations SET updated_at = ?2, title = ?3 WHERE id = ?1",
+            params![conversation_id, now, new_title],
+        )?;
+        tx.commit()?;
+
+        Ok(StoredMessage {
+            id: message_id,
+            conversation_id,
+            role,
+            content: content.to_string(),
+            screenshot_path: screenshot_path.map(str::to_string),
+            vision_description: vision_description.map(str::to_string),
+            created_at: now,
+        })
+    }
+
+    pub fn list_conversations(&self) -> Result<Vec<Conversation>, StoreError> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&format!(
+            "{} ORDER BY c.updated_at DESC, c.id DESC",
+            CONVERSATION_SELECT
+        ))?;
+        let rows = stmt.query_map([], conversation_from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    // Case-insensitive substring match on titles and message text
+    pub fn search_conversations(&self, query: &str) -> Result<Vec<Conversation>, StoreError> {
+        let pattern = format!("%{}%", escape_like(query.trim()));
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE c.title LIKE ?1 ESCAPE '\\'
+                OR EXISTS (SELECT 1 FROM messages m
+                           WHERE m.conversation_id = c.id AND m.content LIKE ?1 ESCAPE '\\')
+             ORDER BY c.updated_at DESC, c.id DESC",
+            CONVERSATION_SELECT
+        ))?;
+        let rows = stmt.query_map(params![pattern], conversation_from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn get_messages(&self, conversation_id: i64) -> Result<Vec<StoredMessage>, StoreError> {
+        let conn = self.lock();
+        Self::get_conversation_locked(&conn, conversation_id)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, conversation_id, role, content, screenshot_path, vision_description,
+                    created_at
+             FROM messages WHERE conversation_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![conversation_id], message_from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    // A blank title resets the conversation to the default one, as on creation
+    pub fn rename_conversation(&self, id: i64, title: &str) -> Result<Conversation, StoreError> {
+        let conn = self.lock();
+        let updated = conn.execute(
+            "UPDATE conversations SET title = ?2, updated_at = ?3 WHERE id = ?1",
+            params![id, title_or_default(Some(title)), now_millis()],
+        )?;
+        if updated == 0 {
+            return Err(StoreError::ConversationNotFound(id));
+        }
+        Self::get_conversation_locked(&conn, id)
+    }
+
+    pub fn delete_conversation(&self, id: i64) -> Result<(), StoreError> {
+        let conn = self.lock();
+        // Messages go with it through ON DELETE CASCADE
+        let deleted = conn.execute("DELETE FROM conversations WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(StoreError::ConversationNotFound(id));
+        }
+        Ok(())
+    }
+
+    // Ranked full-text search over questions, answers and vision descriptions
+    pub fn search_messages(
+        &self,
+        query: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<SearchHit>, StoreError> {
+        let (fts_terms, short_terms): (Vec<&str>, Vec<&str>) = query
+            .split_whitespace()
+            .partition(|term| term.chars().count() >= FTS_MIN_TERM_CHARS);
+        if fts_terms.is_empty() && short_terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+
+        // Short terms: every one must appear in the content or the description
+        let mut like_patterns = Vec::new();
+        let mut like_clauses = String::new();
+        for term in &short_terms {
+            like_patterns.push(format!("%{}%", escape_like(term)));
+            let n = like_patterns.len() + 2; // ?1 = match, ?2 = limit
+            like_clauses.push_str(&format!(
+                " AND (m.content LIKE ?{n} ESCAPE '\\' OR m.vision_description LIKE ?{n} ESCAPE '\\')"
+            ));
+        }
+
+        let sql = if fts_terms.is_empty() {
+            format!(
+                "SELECT m.id, m.conversation_id, c.title, m.role, m.content,
+                        m.vision_description, m.created_at, 0.0, NULL, NULL
+                 FROM messages m JOIN conversations c ON c.id = m.conversation_id
+                 WHERE ?1 IS NULL{like_clauses}
+                 ORDER BY m.created_at DESC LIMIT ?2"
+            )
+        } else {
+            format!(
+                "SELECT m.id, m.conversation_id, c.title, m.role, m.content,
+                        m.vision_description, m.created_at, bm25(messages_fts),
+                        snippet(messages_fts, 0, '{open}', '{close}', '…', {tokens}),
+                        snippet(messages_fts, 1, '{open}', '{close}', '…', {tokens})
+                 FROM messages_fts
+                 JOIN messages m ON m.id = messages_fts.rowid
+                 JOIN conversations c ON c.id = m.conversation_id
+                 WHERE messages_fts MATCH ?1{like_clauses}
+                 ORDER BY bm25(messages_fts) LIMIT ?2",
+                open = HIGHLIGHT_OPEN,
+                close = HIGHLIGHT_CLOSE,
+                tokens = SNIPPET_TOKENS,
+            )
+        };
+        let match_expr = (!fts_terms.is_empty()).then(|| build_match_expression(&fts_terms));
+
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&sql)?;
+        let mut bound: Vec<&dyn rusqlite::ToSql> = vec![&match_expr, &limit];
+        bound.extend(like_patterns.iter().map(|p| p as &dyn rusqlite::ToSql));
+        let rows = stmt.query_map(bound.as_slice(), |row| {
+            let role: String = row.get(3)?;
+            let content: String = row.get(4)?;
+            let vision_description: Option<String> = row.get(5)?;
+            let content_snippet: Option<String> = row.get(8)?;
+            let vision_snippet: Option<String> = row.get(9)?;
+
+            let (field, marked) = pick_snippet(
+                content_snippet,
+                vision_snippet,
+                &content,
+                vision_description.as_deref(),
+                &short_terms,
+            );
+            let (snippet, highlights) = extract_highlights(&marked);
+            Ok(SearchHit {
+                conversation_id: row.get(1)?,
+                conversation_title: row.get(2)?,
+                message_id: row.get(0)?,
+                role: ChatRole::parse(&role).unwrap_or(ChatRole::User),
+                field,
+                snippet,
+                highlights,
+                created_at: row.get(6)?,
+                score: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn get_conversation_locked(conn: &Connection, id: i64) -> Result<Conversation, StoreError> {
+        conn.query_row(
+            &format!("{} WHERE c.id = ?1", CONVERSATION_SELECT),
+            params![id],
+            conversation_from_row,
+        )
+        .optional()?
+        .ok_or(StoreError::ConversationNotFound(id))
+    }
+}
+
+// Trimmed title, or the default one when it is missing or blank
+fn title_or_default(title: Option<&str>) -> &str {
+    title
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(DEFAULT_CONVERSATION_TITLE)
+}
+
+const CONVERSATION_SELECT: &str = "SELECT c.id, c.title, c.created_at, c.updated_at,
+        (SELECT COUNT(*) FROM messages m WHERE m.conversation_id = c.id)
+    FROM conversations c";
+
+fn conversation_from_row(row: &Row<'_>) -> rusqlite::Result<Conversation> {
+    Ok(Conversation {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        created_at: row.get(2)?,
+        updated_at: row.get(3)?,
+        message_count: row.get(4)?,
+    })
+}
+
+fn message_from_row(row: &Row<'_>) -> rusqlite::Result<StoredMessage> {
+    let role: String = row.get(2)?;
+    Ok(StoredMessage {
+        id: row.get(0)?,
+        conversation_id: row.get(1)?,
+        role: ChatRole::parse(&role).unwrap_or(ChatRole::User),
+        content: row.get(3)?,
+        screenshot_path: row.get(4)?,
+        vision_description: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+// Quotes every term so user input can't inject FTS5 query syntax; terms are ANDed
+fn build_match_expression(terms: &[&str]) -> String {
+    terms
+        .iter()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Prefers the FTS snippet that actually contains a highlight. Substring-only
+// matches get a marked excerpt built here.
+fn pick_snippet(
+    content_snippet: Option<String>,
+    vision_snippet: Option<String>,
+    content: &str,
+    vision_description: Option<&str>,
+    short_terms: &[&str],
+) -> (SearchField, String) {
+    if let Some(snippet) = content_snippet.filter(|s| s.contains(HIGHLIGHT_OPEN)) {
+        return (SearchField::Content, snippet);
+    }
+    if let Some(snippet) = vision_snippet.filter(|s| s.contains(HIGHLIGHT_OPEN)) {
+        return (SearchField::VisionDescription, snippet);
+    }
+    let content_hit = short_terms
+        .iter()
+        .any(|t| content.to_lowercase().contains(&t.to_lowercase()));
+    match vision_description {
+        Some(description) if !content_hit => (
+            SearchField::VisionDescription,
+            mark_terms(description, short_terms),
+        ),
+        _ => (SearchField::Content, mark_terms(content, short_terms)),
+    }
+}
+
+// Builds a short excerpt around the first match with every term occurrence marked
+fn mark_terms(text: &str, terms: &[&str]) -> String {
+    const CONTEXT_CHARS: usize = 40;
+    let lower = text.to_lowercase();
+    // Lowercasing can change byte lengths for some scripts; fall back to no marks then
+    if lower.len() != text.len() {
+        return text.chars().take(CONTEXT_CHARS * 2).collect();
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for term in terms {
+        let needle = term.to_lowercase();
+        if needle.is_empty() {
+            continue;
+        }
+        ranges.extend(
+            lower
+                .match_indices(&needle)
+                .map(|(start, m)| (start, start + m.len())),
+        );
+    }
+    ranges.sort_unstable();
+
+    let first = ranges.first().map_or(0, |r| r.0);
+    let start = text[..first]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT_CHARS)
+        .map_or(0, |(i, _)| i);
+    let end = text[first..]
+        .char_indices()
+        .nth(CONTEXT_CHARS * 2)
+        .map_or(text.len(), |(i, _)| first + i);
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut cursor = start;
+    for (range_start, range_end) in ranges {
+        if range_start < cursor || range_end > end {
+            continue; // Overlapping or outside the excerpt
+        }
+        out.push_str(&text[cursor..range_start]);
+        out.push(HIGHLIGHT_OPEN);
+        out.push_str(&text[range_start..range_end]);
+        out.push(HIGHLIGHT_CLOSE);
+        cursor = range_end;
+    }
+    out.push_str(&text[cursor..end]);
+    if end < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+// Strips the highlight markers and returns their positions in UTF-16 units
+fn extract_highlights(marked: &str) -> (String, Vec<HighlightRange>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut utf16_pos = 0;
+    let mut open_at = None;
+    for ch in marked.chars() {
+        match ch {
+            HIGHLIGHT_OPEN => open_at = Some(utf16_pos),
+            HIGHLIGHT_CLOSE => {
+                if let Some(start) = open_at.take() {
+                    highlights.push(HighlightRange {
+                        start,
+                        end: utf16_pos,
+                    });
+                }
+            }
+            _ => {
+                snippet.push(ch);
+                utf16_pos += ch.len_utf16();
+            }
+        }
+    }
+    (snippet, highlights)
+}
+
+fn escape_like(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// --- Tauri Commands ---
+#[tauri::command]
+pub async fn create_conversation(
+    title: Option<String>,
+    store: State<'_, ConversationStore>,
+) -> Result<Conversation, StoreError> {
+    store.create_conversation(title.as_deref())
+}
+
+#[tauri::command]
+pub async fn append_message(
+    conversation_id: i64,
+    role: ChatRole,
+    content: String,
+    screenshot_path: Option<String>,
+    vision_description: Option<String>,
+    store: State<'_, ConversationStore>,
+) -> Result<StoredMessage, StoreError> {
+    store.append_message(
+        conversation_id,
+        role,
+        &content,
+        screenshot_path.as_deref(),
+        vision_description.as_deref(),
+    )
+}
+
+#[tauri::command]
+pub async fn list_conversations(
+    store: State<'_, ConversationStore>,
+) -> Result<Vec<Conversation>, StoreError> {
+    store.list_conversations()
+}
+
+#[tauri::command]
+pub async fn search_conversations(
+    query: String,
+    store: State<'_, ConversationStore>,
+) -> Result<Vec<Conversation>, StoreError> {
+    store.search_conversations(&query)
+}
+
+#[tauri::command]
+pub async fn search_messages(
+    query: String,
+    limit: Option<u32>,
+    store: State<'_, ConversationStore>,
+) -> Result<Vec<SearchHit>, StoreError> {
+    store.search_messages(&query, limit)
+}
+
+#[tauri::command]
+pub async fn get_conversation_messages(
+    conversation_id: i64,
+    store: State<'_, ConversationStore>,
+) -> Result<Vec<StoredMessage>, StoreError> {
+    store.get_messages(conversation_id)
+}
+
+#[tauri::command]
+pub async fn rename_conversation(
+    conversation_id: i64,
+    title: String,
+    store: State<'_, ConversationStore>,
+) -> Result<Conversation, StoreError> {
+    store.rename_conversation(conversation_id, &title)
+}
+
+#[tauri::command]
+pub async fn delete_conversation(
+    conversation_id: i64,
+    store: State<'_, ConversationStore>,
+) -> Result<(), StoreError> {
+    store.delete_conversation(conversation_id)
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read_back_messages() {
+        let store = ConversationStore::open_in_memory().unwrap();
+        let conversation = store.create_conversation(None).unwrap();
+        store
+            .append_message(
+                conversation.id,
+                ChatRole::User,
+                "How do I format on save in VS Code?",
+                Some("/tmp/shot.png"),
+                None,
+            )
+            .unwrap();
+        store
+            .append_message(
+                conversation.id,
+                ChatRole::Assistant,
+                "Enable editor.formatOnSave.",
+                None,
+                None,
+            )
+            .unwrap();
+
+        let messages = store.get_messages(conversation.id).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, ChatRole::User);
+        assert_eq!(
+            messages[0].screenshot_path.as_deref(),
+            Some("/tmp/shot.png")
+        );
+        assert_eq!(messages[1].role, ChatRole::Assistant);
+
+        let listed = store.list_conversations().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].message_count, 2);
+        assert_eq!(listed[0].title, "How do I format on save in VS Code?");
+    }
+
+    #[test]
+    fn test_search_rename_and_delete() {
+        let store = ConversationStore::open_in_memory().unwrap();
+        let first = store.create_conversation(Some("Linux setup")).unwrap();
+        let second = store.create_conversation(Some("Other")).unwrap();
+        store
+            .append_message(
+                second.id,
+                ChatRole::User,
+                "100% sure about this?",
+                None,
+                None,
+            )
+            .unwrap();
+
+        let found = store.search_conversations("linux").unwrap();
+        assert_eq!(
+            found.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![first.id]
+        );
+        let found = store.search_conversations("100%").unwrap();
+        assert_eq!(
+            found.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![second.id]
+        );
+
+        let renamed = store.rename_conversation(first.id, "Ubuntu setup").unwrap();
+        assert_eq!(renamed.title, "Ubuntu setup");
+        let renamed = store.rename_conversation(first.id, "   ").unwrap();
+        assert_eq!(renamed.title, DEFAULT_CONVERSATION_TITLE);
+
+        store.delete_conversation(second.id).unwrap();
+        assert!(matches!(
+            store.get_messages(second.id),
+            Err(StoreError::ConversationNotFound(_))
+        ));
+        assert!(matches!(
+            store.delete_conversation(second.id),
+            Err(StoreError::ConversationNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_search_messages_ranks_and_highlights() {
+        let store = ConversationStore::open_in_memory().unwrap();
+        let vscode = store.create_conversation(Some("Editor")).unwrap();
+        let other = store.create_conversation(Some("Other")).unwrap();
+        store
+            .append_message(
+                vscode.id,
+                ChatRole::User,
+                "How to enable formatting in VS Code?",
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .append_message(
+                vscode.id,
+                ChatRole::Assistant,
+                "Turn on editor.formatOnSave so formatting runs on save.",
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .append_message(
+                other.id,
+                ChatRole::User,
+                "What is this dialog?",
+                Some("/tmp/a.png"),
+                Some("A settings dialog with a Formatting tab selected"),
+            )
+            .unwrap();
+
+        let hits = store.search_messages("formatting", None).unwrap();
+        assert_eq!(hits.len(), 3);
+        let vision_hit = hits.iter().find(|h| h.conversation_id == other.id).unwrap();
+        assert_eq!(vision_hit.field, SearchField::VisionDescription);
+        let range = vision_hit.highlights[0];
+        let highlighted: String = vision_hit
+            .snippet
+            .encode_utf16()
+            .skip(range.start)
+            .take(range.end - range.start)
+            .map(|u| char::from_u32(u as u32).unwrap())
+            .collect();
+        assert_eq!(highlighted, "Formatting");
+
+        let hits = store.search_messages("formatting VS", None).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].conversation_id, vscode.id);
+        assert_eq!(hits[0].conversation_title, "Editor");
+    }
+
+    #[test]
+    fn test_search_messages_cjk_and_deleted_rows() {
+        let store = ConversationStore::open_in_memory().unwrap();
+        let conversation = store.create_conversation(None).unwrap();
+        store
+            .append_message(
+                conversation.id,
+                ChatRole::User,
+                "如何设置代码格式化",
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(store.search_messages("格式化", None).unwrap().len(), 1);
+        assert_eq!(store.search_messages("格式", None).unwrap().len(), 1);
+        assert!(store.search_messages("\"*", None).unwrap().is_empty());
+
+        store.delete_conversation(conversation.id).unwrap();
+        assert!(store.search_messages("格式化", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_extract_highlights_counts_utf16_units() {
+        let marked = format!("😀 {}hit{} end", HIGHLIGHT_OPEN, HIGHLIGHT_CLOSE);
+        let (snippet, highlights) = extract_highlights(&marked);
+        assert_eq!(snippet, "😀 hit end");
+        assert_eq!(highlights, vec![HighlightRange { start: 3, end: 6 }]);
+    }
+
+    #[test]
+    fn test_append_to_missing_conversation_fails() {
+        let store = ConversationStore::open_in_memory().unwrap();
+        assert!(matches!(
+            store.append_message(42, ChatRole::User, "hi", None, None),
+            Err(StoreError::ConversationNotFound(42))
+        ));
+    }
+}
@@ -0,0 +1,366 @@
+// src-tauri/src/stream.rs
+
+// --- Dependencies ---
+use crate::provider::Usage;
+use crate::query_error::QueryError;
+use serde::{Deserialize, Serialize};
+
+// --- Event name used for incremental query output ---
+// The frontend listens for this on the window that invoked the streaming command.
+pub const QUERY_STREAM_EVENT: &str = "query_stream";
+
+// --- Payload emitted to the calling window ---
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryStreamPayload {
+    pub request_id: String,
+    #[serde(flatten)]
+    pub event: QueryStreamEvent,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum QueryStreamEvent {
+    // A new piece of the AI answer
+    Delta {
+        text: String,
+    },
+    // The stream finished; carries the full accumulated answer
+    Done {
+        #[serde(rename = "fullText")]
+        full_text: String,
+        // Token counts, if the provider reports them
+        #[serde(skip_serializing_if = "Option::is_none")]
+        usage: Option<Usage>,
+    },
+    // The stream failed; no further events follow for this request id
+    Error {
+        message: String,
+        error: QueryError, // Same tagged error the command returns
+    },
+    // The request was aborted through `cancel_query`
+    Cancelled,
+}
+
+impl QueryStreamPayload {
+    pub fn new(request_id: &str, event: QueryStreamEvent) -> Self {
+        QueryStreamPayload {
+            request_id: request_id.to_string(),
+            event,
+        }
+    }
+}
+
+// --- How a single SSE `data:` field should be interpreted ---
+#[derive(Debug, PartialEq)]
+pub enum StreamChunk {
+    Delta(String),
+    Done,
+    Error(String),
+}
+
+// Shapes the worker may put inside a `data:` field
+#[derive(Deserialize)]
+struct WorkerStreamData {
+    delta: Option<String>,
+    ai_text: Option<String>,
+    error: Option<String>,
+    // OpenAI-compatible passthrough: {"choices":[{"delta":{"content":"..."}}]}
+    choices: Option<Vec<OpenAiStreamChoice>>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiStreamChoice {
+    delta: Option<OpenAiStreamDelta>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiStreamDelta {
+    content: Option<String>,
+}
+
+/// Interprets the data of one SSE event. Anything that is not recognised JSON
+/// is passed through as raw text so plain-text streams still work.
+pub fn parse_stream_data(data: &str) -> StreamChunk {
+    let trimmed = data.trim();
+    if trimmed == "[DONE]" {
+        return StreamChunk::Done;
+    }
+    match serde_json::from_str::<WorkerStreamData>(trimmed) {
+        Ok(parsed) => {
+            if let Some(err) = parsed.error {
+                return StreamChunk::Error(err);
+            }
+            if let Some(delta) = parsed.delta.or(parsed.ai_text) {
+                return StreamChunk::Delta(delta);
+            }
+            let content: String = parsed
+                .choices
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|c| c.delta.and_then(|d| d.content))
+                .collect();
+            StreamChunk::Delta(content)
+        }
+        Err(_) => StreamChunk::Delta(data.to_string()),
+    }
+}
+
+// --- Incremental Server-Sent Events decoder ---
+// Bytes arrive in arbitrary chunks (a line or even a UTF-8 sequence can be split),
+// so we buffer raw bytes and only decode complete lines.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data_lines: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the response body and returns the data of every event
+    /// completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(newline_pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=newline_pos).collect();
+            line.pop(); // '\n'
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let line = String::from_utf8_lossy(&line);
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes an event left unterminated when the body ends.
+    pub fn finish(&mut self) -> Option<String> {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&rest).to_string();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<String> {
+        if line.is_empty() {
+            return self.dispatch(); // Blank line terminates an event
+        }
+        if line.starts_with(':') {
+            return None; // Comment / keep-alive
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        if field == "data" {
+            self.data_lines.push(value.to_string());
+        }
+        // `event`, `id` and `retry` are not used by the worker protocol
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<String> {
+        if self.data_lines.is_empty() {
+            return None;
+        }
+        let data = self.data_lines.join("\n");
+        self.data_lines.clear();
+        Some(data)
+    }
+}
+
+// --- Newline-delimited JSON decoder ---
+// Ollama streams one JSON object per line instead of SSE events
+#[derive(Default)]
+pub struct LineDecoder {
+    buffer: Vec<u8>,
+}
+
+impl LineDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the response body and returns every non-blank line completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(newline_pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline_pos).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    /// Flushes a last line without a trailing newline.
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let line = String::from_utf8_lossy(&rest).trim().to_string();
+        (!line.is_empty()).then_some(line)
+    }
+}
+
+// --- Plain text decoder ---
+// A worker without SSE streams the answer as raw text. A chunk may end inside a
+// multibyte character, so the incomplete tail waits for the next chunk.
+#[derive(Default)]
+pub struct Utf8Decoder {
+    buffer: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the response body and returns the complete characters so far.
+    /// Invalid bytes become U+FFFD; only an unfinished sequence at the end is held back.
+    pub fn feed(&mut self, chunk: &[u8]) -> String {
+        self.buffer.extend_from_slice(chunk);
+        let mut text = String::new();
+        loop {
+            match std::str::from_utf8(&self.buffer) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    self.buffer.clear();
+                    return text;
+                }
+                Err(e) => {
+                    let valid_up_to = e.valid_up_to();
+                    text.push_str(&String::from_utf8_lossy(&self.buffer[..valid_up_to]));
+                    match e.error_len() {
+                        Some(invalid) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            self.buffer.drain(..valid_up_to + invalid);
+                        }
+                        None => {
+                            self.buffer.drain(..valid_up_to);
+                            return text;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flushes a sequence the body ended in the middle of.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.buffer);
+        String::from_utf8_lossy(&rest).into_owned()
+    }
+}
+
+// --- Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_decoder_handles_split_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: {\"delta\":\"Hel").is_empty());
+        let events = decoder.feed(b"lo\"}\n\ndata: [DONE]\n\n");
+        assert_eq!(events, vec!["{\"delta\":\"Hello\"}", "[DONE]"]);
+    }
+
+    #[test]
+    fn test_sse_decoder_joins_multiline_data_and_skips_comments() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.feed(b": keep-alive\r\nevent: message\r\ndata: a\r\ndata: b\r\n\r\n");
+        assert_eq!(events, vec!["a\nb"]);
+    }
+
+    #[test]
+    fn test_sse_decoder_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"data: tail").is_empty());
+        assert_eq!(decoder.finish(), Some("tail".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_line_decoder_handles_split_lines() {
+        let mut decoder = LineDecoder::new();
+        assert!(decoder.feed(b"{\"a\":").is_empty());
+        assert_eq!(decoder.feed(b"1}\r\n\n{\"b\""), vec!["{\"a\":1}"]);
+        assert_eq!(decoder.finish(), Some("{\"b\"".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_utf8_decoder_holds_split_characters() {
+        let bytes = "你好".as_bytes(); // Three bytes per character
+        let mut decoder = Utf8Decoder::new();
+        assert_eq!(decoder.feed(&bytes[..2]), "");
+        assert_eq!(decoder.feed(&bytes[2..4]), "你");
+        assert_eq!(decoder.feed(&bytes[4..]), "好");
+        assert_eq!(decoder.feed(b"\xffok"), "\u{fffd}ok");
+        assert_eq!(decoder.feed(&bytes[..1]), "");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test]
+    fn test_parse_stream_data_variants() {
+        assert_eq!(parse_stream_data("[DONE]"), StreamChunk::Done);
+        assert_eq!(
+            parse_stream_data(r#"{"delta":"hi"}"#),
+            StreamChunk::Delta("hi".to_string())
+        );
+        assert_eq!(
+            parse_stream_data(r#"{"choices":[{"delta":{"content":"yo"}}]}"#),
+            StreamChunk::Delta("yo".to_string())
+        );
+        assert_eq!(
+            parse_stream_data(r#"{"error":"boom"}"#),
+            StreamChunk::Error("boom".to_string())
+        );
+        assert_eq!(
+            parse_stream_data("plain text"),
+            StreamChunk::Delta("plain text".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stream_payload_serializes_flat() {
+        let payload = QueryStreamPayload::new(
+            "req-1",
+            QueryStreamEvent::Done {
+                full_text: "abc".to_string(),
+                usage: None,
+            },
+        );
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            serde_json::json!({"requestId": "req-1", "kind": "done", "fullText": "abc"})
+        );
+
+        let payload = QueryStreamPayload::new(
+            "req-2",
+            QueryStreamEvent::Done {
+                full_text: "abc".to_string(),
+                usage: Some(Usage {
+                    input_tokens: Some(12),
+                    output_tokens: Some(3),
+                }),
+            },
+        );
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap()["usage"],
+            serde_json::json!({"inputTokens": 12, "outputTokens": 3})
+        );
+    }
+}
@@ -2,12 +2,16 @@
 import {
   Env,
   BackendSyncPayload,
+  GithubTokenExchangeRequest,
+  GithubTokenResponse,
   WorkerQueryRequest,
   WorkerQueryResponse,
   OpenAIVisionPayload, // Reusable type for compatible APIs
   OpenAICompletionResponse,
   ApiResponse, // Make sure ApiResponse is defined if used elsewhere, otherwise remove if unused
   OpenAIMessageContent, // Ensure this type is correctly defined and imported
+  OpenAIMessage,
+  ConversationTurn,
 } from "./types"; // Ensure types.ts defines all these interfaces correctly
 import { jsonResponse, errorResponse } from "./utils"; // Ensure utils.ts defines these helper functions
 import { authenticateRequest } from "./auth"; // Ensure auth.ts defines this function
@@ -18,6 +22,26 @@ import { upsertUserProfile } from "./db"; // Ensure db.ts defines this correctly
 const VISION_MODEL_ID_DEFAULT = "google/gemini-2.0-flash-001"; // Example: Changed to a common Gemini vision model
 const TARGET_MODEL_ID_DEFAULT = "accounts/fireworks/models/deepseek-r1"; // Example: Changed to a Cloudflare Workers AI model
 
+const HISTORY_ROLES = ["system", "user", "assistant"];
+
+/**
+ * Converts the prior turns forwarded by the Tauri backend into chat messages.
+ * Turns with an unknown role or without text are skipped.
+ */
+function toHistoryMessages(
+  turns: ConversationTurn[] | undefined
+): OpenAIMessage[] {
+  if (!Array.isArray(turns)) return [];
+  return turns
+    .filter(
+      (turn) =>
+        HISTORY_ROLES.includes(turn?.role) &&
+        typeof turn.text === "string" &&
+        turn.text.trim() !== ""
+    )
+    .map((turn) => ({ role: turn.role, content: turn.text }));
+}
+
 export default {
   async fetch(
     request: Request,
@@ -38,7 +62,11 @@ export default {
 
     try {
       // --- Authentication (Common to relevant endpoints) ---
-      if (url.pathname === "/sync-user" || url.pathname === "/query") {
+      if (
+        url.pathname === "/sync-user" ||
+        url.pathname === "/query" ||
+        url.pathname === "/auth/github/token"
+      ) {
         const authErrorResponse = authenticateRequest(request, env);
         if (authErrorResponse) {
           return authErrorResponse; // Auth failed
@@ -105,6 +133,90 @@ export default {
         }
       }
 
+      // --- Endpoint: /auth/github/token (OAuth code exchange on behalf of the app) ---
+      // The desktop app sends the code and its PKCE verifier; the client secret never leaves the worker.
+      if (url.pathname === "/auth/github/token" && request.method === "POST") {
+        console.log("Handling /auth/github/token request...");
+        if (request.headers.get("Content-Type") !== "application/json") {
+          return errorResponse("Bad Request: Expected JSON", 400);
+        }
+
+        let exchange: GithubTokenExchangeRequest;
+        try {
+          exchange = await request.json<GithubTokenExchangeRequest>();
+        } catch (e: any) {
+          return errorResponse(`Bad Request: Invalid JSON - ${e.message}`, 400);
+        }
+        if (
+          !exchange?.code ||
+          !exchange?.code_verifier ||
+          !exchange?.redirect_uri
+        ) {
+          return errorResponse(
+            "Bad Request: Missing fields (code, code_verifier, redirect_uri)",
+            400
+          );
+        }
+
+        if (!env.GITHUB_CLIENT_ID || !env.GITHUB_CLIENT_SECRET) {
+          console.error(
+            "CRITICAL: GITHUB_CLIENT_ID or GITHUB_CLIENT_SECRET not set!"
+          );
+          return errorResponse(
+            "Internal Server Error: GitHub OAuth configuration missing",
+            500
+          );
+        }
+
+        // Not retried on our side: a code can only be redeemed once
+        const githubWebUrl = (env.GITHUB_WEB_URL || "https://github.com").replace(/\/+$/, "");
+        const githubResponse = await fetch(
+          `${githubWebUrl}/login/oauth/access_token`,
+          {
+            method: "POST",
+            headers: {
+              Accept: "application/json",
+              "Content-Type": "application/json",
+            },
+            body: JSON.stringify({
+              client_id: env.GITHUB_CLIENT_ID,
+              client_secret: env.GITHUB_CLIENT_SECRET,
+              code: exchange.code,
+              code_verifier: exchange.code_verifier,
+              redirect_uri: exchange.redirect_uri,
+            }),
+          }
+        );
+        if (!githubResponse.ok) {
+          const errorText = await githubResponse.text();
+          return errorResponse(
+            `GitHub token exchange failed (${githubResponse.status}): ${errorText}`,
+            502
+          );
+        }
+
+        const token = await githubResponse.json<GithubTokenResponse>();
+        if (token.error || !token.access_token) {
+          // e.g. bad_verification_code when the code expired or the verifier does not match
+          return errorResponse(
+            `GitHub rejected the code: ${token.error_description || token.error || "no access token"}`,
+            400
+          );
+        }
+        console.log("GitHub token exchange succeeded.");
+        return new Response(
+          JSON.stringify({
+            access_token: token.access_token,
+            token_type: token.token_type,
+            scope: token.scope,
+          }),
+          {
+            status: 200,
+            headers: { "Content-Type": "application/json" },
+          }
+        );
+      }
+
       // --- Endpoint: /query (AI Vision Query) ---
       if (url.pathname === "/query" && request.method === "POST") {
         console.log("Handling /query request...");
@@ -131,8 +243,18 @@ export default {
           );
         }
 
-        const { text, base64ImageDataUrl } = queryRequest;
+        const { text, base64ImageDataUrls } = queryRequest;
         const userQuery = text || "";
+        // Multi-image requests use the list; single-image requests may still use the old field
+        const imageDataUrls: string[] = base64ImageDataUrls?.length
+          ? base64ImageDataUrls
+          : queryRequest.base64ImageDataUrl
+          ? [queryRequest.base64ImageDataUrl]
+          : [];
+        const base64ImageDataUrl = imageDataUrls[0];
+        // Earlier turns go before the new question in the text model calls. Only their
+        // text is forwarded; the screenshots of earlier turns are not re-sent.
+        const historyMessages = toHistoryMessages(queryRequest.messages);
 
         if (!userQuery && !base64ImageDataUrl) {
           return errorResponse("Bad Request: Requires text or image data", 400);
@@ -140,7 +262,7 @@ export default {
         console.log(
           `Received query: Text='${
             userQuery ? userQuery.substring(0, 50) + "..." : "None"
-          }', Image=${base64ImageDataUrl ? "Present" : "None"}`
+          }', Images=${imageDataUrls.length}, Prior turns=${historyMessages.length}`
         );
 
         // --- AI API Configuration Check ---
@@ -150,7 +272,8 @@ export default {
           );
           return errorResponse(
             "Internal Server Error: AI provider configuration missing",
-            500
+            500,
+            true // Nothing was sent to the provider yet
           );
         }
 
@@ -159,7 +282,7 @@ export default {
           // === Branch 1: Image Present - Two-Step Process ===
           console.log("Image detected. Starting two-step AI process...");
 
-          if (!base64ImageDataUrl.startsWith("data:image/")) {
+          if (!imageDataUrls.every((url) => url.startsWith("data:image/"))) {
             console.warn(
               "Received potentially invalid image data URL format. Ensure it's 'data:image/[type];base64,...'"
             );
@@ -197,10 +320,12 @@ export default {
             // A2. Prepare Vision Model API Payload
             const visionContent: OpenAIMessageContent[] = [
               { type: "text", text: geminiSystemPrompt },
-              {
-                type: "image_url",
-                image_url: { url: base64ImageDataUrl },
-              },
+              ...imageDataUrls.map(
+                (url): OpenAIMessageContent => ({
+                  type: "image_url",
+                  image_url: { url },
+                })
+              ),
             ];
 
             // Ensure the payload format matches the expected format for the Vision Model API / Gateway
@@ -381,7 +506,10 @@ ${imageDescriptionJsonString}
             // Adjust payload structure based on the TARGET model's requirements
             const targetPayload = {
               model: targetModelId, // Use the target model ID
-              messages: [{ role: "user", content: deepseekPrompt }],
+              messages: [
+                ...historyMessages,
+                { role: "user", content: deepseekPrompt },
+              ],
               max_tokens: 3000,
               temperature: 0.6,
               // stream: false, // Ensure stream is false if not handling streaming response
@@ -488,7 +616,10 @@ ${imageDescriptionJsonString}
             // Prepare payload for the target model directly
             const directPayload = {
               model: targetModelId, // Use the target model ID
-              messages: [{ role: "user", content: userQuery }],
+              messages: [
+                ...historyMessages,
+                { role: "user", content: userQuery },
+              ],
               max_tokens: 3000,
               temperature: 0.7,
               //stream: false,
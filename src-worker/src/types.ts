@@ -15,6 +15,12 @@ export interface Env {
 
   /** The secret API Key for the custom AI API. */
   CUSTOM_AI_API_KEY: string; // Key this worker uses to talk to AI API
+
+  /** GitHub OAuth app credentials, used to exchange login codes for the desktop app. */
+  GITHUB_CLIENT_ID?: string;
+  GITHUB_CLIENT_SECRET?: string; // Stays on the worker so the desktop binary never holds it
+  /** Base URL of a GitHub Enterprise Server, e.g. `https://ghe.example.com`. Defaults to github.com. */
+  GITHUB_WEB_URL?: string;
 }
 
 /** Github User Profile (from auth flow) */
@@ -31,11 +37,39 @@ export interface BackendSyncPayload {
   profile: GithubUserProfile;
 }
 
+/** Payload from Tauri for the /auth/github/token endpoint (PKCE code exchange) */
+export interface GithubTokenExchangeRequest {
+  code: string;
+  code_verifier: string;
+  redirect_uri: string;
+}
+
+/** GitHub's reply to a code exchange; errors come back with status 200 */
+export interface GithubTokenResponse {
+  access_token?: string;
+  token_type?: string;
+  scope?: string;
+  error?: string;
+  error_description?: string;
+}
+
 /** Payload from Tauri for the /query endpoint */
 export interface WorkerQueryRequest {
   text: string;
   // Expecting data URL format: data:image/png;base64,...
   base64ImageDataUrl?: string | null;
+  // Sent instead of base64ImageDataUrl when the query has more than one screenshot
+  base64ImageDataUrls?: string[];
+  // Prior conversation turns, oldest first (already trimmed by the Tauri backend)
+  messages?: ConversationTurn[];
+}
+
+/** One prior turn of a conversation, as forwarded by the Tauri backend */
+export interface ConversationTurn {
+  role: "system" | "user" | "assistant";
+  text: string;
+  // Path or id of the screenshot used for that turn; the image itself is not re-sent
+  imageRef?: string;
 }
 
 /** Structure for OpenAI Vision API messages */
@@ -44,7 +78,7 @@ export interface OpenAIMessageContent {
   text?: string;
   image_url?: { url: string; detail?: "low" | "high" | "auto" }; // Added detail option
 }
-interface OpenAIMessage {
+export interface OpenAIMessage {
   role: "user" | "assistant" | "system";
   content: string | OpenAIMessageContent[];
 }
@@ -32,9 +32,16 @@ export function jsonResponse<T>(
  * Creates an error JSON Response object.
  * @param message - The error message.
  * @param status - The HTTP error status code (default: 500).
+ * @param retrySafe - Set when the request failed before reaching the AI provider, so the
+ *   Tauri client may retry it without risking a second AI call (sends `X-Retry-Safe: true`).
  * @returns A Response object.
  */
-export function errorResponse(message: string, status: number = 500): Response {
+export function errorResponse(
+  message: string,
+  status: number = 500,
+  retrySafe: boolean = false
+): Response {
   console.error(`Error Response (${status}): ${message}`); // Log the error server-side
-  return jsonResponse({ message }, status); // success will be false due to status code
+  const headers: HeadersInit = retrySafe ? { "X-Retry-Safe": "true" } : {};
+  return jsonResponse({ message }, status, headers); // success will be false due to status code
 }
@@ -0,0 +1,39 @@
+// src-worker/tests/github-token.test.ts
+import { describe, it, expect } from "vitest";
+
+const WORKER_URL = process.env.WORKER_URL!;
+const VALID_API_KEY = process.env.WORKER_API_KEY!;
+
+// The happy path needs a real GitHub code; these cover what the worker checks itself.
+describe("/auth/github/token Endpoint", () => {
+  it("Should return 401 without an API key", async () => {
+    const response = await fetch(`${WORKER_URL}/auth/github/token`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({
+        code: "code",
+        code_verifier: "verifier",
+        redirect_uri: "revision://github/callback",
+      }),
+    });
+    expect(response.status).toBe(401);
+  });
+
+  it("Should return 400 if the PKCE verifier is missing", async () => {
+    const response = await fetch(`${WORKER_URL}/auth/github/token`, {
+      method: "POST",
+      headers: {
+        "Content-Type": "application/json",
+        Authorization: `Bearer ${VALID_API_KEY}`,
+      },
+      body: JSON.stringify({
+        code: "code",
+        redirect_uri: "revision://github/callback",
+      }),
+    });
+    expect(response.status).toBe(400);
+    const body = await response.json();
+    expect(body.success).toBe(false);
+    expect(body.message).toContain("code_verifier");
+  });
+});
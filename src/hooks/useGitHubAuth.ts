@@ -11,13 +11,30 @@ import {
   type AuthStatus,
 } from "@/store/authStore"; // 确保导入 AuthStatus
 
+// 后端会话 (session_changed 事件 / get_session 命令的载荷)，不包含 token
+interface SessionInfo {
+  profile: GitHubProfile;
+  signedInAt: number;
+  profileRefreshedAt: number;
+}
+
+// 设备码登录时需要展示给用户的内容 (login_with_github_device 的返回值)
+export interface DeviceCodeInfo {
+  userCode: string;
+  verificationUri: string;
+  expiresIn: number;
+  interval: number;
+}
+
 // 定义 Hook 返回值的类型 (使用从 Store 推断的类型)
 interface UseGitHubAuthReturn {
   authState: AuthStatus; // 使用导入的 AuthStatus
   userProfile: GitHubProfile | null;
   authError: string | null;
   login: () => Promise<void>;
-  logout: () => void;
+  loginWithDevice: () => Promise<DeviceCodeInfo | null>;
+  cancelDeviceLogin: () => Promise<void>;
+  logout: () => Promise<void>;
 }
 
 export function useGitHubAuth(): UseGitHubAuthReturn {
@@ -35,6 +52,7 @@ export function useGitHubAuth(): UseGitHubAuthReturn {
   // --- Refs for listeners (保持不变) ---
   const unlistenSuccessRef = useRef<UnlistenFn | null>(null);
   const unlistenErrorRef = useRef<UnlistenFn | null>(null);
+  const unlistenSessionRef = useRef<UnlistenFn | null>(null);
 
   // --- 2. useEffect for listeners (调用 Store Actions) ---
   useEffect(() => {
@@ -85,9 +103,35 @@ export function useGitHubAuth(): UseGitHubAuthReturn {
           }
         );
 
+        // --- 会话监听器: 登录、登出或 token 失效时由后端广播到所有窗口 ---
+        const sessionListener = await listen<SessionInfo | null>(
+          "session_changed",
+          (event) => {
+            if (isMounted) {
+              console.log("[Hook] Received session_changed event.");
+              if (event.payload) {
+                loginSuccess(event.payload.profile);
+              } else {
+                storeLogout();
+              }
+            }
+          }
+        );
+
         unlistenSuccessRef.current = successListener;
         unlistenErrorRef.current = errorListener;
+        unlistenSessionRef.current = sessionListener;
         console.log("[Hook] Auth event listeners successfully attached.");
+
+        // --- 以后端恢复的会话为准 (持久化的 store 可能已过期) ---
+        const session = await invoke<SessionInfo | null>("get_session");
+        if (isMounted) {
+          if (session) {
+            loginSuccess(session.profile);
+          } else {
+            storeLogout();
+          }
+        }
       } catch (error) {
         console.error("[Hook] Failed to attach auth listeners:", error);
         if (isMounted) {
@@ -119,10 +163,14 @@ export function useGitHubAuth(): UseGitHubAuthReturn {
         unlistenErrorRef.current();
         console.log("[Hook] Error listener detached.");
       }
+      if (unlistenSessionRef.current) {
+        unlistenSessionRef.current();
+        console.log("[Hook] Session listener detached.");
+      }
     };
     // 依赖项为 store actions，因为它们是稳定的引用 (由 Zustand 保证)
     // 但如果它们在 store 定义中改变，这里需要更新。
-  }, [loginSuccess, loginFailure]);
+  }, [loginSuccess, loginFailure, storeLogout]);
 
   // --- 3. login 函数 (调用 Store Actions) ---
   const login = useCallback(async () => {
@@ -147,12 +195,42 @@ export function useGitHubAuth(): UseGitHubAuthReturn {
     }
   }, [loginStart, loginFailure]); // 依赖于 store actions
 
-  // --- 4. logout 函数 (调用 Store Action) ---
-  const logout = useCallback(() => {
-    console.log("[Hook] Logout requested. Calling store logout action.");
-    // 调用 Store 的 logout Action 来重置状态
+  // --- 3b. 设备码登录 (无需回调服务器或深层链接) ---
+  // 返回用户码和验证地址供界面显示；结果仍通过 github_auth_success / github_auth_error 事件到达
+  const loginWithDevice = useCallback(async () => {
+    loginStart();
+    try {
+      console.log("[Hook] Invoking 'login_with_github_device' command...");
+      const deviceCode = await invoke<DeviceCodeInfo>(
+        "login_with_github_device"
+      );
+      await openUrl(deviceCode.verificationUri);
+      return deviceCode;
+    } catch (error: any) {
+      console.error("[Hook] Failed to start device login:", error);
+      loginFailure(
+        `Device login initiation failed: ${error?.message || String(error)}`
+      );
+      return null;
+    }
+  }, [loginStart, loginFailure]);
+
+  const cancelDeviceLogin = useCallback(async () => {
+    await invoke("cancel_device_login");
+    storeLogout();
+  }, [storeLogout]);
+
+  // --- 4. logout 函数 (清除后端会话，再调用 Store Action) ---
+  const logout = useCallback(async () => {
+    console.log("[Hook] Logout requested. Invoking 'logout' command.");
+    try {
+      await invoke("logout");
+    } catch (error) {
+      // 后端已清除内存中的会话，只是删除持久化副本失败
+      console.error("[Hook] Failed to clear stored session:", error);
+    }
+    // 调用 Store 的 logout Action 来重置状态 (session_changed 也会触发)
     storeLogout();
-    // 本地不需要做其他状态清理，由 store 负责
   }, [storeLogout]); // 依赖于 store action
 
   // --- 5. 返回从 Store 读取的状态和 Hook 封装的 Actions ---
@@ -161,6 +239,8 @@ export function useGitHubAuth(): UseGitHubAuthReturn {
     userProfile,
     authError,
     login,
+    loginWithDevice,
+    cancelDeviceLogin,
     logout,
   };
 }
@@ -76,7 +76,7 @@ function GitHubAuth() {
               {/* ... user profile display ... */}
               <div className="relative">
                 <img
-                  src={userProfile.avatar_url}
+                  src={userProfile.avatar_url ?? undefined}
                   alt={`${userProfile.login}'s avatar`}
                   className="w-24 h-24 rounded-full ring-4 ring-indigo-500 ring-offset-2 dark:ring-offset-gray-800"
                 />
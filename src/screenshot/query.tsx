@@ -7,7 +7,7 @@ import { convertFileSrc, invoke } from "@tauri-apps/api/core"; // <-- Import inv
 import { listen } from "@tauri-apps/api/event";
 import { v4 as uuidv4 } from "uuid";
 
-import { ChatMessage } from "@/types/chat";
+import { ChatMessage, describeInvokeError } from "@/types/chat";
 import MessageBubble from "@/components/MessageBubble/MessageBubble"; // Ensure this path is correct
 
 import "./query.css";
@@ -184,8 +184,7 @@ const QueryPage: React.FC = () => {
         )
       );
     } catch (error) {
-      const errorMessage =
-        error instanceof Error ? error.message : String(error);
+      const errorMessage = describeInvokeError(error);
       console.error(
         "[QueryPage] Error invoking send_query_to_worker:",
         errorMessage
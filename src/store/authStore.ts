@@ -5,12 +5,14 @@ import { persist, createJSONStorage } from "zustand/middleware";
 // --- 复用或重新定义类型 ---
 type AuthStatus = "idle" | "loading" | "success" | "error";
 
+// 与身份提供方无关的用户资料 (GitHub 或 OIDC)，字段名沿用 GitHub 的格式
 interface GitHubProfile {
+  provider?: string; // "github" | "oidc"；旧版本保存的资料没有该字段
   login: string;
-  id: number;
-  name?: string;
-  avatar_url: string;
-  email?: string;
+  id: number | string;
+  name?: string | null;
+  avatar_url?: string | null;
+  email?: string | null;
 }
 
 interface AuthStoreState {
@@ -21,3 +21,77 @@ export interface ChatMessage {
   /** Optional: Any additional metadata (can be extended as needed). */
   metadata?: Record<string, any>;
 }
+
+/**
+ * Error returned by the query commands (`send_query_to_worker` and its streaming variant).
+ * Tagged by `kind`; every variant except `cancelled` carries a readable `message`.
+ */
+export type QueryError =
+  | { kind: "configMissing"; message: string }
+  | { kind: "imageReadFailed"; message: string; errors: unknown[] }
+  | { kind: "network"; message: string }
+  | { kind: "timeout"; message: string }
+  | { kind: "authRejected"; status: number; message: string }
+  | { kind: "rateLimited"; retryAfterSecs: number | null; message: string }
+  | { kind: "upstream"; status: number; message: string }
+  | { kind: "rejected"; status: number; message: string }
+  | { kind: "streamFailed"; message: string }
+  | { kind: "parse"; message: string }
+  | { kind: "invalidStageOutput"; stage: PipelineStage; message: string }
+  | { kind: "cancelled" };
+
+/** Readable text for an error thrown by `invoke`, whether it is a QueryError or not. */
+export function describeInvokeError(error: unknown): string {
+  if (error instanceof Error) return error.message;
+  if (typeof error === "object" && error !== null && "kind" in error) {
+    const queryError = error as QueryError;
+    if (queryError.kind === "cancelled") return "Cancelled";
+    if (queryError.kind === "rateLimited" && queryError.retryAfterSecs) {
+      return `${queryError.message} (retry in ${queryError.retryAfterSecs}s)`;
+    }
+    return queryError.message;
+  }
+  return String(error);
+}
+
+/** Where a query is sent: the worker, or a provider the app calls directly. */
+export type QueryProfile = "worker" | "openai" | "anthropic" | "ollama" | "pipeline";
+
+/** A query profile that is configured, as returned by `list_providers`. */
+export interface ProviderInfo {
+  id: QueryProfile;
+  name: string;
+  /** Model used when the query doesn't pick one; always null for the worker. */
+  defaultModel: string | null;
+  /** Whether queries without a `profile` go here. */
+  isDefault: boolean;
+}
+
+/** A model offered by a direct provider, as returned by `list_models`. */
+export interface ModelInfo {
+  /** Passed as `model` to the query commands. */
+  id: string;
+  name: string | null;
+}
+
+/** A step of the `pipeline` profile: the vision model describes, the reasoning model answers. */
+export type PipelineStage = "vision" | "reasoning";
+
+/**
+ * Payload of the `query_pipeline_stage` event, emitted when a pipeline stage starts,
+ * finishes or fails. `output` of the vision stage is what the vision model "saw".
+ */
+export type PipelineStageEvent = {
+  requestId: string | null;
+  stage: PipelineStage;
+  provider: string;
+  model: string;
+} & (
+  | { status: "started" }
+  | {
+      status: "completed";
+      output: string;
+      usage?: { inputTokens: number | null; outputTokens: number | null };
+    }
+  | { status: "failed"; error: QueryError }
+);